@@ -0,0 +1,230 @@
+//! Derive macro for the `Archive` trait of `nscoder`.
+//!
+//! This crate is not intended to be used directly, enable the `derive`
+//! feature of `nscoder` instead.
+#![deny(warnings)]
+#![deny(missing_docs)]
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr, Path};
+
+/// Derives the `Archive` trait for a struct with named fields.
+///
+/// ## Container attributes
+///
+/// - `#[archive(class = "RCDPerson")]`: the Cocoa class name of the type,
+///   defaults to the name of the struct.
+/// - `#[archive(super = Base)]`: the superclass of the type, defaults to
+///   `RootObject`. The struct must have a field marked with
+///   `#[archive(super)]` that holds the data of its superclass, which is
+///   encoded and decoded before other fields.
+///
+/// ## Field attributes
+///
+/// - `#[archive(super)]`: the field holds the data of the superclass given
+///   by `super = Base`, and cannot have other attributes.
+/// - `#[archive(key = "FirstName")]`: the key of the field, defaults to the
+///   name of the field.
+/// - `#[archive(default)]`: use `Default::default()` if the field is absent
+///   or failed to decode, instead of failing the whole object.
+/// - `#[archive(skip)]`: neither encode nor decode the field, and use
+///   `Default::default()` as its value.
+///
+/// Types of the fields must implement `ArchiveField`, and `Option` fields
+/// are optional by nature.
+#[proc_macro_derive(Archive, attributes(archive))]
+pub fn derive_archive(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[derive(Default)]
+struct ContainerAttrs {
+    class: Option<LitStr>,
+    super_class: Option<Path>,
+}
+
+#[derive(Default)]
+struct FieldAttrs {
+    is_super: bool,
+    key: Option<LitStr>,
+    default: bool,
+    skip: bool,
+}
+
+fn parse_container_attrs(input: &DeriveInput) -> syn::Result<ContainerAttrs> {
+    let mut attrs = ContainerAttrs::default();
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("archive")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("class") {
+                attrs.class = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("super") {
+                attrs.super_class = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("unsupported container attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(attrs)
+}
+
+fn parse_field_attrs(field: &syn::Field) -> syn::Result<FieldAttrs> {
+    let mut attrs = FieldAttrs::default();
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("archive")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("super") {
+                attrs.is_super = true;
+            } else if meta.path.is_ident("key") {
+                attrs.key = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("default") {
+                attrs.default = true;
+            } else if meta.path.is_ident("skip") {
+                attrs.skip = true;
+            } else {
+                return Err(meta.error("unsupported field attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    if attrs.is_super && (attrs.key.is_some() || attrs.default || attrs.skip) {
+        return Err(syn::Error::new_spanned(
+            field,
+            "the superclass field cannot have other attributes",
+        ));
+    }
+    Ok(attrs)
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "`Archive` can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "`Archive` can only be derived for structs with named fields",
+        ));
+    };
+
+    let attrs = parse_container_attrs(&input)?;
+    let ident = &input.ident;
+    let class = match &attrs.class {
+        Some(class) => class.clone(),
+        None => LitStr::new(&ident.to_string(), ident.span()),
+    };
+
+    let mut super_field = None;
+    let mut encode_fields = Vec::new();
+    let mut decode_fields = Vec::new();
+    for field in &fields.named {
+        let field_ident = field.ident.as_ref().expect("fields should be named");
+        let field_attrs = parse_field_attrs(field)?;
+
+        if field_attrs.is_super {
+            if attrs.super_class.is_none() {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "the superclass field requires `#[archive(super = ...)]` on the struct",
+                ));
+            }
+            if super_field.is_some() {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "only one field can hold the data of the superclass",
+                ));
+            }
+            super_field = Some(field_ident);
+            // The superclass is decoded first, see below.
+            decode_fields.push(quote! { #field_ident: __super });
+            continue;
+        }
+
+        if field_attrs.skip {
+            decode_fields.push(quote! {
+                #field_ident: ::core::default::Default::default()
+            });
+            continue;
+        }
+
+        let key = match &field_attrs.key {
+            Some(key) => key.clone(),
+            None => LitStr::new(&field_ident.to_string(), field_ident.span()),
+        };
+        encode_fields.push(quote! {
+            ::nscoder::ArchiveField::encode_field(&self.#field_ident, archiver, #key);
+        });
+        let decode = quote! {
+            ::nscoder::ArchiveField::decode_field(unarchiver, #key)
+        };
+        decode_fields.push(if field_attrs.default {
+            quote! { #field_ident: #decode.unwrap_or_default() }
+        } else {
+            quote! { #field_ident: #decode? }
+        });
+    }
+
+    let (super_type, encode_super, decode_super) = match (&attrs.super_class, super_field) {
+        // The superclass is encoded and decoded before other fields, however
+        // its field is declared, since typedstream reads values in order.
+        (Some(super_class), Some(field)) => (
+            quote! { #super_class },
+            quote! {
+                <#super_class as ::nscoder::Archive>::encode(&self.#field, archiver);
+            },
+            quote! {
+                let __super = <#super_class as ::nscoder::Archive>::decode(unarchiver)?;
+            },
+        ),
+        (Some(super_class), None) => {
+            return Err(syn::Error::new_spanned(
+                super_class,
+                "a field marked with `#[archive(super)]` is required to hold its data",
+            ));
+        }
+        (None, _) => (quote! { ::nscoder::RootObject }, quote! {}, quote! {}),
+    };
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::nscoder::Archive for #ident #ty_generics #where_clause {
+            type Super = #super_type;
+
+            fn class_name() -> &'static str {
+                #class
+            }
+
+            fn encode(&self, archiver: &mut dyn ::nscoder::Encoder) {
+                #encode_super
+                #(#encode_fields)*
+            }
+
+            fn decode(unarchiver: &dyn ::nscoder::Decoder) -> ::core::option::Option<Self> {
+                #decode_super
+                ::core::option::Option::Some(Self {
+                    #(#decode_fields,)*
+                })
+            }
+        }
+
+        impl #impl_generics ::nscoder::ArchiveField for #ident #ty_generics #where_clause {
+            fn encode_field(&self, archiver: &mut dyn ::nscoder::Encoder, key: &str) {
+                archiver.encode_value(self, key);
+            }
+
+            fn decode_field(
+                unarchiver: &dyn ::nscoder::Decoder,
+                key: &str,
+            ) -> ::core::option::Option<Self> {
+                unarchiver.decode_value(key)
+            }
+        }
+    })
+}
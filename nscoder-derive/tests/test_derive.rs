@@ -0,0 +1,195 @@
+use nscoder::{Archive, TypeRegistry};
+
+#[derive(Debug, Archive)]
+#[archive(class = "RCDEntity")]
+struct Entity {
+    #[archive(key = "Identifier")]
+    identifier: i64,
+}
+
+#[derive(Debug, Archive)]
+#[archive(class = "RCDPerson", super = Entity)]
+struct Person {
+    #[archive(super)]
+    entity: Entity,
+    #[archive(key = "Age")]
+    age: u32,
+    #[archive(key = "FirstName")]
+    first_name: String,
+    #[archive(key = "Nickname")]
+    nickname: Option<String>,
+    #[archive(key = "Friends")]
+    friends: Vec<Person>,
+    #[archive(key = "Score", default)]
+    score: f64,
+    #[archive(skip)]
+    cache: Vec<u8>,
+}
+
+#[test]
+fn test_derive() {
+    let person = Person {
+        entity: Entity { identifier: 42 },
+        age: 26,
+        first_name: "Cyan".to_owned(),
+        nickname: None,
+        friends: vec![Person {
+            entity: Entity { identifier: 43 },
+            age: 27,
+            first_name: "Yang".to_owned(),
+            nickname: Some("Y".to_owned()),
+            friends: vec![],
+            score: 1.5,
+            cache: vec![],
+        }],
+        score: 0.5,
+        cache: vec![1, 2, 3],
+    };
+
+    let encoded_bytes = nscoder::to_bytes(&person).expect("should encode successfully");
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Person>();
+
+    let object =
+        nscoder::from_bytes(&encoded_bytes, &registry).expect("should decode successfully");
+    let decoded_person: &Person = object
+        .downcast_ref()
+        .expect("type of the value should be `Person`");
+
+    assert_eq!(Person::class_name(), "RCDPerson");
+    assert_eq!(decoded_person.entity.identifier, 42);
+    assert_eq!(decoded_person.age, 26);
+    assert_eq!(decoded_person.first_name, "Cyan");
+    assert_eq!(decoded_person.nickname, None);
+    assert_eq!(decoded_person.score, 0.5);
+    assert!(decoded_person.cache.is_empty());
+
+    let friend = &decoded_person.friends[0];
+    assert_eq!(friend.entity.identifier, 43);
+    assert_eq!(friend.first_name, "Yang");
+    assert_eq!(friend.nickname.as_deref(), Some("Y"));
+}
+
+#[derive(Debug, Archive)]
+#[archive(class = "RCDPerson")]
+struct PersonV2 {
+    #[archive(key = "FirstName")]
+    first_name: String,
+    #[archive(key = "LastName", default)]
+    last_name: String,
+    #[archive(key = "Age")]
+    age: Option<u32>,
+}
+
+#[test]
+fn test_derive_missing_keys() {
+    let person = Entity { identifier: 1 };
+    let encoded_bytes = nscoder::to_bytes(&person).expect("should encode successfully");
+
+    // Required fields fail the whole object.
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<PersonV2>();
+    assert!(nscoder::from_bytes(&encoded_bytes, &registry).is_err());
+
+    // Defaulted and optional fields do not.
+    let person = PersonV2 {
+        first_name: "Cyan".to_owned(),
+        last_name: String::new(),
+        age: None,
+    };
+    let encoded_bytes = nscoder::to_bytes(&person).expect("should encode successfully");
+    let object =
+        nscoder::from_bytes(&encoded_bytes, &registry).expect("should decode successfully");
+    let decoded_person: &PersonV2 = object
+        .downcast_ref()
+        .expect("type of the value should be `PersonV2`");
+    assert_eq!(decoded_person.first_name, "Cyan");
+    assert_eq!(decoded_person.last_name, "");
+    assert_eq!(decoded_person.age, None);
+}
+
+// The superclass can be spelled in any way.
+#[derive(Debug, Archive)]
+#[archive(class = "RCDCounter", super = self::Entity)]
+struct Counter {
+    #[archive(super)]
+    entity: Entity,
+    #[archive(key = "Count")]
+    count: u64,
+}
+
+#[derive(Debug, Archive)]
+#[archive(class = "RCDCounter", super = Entity)]
+struct SmallCounter {
+    #[archive(super)]
+    entity: Entity,
+    #[archive(key = "Count")]
+    count: u8,
+}
+
+#[test]
+fn test_derive_integers() {
+    let counter = Counter {
+        entity: Entity { identifier: 7 },
+        count: u64::MAX,
+    };
+    let encoded_bytes = nscoder::to_bytes(&counter).expect("should encode successfully");
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Counter>();
+    let object =
+        nscoder::from_bytes(&encoded_bytes, &registry).expect("should decode successfully");
+    let decoded_counter: &Counter = object
+        .downcast_ref()
+        .expect("type of the value should be `Counter`");
+    assert_eq!(decoded_counter.entity.identifier, 7);
+    assert_eq!(decoded_counter.count, u64::MAX);
+
+    // Values that do not fit are not truncated.
+    let counter = Counter {
+        entity: Entity { identifier: 7 },
+        count: 300,
+    };
+    let encoded_bytes = nscoder::to_bytes(&counter).expect("should encode successfully");
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<SmallCounter>();
+    assert!(nscoder::from_bytes(&encoded_bytes, &registry).is_err());
+}
+
+#[derive(Debug, Archive)]
+#[archive(class = "RCDTagged", super = Entity)]
+struct Tagged {
+    #[archive(key = "Tag")]
+    tag: i64,
+    // Declared last, but still decoded first.
+    #[archive(super)]
+    entity: Entity,
+}
+
+#[test]
+fn test_derive_super_order() {
+    let bytes = [
+        b"\x04\x0bstreamtyped\x81\xe8\x03".as_slice(),
+        // Type `@`, and a new object of class `RCDTagged`, whose superclass
+        // is `RCDEntity`.
+        b"\x84\x01@\x84",
+        b"\x84\x84\x09RCDTagged\x00",
+        b"\x84\x84\x09RCDEntity\x00",
+        b"\x84\x84\x08NSObject\x00\x85",
+        // Type `ii`, the identifier of the superclass and then the tag.
+        b"\x84\x02ii\x07\x05",
+        b"\x86",
+    ]
+    .concat();
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Tagged>();
+    let object =
+        nscoder::typedstream::from_bytes(&bytes, &registry).expect("should decode successfully");
+    let tagged: &Tagged = object
+        .downcast_ref()
+        .expect("type of the value should be `Tagged`");
+    assert_eq!(tagged.entity.identifier, 7);
+    assert_eq!(tagged.tag, 5);
+}
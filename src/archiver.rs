@@ -1,11 +1,34 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
 use std::path::Path;
+use std::rc::Rc;
 
 use plist::{Uid as PlistUid, Value as PlistValue};
 
-use crate::object::{get_classes, AnyObject, Archive, TypeRegistry};
-use crate::types::{ArchiveDict, Error};
+use crate::object::{
+    AnyObject, Archive, ArrayRef, Class, EncodeObject, NSArray, NSDictionary, NSOrderedSet, NSSet,
+    ObjectRef, TypeRegistry, WeakObjectRef,
+};
+use crate::types::{ArchiveDict, Error, OutputFormat, ROOT_KEY};
+use crate::unknown::UnknownFields;
 
 /// A type that can encode data into an object archive.
+///
+/// ## Object identity
+///
+/// An [`AnyObject`] keeps its identity in the archive: encoding the same
+/// `AnyObject` more than once while archiving an object graph, whether by
+/// reference or as a shared object (see [`Encoder::encode_shared_object`]),
+/// archives it only once, and every key references the same archived object.
+/// This includes the elements of the built-in collections, such as
+/// [`NSArray`].
+///
+/// Values that are not erased into an `AnyObject`, like the ones passed to
+/// [`Encoder::encode_value`], are archived again each time they are encoded,
+/// and the copies are decoded as distinct objects. They cannot be told apart
+/// by their addresses, since a temporary can reuse the address of one that is
+/// already dropped. Strings and class info are uniqued by value regardless.
 pub trait Encoder {
     /// Encodes an `i32` value and associates it with a given key.
     fn encode_i32(&mut self, value: i32, key: &str);
@@ -13,46 +36,642 @@ pub trait Encoder {
     /// Encodes an `i64` value and associates it with a given key.
     fn encode_i64(&mut self, value: i64, key: &str);
 
+    /// Encodes a boolean value and associates it with a given key.
+    fn encode_bool(&mut self, value: bool, key: &str);
+
+    /// Encodes an `f32` value and associates it with a given key.
+    fn encode_f32(&mut self, value: f32, key: &str);
+
+    /// Encodes an `f64` value and associates it with a given key.
+    fn encode_f64(&mut self, value: f64, key: &str);
+
     /// Encodes a string value and associates it with a given key.
     fn encode_string(&mut self, value: &str, key: &str);
 
+    /// Encodes a buffer of bytes and associates it with a given key.
+    fn encode_bytes(&mut self, value: &[u8], key: &str);
+
+    /// Encodes an object of any archivable type and associates it with a
+    /// given key.
+    ///
+    /// Unlike [`Encoder::encode_object`], the object does not need to be
+    /// erased into an [`AnyObject`] first. It is archived again every time it
+    /// is encoded, unless it is an `AnyObject`, see
+    /// [Object identity](Encoder#object-identity).
+    fn encode_value(&mut self, value: &dyn EncodeObject, key: &str);
+
     /// Encodes an object and associates it with a given key.
-    fn encode_object(&mut self, object: &AnyObject, key: &str);
+    ///
+    /// The object is archived only once while archiving an object graph, and
+    /// encoding it again references the same archived object, see
+    /// [Object identity](Encoder#object-identity).
+    fn encode_object(&mut self, object: &AnyObject, key: &str) {
+        self.encode_value(object, key)
+    }
+
+    /// Encodes a shared object and associates it with a given key.
+    ///
+    /// A shared object is archived only once no matter how many times it is
+    /// encoded, every key it is associated with references the same archived
+    /// object. This is the counterpart of [`Decoder::decode_shared_object`].
+    fn encode_shared_object(&mut self, object: &ObjectRef, key: &str);
+
+    /// Encodes a weak reference to a shared object and associates it with a
+    /// given key.
+    ///
+    /// This is typically used for back-pointers, like the parent of a child
+    /// object, and is the counterpart of [`Decoder::decode_weak_object`].
+    /// The object is encoded like [`Encoder::encode_shared_object`], so an
+    /// object that is being encoded is referenced rather than archived
+    /// again. Nothing is encoded if the object is no longer alive.
+    fn encode_weak_object(&mut self, object: &WeakObjectRef, key: &str) {
+        if let Some(object) = object.upgrade() {
+            self.encode_shared_object(&object, key);
+        }
+    }
+
+    /// Encodes a list of shared objects as an array of references, and
+    /// associates it with a given key.
+    ///
+    /// This is how the built-in collections encode their elements, see
+    /// [`Encoder::encode_shared_object`].
+    ///
+    /// The default implementation is for encoders without arrays of
+    /// references, like the default implementation of
+    /// [`Encoder::encode_values`].
+    fn encode_shared_objects(&mut self, objects: &[ObjectRef], key: &str) {
+        self.encode_i64(objects.len() as i64, key);
+        for (index, object) in objects.iter().enumerate() {
+            self.encode_shared_object(object, &format!("{key}.{index}"));
+        }
+    }
+
+    /// Encodes a list of objects of any archivable types as an array of
+    /// references, and associates it with a given key.
+    ///
+    /// This is the building block of Cocoa collection classes, such as the
+    /// `NS.objects` key of `NSArray`. Like [`Encoder::encode_value`], every
+    /// value that is not an [`AnyObject`] is archived as a new object.
+    ///
+    /// The default implementation is for encoders without arrays of
+    /// references, it encodes the number of values with the key, and every
+    /// value with the key followed by its index (like `NS.objects.0`).
+    fn encode_values(&mut self, values: &[&dyn EncodeObject], key: &str) {
+        self.encode_i64(values.len() as i64, key);
+        for (index, value) in values.iter().enumerate() {
+            self.encode_value(*value, &format!("{key}.{index}"));
+        }
+    }
+
+    /// Encodes a list of objects as an array of references, and associates
+    /// it with a given key.
+    ///
+    /// This is the building block of Cocoa collection classes, such as the
+    /// `NS.objects` key of `NSArray`. Like [`Encoder::encode_object`], an
+    /// object that is already archived is referenced rather than archived
+    /// again. To encode an array object, use [`Encoder::encode_array`]
+    /// instead.
+    fn encode_objects(&mut self, objects: &[AnyObject], key: &str) {
+        let objects: Vec<_> = objects.iter().map(|o| o as &dyn EncodeObject).collect();
+        self.encode_values(&objects, key)
+    }
+
+    /// Encodes an `NSArray` object with the given elements and associates
+    /// it with a given key.
+    fn encode_array(&mut self, objects: &[AnyObject], key: &str) {
+        self.encode_value(&ArrayRef(objects), key)
+    }
+
+    /// Encodes the values that were not read while decoding an object, see
+    /// [`Decoder::unknown_fields`].
+    ///
+    /// Values whose keys are already encoded are skipped, so this is usually
+    /// called after encoding the known values. Encoders that cannot carry
+    /// unknown values ignore them.
+    fn encode_unknown_fields(&mut self, fields: &UnknownFields) {
+        let _ = fields;
+    }
+}
+
+impl dyn Encoder + '_ {
+    /// Encodes an `NSArray` object with the given elements of type `T`, and
+    /// associates it with a given key.
+    pub fn encode_array_of<T: Archive>(&mut self, objects: &[T], key: &str) {
+        self.encode_value(&ArrayRef(objects), key)
+    }
 }
 
 /// A type that can decode data from an object archive.
+///
+/// Every value can be decoded in two flavors. The `decode_*` methods are
+/// lenient, they fall back to a default value (or `None`) on any failure,
+/// just like `NSCoder` does. The `try_decode_*` methods return an [`Error`]
+/// instead, which tells where and why the decoding failed.
+///
+/// ## Object identity
+///
+/// Only shared objects keep their identity when decoded, see
+/// [`Decoder::decode_shared_object`]. This includes the elements of the
+/// built-in collections, such as [`NSArray`], so an object that is an element
+/// of multiple collections is decoded once. Every other method that returns
+/// an owned value, such as [`Decoder::decode_object`], decodes an archived
+/// object into a new value each time it is referenced.
+///
+/// An object cannot strongly reference itself, directly or not, and decoding
+/// it fails with [`Error::CyclicReference`]. Back-pointers to a shared object
+/// that is being decoded, like the parent of a child object, are decoded
+/// with [`Decoder::decode_weak_object`] instead.
 pub trait Decoder {
+    /// Returns `true` if the object being decoded has a value associated with
+    /// a given key.
+    ///
+    /// This is useful to distinguish absent values from default values, for
+    /// example in versioned formats.
+    fn contains_key(&self, key: &str) -> bool;
+
+    /// Returns the keys of all values of the object being decoded, in the
+    /// order they were archived.
+    fn keys(&self) -> Vec<String>;
+
+    /// Returns the version of a given class in the archive.
+    ///
+    /// Only non-keyed archives store class versions, keyed archives always
+    /// return `None`.
+    fn class_version(&self, class_name: &str) -> Option<i64> {
+        let _ = class_name;
+        None
+    }
+
     /// Decodes and returns an `i32` value associated with a given key.
     ///
     /// Returns `0` if key does not exist.
-    fn decode_i32(&self, key: &str) -> i32;
+    fn decode_i32(&self, key: &str) -> i32 {
+        self.try_decode_i32(key).unwrap_or(0)
+    }
+
+    /// Decodes and returns an `i32` value associated with a given key, or
+    /// an error if the value does not exist, is not an integer, or does not
+    /// fit in an `i32`.
+    fn try_decode_i32(&self, key: &str) -> Result<i32, Error> {
+        let value = self.try_decode_i64(key)?;
+        i32::try_from(value).map_err(|_| Error::TypeMismatch {
+            path: key.to_owned(),
+            expected: "32-bit integer",
+            actual: "integer out of range",
+        })
+    }
 
     /// Decodes and returns an `i64` value associated with a given key.
     ///
     /// Returns `0` if key does not exist.
-    fn decode_i64(&self, key: &str) -> i64;
+    fn decode_i64(&self, key: &str) -> i64 {
+        self.try_decode_i64(key).unwrap_or(0)
+    }
+
+    /// Decodes and returns an `i64` value associated with a given key, or
+    /// an error if the value does not exist or is not an integer.
+    fn try_decode_i64(&self, key: &str) -> Result<i64, Error>;
+
+    /// Decodes and returns a boolean value associated with a given key.
+    ///
+    /// Returns `false` if key does not exist.
+    fn decode_bool(&self, key: &str) -> bool {
+        self.try_decode_bool(key).unwrap_or(false)
+    }
+
+    /// Decodes and returns a boolean value associated with a given key, or
+    /// an error if the value does not exist or is not a boolean.
+    fn try_decode_bool(&self, key: &str) -> Result<bool, Error>;
+
+    /// Decodes and returns an `f32` value associated with a given key.
+    ///
+    /// Returns `0.0` if key does not exist.
+    fn decode_f32(&self, key: &str) -> f32 {
+        self.try_decode_f32(key).unwrap_or(0.0)
+    }
+
+    /// Decodes and returns an `f32` value associated with a given key, or
+    /// an error if the value does not exist or is not a number.
+    fn try_decode_f32(&self, key: &str) -> Result<f32, Error> {
+        self.try_decode_f64(key).map(|value| value as f32)
+    }
+
+    /// Decodes and returns an `f64` value associated with a given key.
+    ///
+    /// Returns `0.0` if key does not exist.
+    fn decode_f64(&self, key: &str) -> f64 {
+        self.try_decode_f64(key).unwrap_or(0.0)
+    }
+
+    /// Decodes and returns an `f64` value associated with a given key, or
+    /// an error if the value does not exist or is not a number.
+    fn try_decode_f64(&self, key: &str) -> Result<f64, Error>;
 
     /// Decodes and returns a string associated with a given key.
     ///
     /// Returns `None` if key does not exist, or the value is not a string.
-    fn decode_string(&self, key: &str) -> Option<String>;
+    fn decode_string(&self, key: &str) -> Option<String> {
+        self.try_decode_string(key).ok()
+    }
+
+    /// Decodes and returns a string associated with a given key, or an
+    /// error if the value does not exist or is not a string.
+    fn try_decode_string(&self, key: &str) -> Result<String, Error>;
+
+    /// Decodes and returns a buffer of bytes associated with a given key.
+    ///
+    /// Returns `None` if key does not exist, or the value is not a data.
+    fn decode_bytes(&self, key: &str) -> Option<Vec<u8>> {
+        self.try_decode_bytes(key).ok()
+    }
+
+    /// Decodes and returns a buffer of bytes associated with a given key,
+    /// or an error if the value does not exist or is not a data.
+    fn try_decode_bytes(&self, key: &str) -> Result<Vec<u8>, Error>;
 
     /// Decodes and returns an object associated with a given key.
     ///
     /// Returns `None` if key does not exist, or the object failed to decode.
-    fn decode_object(&self, key: &str) -> Option<AnyObject>;
+    ///
+    /// The object is decoded into a new value every time, even if it is
+    /// referenced by multiple keys in the archive, see
+    /// [Object identity](Decoder#object-identity).
+    fn decode_object(&self, key: &str) -> Option<AnyObject> {
+        self.try_decode_object(key).ok()
+    }
+
+    /// Decodes and returns an object associated with a given key, or an
+    /// error if the key does not exist or the object failed to decode.
+    fn try_decode_object(&self, key: &str) -> Result<AnyObject, Error>;
+
+    /// Decodes and returns an object associated with a given key, only if
+    /// its class is one of the given classes.
+    ///
+    /// Returns `None` if key does not exist, the object failed to decode, or
+    /// any class is not allowed.
+    fn decode_object_of_classes(&self, classes: &[&str], key: &str) -> Option<AnyObject> {
+        self.try_decode_object_of_classes(classes, key).ok()
+    }
+
+    /// Decodes and returns an object associated with a given key, only if
+    /// its class is one of the given classes, or an error if the key does
+    /// not exist or the object failed to decode.
+    ///
+    /// This is the counterpart of `decodeObjectOfClasses:forKey:`, which is
+    /// used to decode untrusted archives. Classes are checked before objects
+    /// are instantiated, and the allowlist also applies to every object
+    /// nested in the decoded object (such as the elements of collections),
+    /// unless they are decoded with an allowlist of their own. Subclasses
+    /// are not allowed implicitly, they need to be listed explicitly.
+    ///
+    /// Decoding a class that is not allowed fails the whole archive with
+    /// [`Error::DisallowedClass`].
+    fn try_decode_object_of_classes(&self, classes: &[&str], key: &str)
+        -> Result<AnyObject, Error>;
+
+    /// Decodes and returns a shared object associated with a given key.
+    ///
+    /// An archived object is decoded only once no matter how many times it is
+    /// referenced, and every call that resolves to it returns the same
+    /// [`ObjectRef`].
+    ///
+    /// Returns `None` if key does not exist, or the object failed to decode.
+    fn decode_shared_object(&self, key: &str) -> Option<ObjectRef> {
+        self.try_decode_shared_object(key).ok()
+    }
+
+    /// Decodes and returns a shared object associated with a given key, or
+    /// an error if the key does not exist or the object failed to decode.
+    fn try_decode_shared_object(&self, key: &str) -> Result<ObjectRef, Error>;
+
+    /// Decodes and returns a weak reference to a shared object associated
+    /// with a given key.
+    ///
+    /// Unlike [`Decoder::decode_shared_object`], this succeeds even if the
+    /// object is still being decoded, which is the case of back-pointers,
+    /// and the reference can be upgraded once the object is complete. A weak
+    /// reference does not keep the object alive, so the object needs to be
+    /// referenced strongly elsewhere in the decoded graph.
+    ///
+    /// Returns `None` if key does not exist, or the object failed to decode.
+    fn decode_weak_object(&self, key: &str) -> Option<WeakObjectRef> {
+        self.try_decode_weak_object(key).ok()
+    }
+
+    /// Decodes and returns a weak reference to a shared object associated
+    /// with a given key, or an error if the key does not exist or the object
+    /// failed to decode.
+    fn try_decode_weak_object(&self, key: &str) -> Result<WeakObjectRef, Error>;
+
+    /// Decodes and returns a list of shared objects stored as an array of
+    /// references associated with a given key.
+    ///
+    /// This is the counterpart of [`Encoder::encode_shared_objects`], and
+    /// how the built-in collections decode their elements.
+    ///
+    /// Returns `None` if key does not exist, or any of the objects failed to
+    /// decode.
+    fn decode_shared_objects(&self, key: &str) -> Option<Vec<ObjectRef>> {
+        self.try_decode_shared_objects(key).ok()
+    }
+
+    /// Decodes and returns a list of shared objects stored as an array of
+    /// references associated with a given key, or an error if the key does
+    /// not exist or any of the objects failed to decode.
+    ///
+    /// The default implementation decodes the objects in the form that the
+    /// default implementation of [`Encoder::encode_shared_objects`] encodes
+    /// them.
+    fn try_decode_shared_objects(&self, key: &str) -> Result<Vec<ObjectRef>, Error> {
+        let count = self.try_decode_i64(key)?;
+        (0..count)
+            .map(|index| self.try_decode_shared_object(&format!("{key}.{index}")))
+            .collect()
+    }
+
+    /// Decodes and returns a list of objects stored as an array of references
+    /// associated with a given key.
+    ///
+    /// This is the counterpart of [`Encoder::encode_objects`]. Like
+    /// [`Decoder::decode_object`], every object is decoded into a new value.
+    ///
+    /// Returns `None` if key does not exist, or any of the objects failed to
+    /// decode.
+    fn decode_objects(&self, key: &str) -> Option<Vec<AnyObject>> {
+        self.try_decode_objects(key).ok()
+    }
+
+    /// Decodes and returns a list of objects stored as an array of references
+    /// associated with a given key, or an error if the key does not exist or
+    /// any of the objects failed to decode.
+    ///
+    /// The default implementation decodes the objects in the form that the
+    /// default implementation of [`Encoder::encode_values`] encodes them.
+    fn try_decode_objects(&self, key: &str) -> Result<Vec<AnyObject>, Error> {
+        let count = self.try_decode_i64(key)?;
+        (0..count)
+            .map(|index| self.try_decode_object(&format!("{key}.{index}")))
+            .collect()
+    }
+
+    /// Decodes and returns the elements of an `NSArray` object associated
+    /// with a given key.
+    ///
+    /// Returns `None` if key does not exist, the object is not an array, or
+    /// any of the elements is shared with other objects (see
+    /// [Object identity](Decoder#object-identity)). Decode the array with
+    /// [`Decoder::decode_object`] to get its shared elements instead.
+    fn decode_array(&self, key: &str) -> Option<Vec<AnyObject>> {
+        self.decode_object(key)
+            .and_then(NSArray::from_object)
+            .and_then(|array| unwrap_objects(array.objects))
+    }
+
+    /// Decodes and returns an `NSDictionary` object associated with a given
+    /// key.
+    ///
+    /// Returns `None` if key does not exist, or the object is not a dictionary.
+    fn decode_dictionary(&self, key: &str) -> Option<NSDictionary> {
+        self.decode_object(key).and_then(NSDictionary::from_object)
+    }
+
+    /// Decodes and returns the elements of an `NSSet` object associated with
+    /// a given key, in the order they were archived.
+    ///
+    /// Returns `None` if key does not exist, the object is not a set, or any
+    /// of the elements is shared, like [`Decoder::decode_array`].
+    fn decode_set(&self, key: &str) -> Option<Vec<AnyObject>> {
+        self.decode_object(key)
+            .and_then(NSSet::from_object)
+            .and_then(|set| unwrap_objects(set.objects))
+    }
+
+    /// Decodes and returns the elements of an `NSOrderedSet` object associated
+    /// with a given key.
+    ///
+    /// Returns `None` if key does not exist, the object is not an ordered
+    /// set, or any of the elements is shared, like [`Decoder::decode_array`].
+    fn decode_ordered_set(&self, key: &str) -> Option<Vec<AnyObject>> {
+        self.decode_object(key)
+            .and_then(NSOrderedSet::from_object)
+            .and_then(|set| unwrap_objects(set.objects))
+    }
+
+    /// Returns the values of the object being decoded that have not been
+    /// read so far, along with the objects they reference.
+    ///
+    /// This is usually called after decoding the known values, so that the
+    /// rest can be written back with [`Encoder::encode_unknown_fields`].
+    /// Only keyed archives keep track of the read values, other decoders
+    /// return an empty [`UnknownFields`].
+    fn unknown_fields(&self) -> UnknownFields {
+        UnknownFields::default()
+    }
+}
+
+impl dyn Decoder + '_ {
+    /// Decodes and returns the elements of an `NSArray` object associated
+    /// with a given key, as values of type `T`.
+    ///
+    /// Returns `None` if key does not exist, the object is not an array, or
+    /// any of the elements is shared or not of type `T`.
+    pub fn decode_array_of<T: Archive + 'static>(&self, key: &str) -> Option<Vec<T>> {
+        self.decode_array(key)?
+            .into_iter()
+            .map(|object| object.downcast::<T>().ok().map(|object| *object))
+            .collect()
+    }
+
+    /// Decodes and returns an object of type `T` associated with a given key.
+    ///
+    /// Returns `None` if key does not exist, the object failed to decode, or
+    /// the object is not of type `T`.
+    pub fn decode_value<T: Archive + 'static>(&self, key: &str) -> Option<T> {
+        let object = self.decode_object(key)?;
+        object.downcast::<T>().ok().map(|object| *object)
+    }
+
+    /// Decodes and returns an object of type `T` associated with a given key,
+    /// allowing only the class of `T`.
+    ///
+    /// Returns `None` if key does not exist, the object failed to decode, or
+    /// its class is not allowed.
+    pub fn decode_object_of<T: Archive + 'static>(&self, key: &str) -> Option<T> {
+        self.try_decode_object_of(key).ok()
+    }
+
+    /// Decodes and returns an object of type `T` associated with a given key,
+    /// allowing only the class of `T`, or an error if the key does not exist
+    /// or the object failed to decode.
+    ///
+    /// If another type is registered with the class name of `T`, the object
+    /// is decoded as that type, and this returns [`Error::TypeMismatch`].
+    ///
+    /// See [`Decoder::try_decode_object_of_classes`] for more information.
+    pub fn try_decode_object_of<T: Archive + 'static>(&self, key: &str) -> Result<T, Error> {
+        let object = self.try_decode_object_of_classes(&[T::class_name()], key)?;
+        match object.downcast::<T>() {
+            Ok(object) => Ok(*object),
+            Err(_) => Err(Error::TypeMismatch {
+                path: key.to_owned(),
+                expected: std::any::type_name::<T>(),
+                actual: "another type registered with the same class name",
+            }),
+        }
+    }
+}
+
+/// Takes the elements of a decoded collection, as long as none of them is
+/// referenced elsewhere.
+fn unwrap_objects(objects: Vec<ObjectRef>) -> Option<Vec<AnyObject>> {
+    objects
+        .into_iter()
+        .map(|object| Rc::try_unwrap(object).ok())
+        .collect()
+}
+
+/// Options that customize how object graphs are unarchived.
+///
+/// This is accepted by the `*_with_options` variants of the unarchiving functions, such as
+/// [`from_bytes_with_options`], as well as untyped object graphs ([`ArchiveNode`]), typedstream
+/// and NIB files. All limits are disabled by default, set them when decoding untrusted archives,
+/// so that malicious ones cannot exhaust the stack or memory. Exceeding any limit fails the whole
+/// archive.
+///
+/// [`ArchiveNode`]: crate::ArchiveNode
+#[derive(Clone, Debug)]
+pub struct UnarchiveOptions {
+    pub(crate) root_key: String,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) max_objects: Option<usize>,
+    pub(crate) max_length: Option<usize>,
+    max_size: Option<usize>,
+    pub(crate) allowed_classes: Option<Vec<String>>,
+}
+
+impl UnarchiveOptions {
+    /// Constructs a new `UnarchiveOptions` with default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the key of the root object in `$top`, which is `"root"` by default.
+    ///
+    /// This is useful to read archives whose root object is stored with another key, such as
+    /// `IB.objectdata`.
+    pub fn set_root_key(&mut self, key: &str) {
+        self.root_key = key.to_owned();
+    }
+
+    /// Limits how deep objects can be nested in each other, where the root object is at depth 1.
+    ///
+    /// Exceeding the limit yields [`Error::DepthLimitExceeded`].
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = Some(max_depth);
+    }
+
+    /// Limits how many objects can be decoded in total, including plain values decoded as
+    /// objects. Shared objects are counted only once.
+    ///
+    /// Exceeding the limit yields [`Error::ObjectLimitExceeded`].
+    pub fn set_max_objects(&mut self, max_objects: usize) {
+        self.max_objects = Some(max_objects);
+    }
+
+    /// Limits the length of every decoded string and buffer of bytes, in bytes.
+    ///
+    /// Exceeding the limit yields [`Error::LengthLimitExceeded`].
+    pub fn set_max_length(&mut self, max_length: usize) {
+        self.max_length = Some(max_length);
+    }
+
+    /// Allows only the given classes for the root object, like
+    /// `unarchivedObjectOfClasses:fromData:error:` does. This is required to
+    /// decode untrusted archives securely.
+    ///
+    /// Like [`Decoder::try_decode_object_of_classes`], the allowlist also
+    /// applies to every object nested in the root object, unless they are
+    /// decoded with an allowlist of their own. Untyped object graphs have no
+    /// such allowlists, so every object must be of an allowed class.
+    ///
+    /// Decoding a class that is not allowed yields [`Error::DisallowedClass`].
+    pub fn set_allowed_classes(&mut self, classes: &[&str]) {
+        self.allowed_classes = Some(classes.iter().map(|class| (*class).to_owned()).collect());
+    }
+
+    /// Limits the size of the archive data, in bytes. The limit is checked before the data is
+    /// parsed, and files larger than the limit are not read entirely.
+    ///
+    /// Exceeding the limit yields [`Error::SizeLimitExceeded`].
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = Some(max_size);
+    }
+
+    /// Checks the size of archive data before it is parsed.
+    pub(crate) fn check_size(&self, size: usize) -> Result<(), Error> {
+        match self.max_size {
+            Some(max_size) if size > max_size => Err(Error::SizeLimitExceeded(max_size)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads archive data from a file, but no more than the size limit.
+    pub(crate) fn read_file(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        let Some(max_size) = self.max_size else {
+            return Ok(fs::read(path)?);
+        };
+        let mut bytes = vec![];
+        // One more byte is read to tell whether the file is too large.
+        File::open(path)?
+            .take(max_size as u64 + 1)
+            .read_to_end(&mut bytes)?;
+        self.check_size(bytes.len())?;
+        Ok(bytes)
+    }
+}
+
+impl Default for UnarchiveOptions {
+    fn default() -> Self {
+        Self {
+            root_key: ROOT_KEY.to_owned(),
+            max_depth: None,
+            max_objects: None,
+            max_length: None,
+            max_size: None,
+            allowed_classes: None,
+        }
+    }
 }
 
 /// Decodes a previously-archived object graph from a file, and returns its root object.
 pub fn from_file<P: AsRef<Path>>(path: P, registry: &TypeRegistry) -> Result<AnyObject, Error> {
-    let dict: ArchiveDict = plist::from_file(path)?;
-    from_archive_dict(dict, registry)
+    from_file_with_options(path, registry, &UnarchiveOptions::default())
+}
+
+/// Like [`from_file`], but unarchives the object graph with the given options.
+pub fn from_file_with_options<P: AsRef<Path>>(
+    path: P,
+    registry: &TypeRegistry,
+    options: &UnarchiveOptions,
+) -> Result<AnyObject, Error> {
+    let bytes = options.read_file(path.as_ref())?;
+    from_bytes_with_options(&bytes, registry, options)
 }
 
 /// Decodes a previously-archived object graph from a byte slice, and returns its root object.
 pub fn from_bytes(bytes: &[u8], registry: &TypeRegistry) -> Result<AnyObject, Error> {
-    let dict: ArchiveDict = plist::from_bytes(bytes)?;
-    from_archive_dict(dict, registry)
+    from_bytes_with_options(bytes, registry, &UnarchiveOptions::default())
+}
+
+/// Like [`from_bytes`], but unarchives the object graph with the given options.
+pub fn from_bytes_with_options(
+    bytes: &[u8],
+    registry: &TypeRegistry,
+    options: &UnarchiveOptions,
+) -> Result<AnyObject, Error> {
+    options.check_size(bytes.len())?;
+    let dict = ArchiveDict::from_bytes(bytes)?;
+    from_archive_dict(dict, registry, options)
 }
 
 /// Decodes a previously-archived object graph from a [`plist::Value`], and returns its root object.
@@ -61,69 +680,355 @@ pub fn from_bytes(bytes: &[u8], registry: &TypeRegistry) -> Result<AnyObject, Er
 /// This is typically used to reuse a deserialized plist value, and the input should be previously
 /// encoded with a keyed archiver. Arbitrarily modifying the value may lead to unexpected results.
 pub fn from_plist_value(value: &PlistValue, registry: &TypeRegistry) -> Result<AnyObject, Error> {
-    let dict: ArchiveDict = plist::from_value(value)?;
-    from_archive_dict(dict, registry)
+    from_plist_value_with_options(value, registry, &UnarchiveOptions::default())
+}
+
+/// Like [`from_plist_value`], but unarchives the object graph with the given options.
+pub fn from_plist_value_with_options(
+    value: &PlistValue,
+    registry: &TypeRegistry,
+    options: &UnarchiveOptions,
+) -> Result<AnyObject, Error> {
+    let dict = ArchiveDict::from_plist_value(value)?;
+    from_archive_dict(dict, registry, options)
+}
+
+/// Decodes a previously-archived object graph from a file, and returns all of its top-level
+/// objects keyed by their keys in `$top`.
+///
+/// See [`from_bytes_top_objects`] for more information.
+pub fn from_file_top_objects<P: AsRef<Path>>(
+    path: P,
+    registry: &TypeRegistry,
+    options: &UnarchiveOptions,
+) -> Result<BTreeMap<String, AnyObject>, Error> {
+    let bytes = options.read_file(path.as_ref())?;
+    from_bytes_top_objects(&bytes, registry, options)
+}
+
+/// Decodes a previously-archived object graph from a byte slice, and returns all of its top-level
+/// objects keyed by their keys in `$top`.
+///
+/// The top-level objects are decoded in the order of their keys, so that the decoding is
+/// deterministic. They are decoded with the same decoder, so shared objects (see
+/// [`Decoder::decode_shared_object`]) referenced by more than one of them are decoded only once.
+/// Other objects are decoded again for each reference. The root key in the options is ignored.
+pub fn from_bytes_top_objects(
+    bytes: &[u8],
+    registry: &TypeRegistry,
+    options: &UnarchiveOptions,
+) -> Result<BTreeMap<String, AnyObject>, Error> {
+    options.check_size(bytes.len())?;
+    let dict = ArchiveDict::from_bytes(bytes)?;
+    __impl::Unarchiver::new(dict, registry, options).unarchive_top_objects()
+}
+
+/// Decodes a previously-archived object graph from a [`plist::Value`], and returns all of its
+/// top-level objects keyed by their keys in `$top`.
+///
+/// See [`from_bytes_top_objects`] for more information.
+pub fn from_plist_value_top_objects(
+    value: &PlistValue,
+    registry: &TypeRegistry,
+    options: &UnarchiveOptions,
+) -> Result<BTreeMap<String, AnyObject>, Error> {
+    let dict = ArchiveDict::from_plist_value(value)?;
+    __impl::Unarchiver::new(dict, registry, options).unarchive_top_objects()
 }
 
 #[inline(always)]
-fn from_archive_dict(dict: ArchiveDict, registry: &TypeRegistry) -> Result<AnyObject, Error> {
-    let unarchiver = __impl::Unarchiver::new(dict, registry);
+fn from_archive_dict(
+    dict: ArchiveDict,
+    registry: &TypeRegistry,
+    options: &UnarchiveOptions,
+) -> Result<AnyObject, Error> {
+    let unarchiver = __impl::Unarchiver::new(dict, registry, options);
     unarchiver.unarchive_root_object()
 }
 
+/// Options that customize how object graphs are archived.
+///
+/// This is accepted by the `*_with_options` variants of the archiving functions, such as
+/// [`to_bytes_with_options`].
+#[derive(Clone, Debug)]
+pub struct ArchiveOptions {
+    class_names: HashMap<Class, String>,
+    root_key: String,
+    canonical: bool,
+}
+
+impl ArchiveOptions {
+    /// Constructs a new `ArchiveOptions` with default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the key of the root object in `$top`, which is `"root"` by default.
+    pub fn set_root_key(&mut self, key: &str) {
+        self.root_key = key.to_owned();
+    }
+
+    /// Archives objects of the given type with the given class name, instead of the one returned
+    /// by [`Archive::class_name`].
+    ///
+    /// This is the counterpart of `setClassName:forClass:` on `NSKeyedArchiver`, and applies to
+    /// the type wherever it appears in the class hierarchy of an archived object.
+    pub fn set_class_name_for<T: Archive + 'static>(&mut self, class_name: &str) {
+        self.class_names
+            .insert(Class::of::<T>(), class_name.to_owned());
+    }
+
+    /// Sets whether archives are written in their canonical form, which is `false` by default.
+    ///
+    /// Equal object graphs always produce byte-identical canonical archives, regardless of the
+    /// order their values are encoded in. This is useful for archives that are content-hashed or
+    /// checked into version control. See [`ArchiveDict::canonicalize`] for the details.
+    pub fn set_canonical(&mut self, canonical: bool) {
+        self.canonical = canonical;
+    }
+
+    /// Substitutes the name of a class that has no `Archive` type of its own.
+    pub(crate) fn set_class_name(&mut self, class: &'static str, class_name: &str) {
+        self.class_names
+            .insert(Class::Name(class), class_name.to_owned());
+    }
+
+    /// Returns the class name that objects of the given type are archived with, if it was set
+    /// with [`ArchiveOptions::set_class_name_for`].
+    pub fn class_name_for<T: Archive + 'static>(&self) -> Option<&str> {
+        self.class_names.get(&Class::of::<T>()).map(String::as_str)
+    }
+}
+
+impl Default for ArchiveOptions {
+    fn default() -> Self {
+        Self {
+            class_names: HashMap::new(),
+            root_key: ROOT_KEY.to_owned(),
+            canonical: false,
+        }
+    }
+}
+
 /// Encodes an object graph with the given root object into a data representation, and returns the
 /// archive data as bytes.
+///
+/// The archive data is always in binary format, use [`to_writer`] to choose other formats.
 pub fn to_bytes<O: Archive>(object: &O) -> Result<Vec<u8>, Error> {
-    let dict = to_archive_dict(object);
+    to_bytes_with_options(object, &ArchiveOptions::default())
+}
+
+/// Like [`to_bytes`], but archives the object graph with the given options.
+pub fn to_bytes_with_options<O: Archive>(
+    object: &O,
+    options: &ArchiveOptions,
+) -> Result<Vec<u8>, Error> {
     let mut buf = Vec::new();
-    plist::to_writer_binary(&mut buf, &dict)?;
+    to_writer_with_options(&mut buf, object, OutputFormat::Binary, options)?;
     Ok(buf)
 }
 
+/// Encodes an object graph with the given root object into a data representation, and writes the
+/// archive data in the given format to a writer.
+pub fn to_writer<W: Write, O: Archive>(
+    writer: W,
+    object: &O,
+    format: OutputFormat,
+) -> Result<(), Error> {
+    to_writer_with_options(writer, object, format, &ArchiveOptions::default())
+}
+
+/// Like [`to_writer`], but archives the object graph with the given options.
+pub fn to_writer_with_options<W: Write, O: Archive>(
+    writer: W,
+    object: &O,
+    format: OutputFormat,
+    options: &ArchiveOptions,
+) -> Result<(), Error> {
+    to_root_archive_dict(object, options).to_writer(writer, format)
+}
+
+/// Encodes an object graph with the given root object into a data representation, and writes the
+/// archive data in the given format to a file.
+pub fn to_file<P: AsRef<Path>, O: Archive>(
+    path: P,
+    object: &O,
+    format: OutputFormat,
+) -> Result<(), Error> {
+    to_file_with_options(path, object, format, &ArchiveOptions::default())
+}
+
+/// Like [`to_file`], but archives the object graph with the given options.
+pub fn to_file_with_options<P: AsRef<Path>, O: Archive>(
+    path: P,
+    object: &O,
+    format: OutputFormat,
+    options: &ArchiveOptions,
+) -> Result<(), Error> {
+    let file = File::create(path)?;
+    let mut writer = BufWriter::new(file);
+    to_writer_with_options(&mut writer, object, format, options)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Encodes an object graph with the given root object into a [`plist::Value`].
+///
+/// This is the counterpart of [`from_plist_value`], and is typically used to embed the archive
+/// into another plist.
+pub fn to_plist_value<O: Archive>(object: &O) -> Result<PlistValue, Error> {
+    to_plist_value_with_options(object, &ArchiveOptions::default())
+}
+
+/// Like [`to_plist_value`], but archives the object graph with the given options.
+pub fn to_plist_value_with_options<O: Archive>(
+    object: &O,
+    options: &ArchiveOptions,
+) -> Result<PlistValue, Error> {
+    to_root_archive_dict(object, options).to_plist_value()
+}
+
+/// Encodes an object graph with multiple named top-level objects into a data representation, and
+/// returns the archive data as bytes.
+///
+/// Objects are stored in `$top` with the given keys, and the root object is the one with the key
+/// `"root"` (if any). The top-level objects are encoded with the same archiver, so an
+/// [`AnyObject`] referenced by more than one of them is archived only once, see
+/// [Object identity](Encoder#object-identity). Other values are archived again for each reference.
+pub fn to_bytes_top_objects(
+    objects: &[(&str, &dyn EncodeObject)],
+    options: &ArchiveOptions,
+) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    to_writer_top_objects(&mut buf, objects, OutputFormat::Binary, options)?;
+    Ok(buf)
+}
+
+/// Encodes an object graph with multiple named top-level objects into a data representation, and
+/// writes the archive data in the given format to a writer.
+///
+/// See [`to_bytes_top_objects`] for more information.
+pub fn to_writer_top_objects<W: Write>(
+    writer: W,
+    objects: &[(&str, &dyn EncodeObject)],
+    format: OutputFormat,
+    options: &ArchiveOptions,
+) -> Result<(), Error> {
+    to_archive_dict(objects, options).to_writer(writer, format)
+}
+
 #[inline(always)]
-fn to_archive_dict<O: Archive>(object: &O) -> ArchiveDict {
-    let mut archiver = __impl::Archiver::new();
-    let root_object = archiver.encode_new_object(|archiver| {
-        object.encode(archiver);
-        get_classes::<O>()
-    });
-    archiver.seal(root_object)
+fn to_root_archive_dict<O: Archive>(object: &O, options: &ArchiveOptions) -> ArchiveDict {
+    to_archive_dict(&[(&options.root_key, object)], options)
+}
+
+fn to_archive_dict(objects: &[(&str, &dyn EncodeObject)], options: &ArchiveOptions) -> ArchiveDict {
+    let mut archiver = __impl::Archiver::new(options);
+    let top_objects = objects
+        .iter()
+        .map(|(key, object)| ((*key).to_owned(), archiver.encode_any_object(*object)))
+        .collect();
+    let mut dict = archiver.seal(top_objects);
+    if options.canonical {
+        dict.canonicalize();
+    }
+    dict
+}
+
+/// Encodes an object into the object table of an existing archive, and
+/// returns its UID. Strings and class info already in the table are reused.
+pub(crate) fn encode_into_objects(
+    objects: &mut Vec<PlistValue>,
+    object: &dyn EncodeObject,
+) -> PlistUid {
+    let mut archiver = __impl::Archiver::with_objects(std::mem::take(objects));
+    let uid = archiver.encode_any_object(object);
+    *objects = archiver.into_objects();
+    uid
 }
 
 mod __impl {
-    use std::cell::Cell;
-    use std::collections::HashMap;
+    use std::cell::RefCell;
+    use std::collections::{BTreeMap, HashMap, HashSet};
+    use std::rc::Rc;
 
     use plist::Dictionary as PlistDictionary;
 
-    use super::{Encoder, PlistUid, PlistValue};
-    use crate::object::{AnyObject, TypeRegistry};
-    use crate::types::{ArchiveDict, Error};
+    use super::{ArchiveOptions, Encoder, PlistUid, PlistValue, UnarchiveOptions};
+    use crate::decoding::DecodingState;
+    use crate::object::{
+        from_inline_value, AnyObject, Class, EncodeObject, ObjectRef, TypeRegistry, WeakObjectRef,
+        NS_OBJECTS_KEY,
+    };
+    use crate::types::{map_references, references_of};
+    use crate::types::{value_type_name, ArchiveDict, Error, CLASS_KEY, KEYED_ARCHIVER_CLASS_NAME};
+    use crate::unknown::{Alias, UnknownFields};
 
     mod traits {
         pub use crate::archiver::{Decoder, Encoder};
     }
 
-    // Non-keyed archivers are not supported since `NSArchiver` is deprecated
-    // for better forward and backward compatibility.
-    const KEYED_ARCHIVER_CLASS_NAME: &str = "NSKeyedArchiver";
-
     #[derive(Default)]
     pub struct Archiver {
         objects: Vec<PlistValue>,
-        active_object_index: Option<usize>,
+        // Indices of the objects being encoded, the active one is the last.
+        active_objects: Vec<usize>,
+        // Class info and strings are uniqued by value, like `NSKeyedArchiver` does.
+        class_infos: HashMap<Vec<Class>, PlistUid>,
+        // Class info by the archived class names, which also covers class
+        // info carried by unknown fields.
+        archived_class_infos: HashMap<Vec<String>, PlistUid>,
+        strings: HashMap<String, PlistUid>,
+        // Objects erased into `AnyObject`, shared or not, are uniqued by
+        // identity.
+        object_uids: HashMap<u64, PlistUid>,
+        // Substituted class names, keyed by the classes they replace.
+        class_names: HashMap<Class, String>,
     }
 
     impl Archiver {
-        pub fn new() -> Self {
-            let mut this = Self::default();
+        pub fn new(options: &ArchiveOptions) -> Self {
+            let mut this = Self {
+                class_names: options.class_names.clone(),
+                ..Self::default()
+            };
             this.objects.push(PlistValue::String("$null".to_owned()));
             this
         }
 
+        /// Creates an archiver that appends to the object table of an
+        /// existing archive.
+        pub fn with_objects(objects: Vec<PlistValue>) -> Self {
+            if objects.is_empty() {
+                return Self::new(&ArchiveOptions::default());
+            }
+            let mut this = Self::default();
+            // The `$null` at UID 0 is not a string to reuse.
+            for (uid, value) in objects.iter().enumerate().skip(1) {
+                let uid = PlistUid::new(uid as _);
+                match value {
+                    PlistValue::String(string) => {
+                        this.strings.entry(string.clone()).or_insert(uid);
+                    }
+                    PlistValue::Dictionary(dict) => {
+                        if let Some(class_names) = archived_class_names(dict) {
+                            this.archived_class_infos.entry(class_names).or_insert(uid);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            this.objects = objects;
+            this
+        }
+
+        pub fn into_objects(self) -> Vec<PlistValue> {
+            self.objects
+        }
+
         pub fn encode_new_object<E>(&mut self, encode_f: E) -> PlistUid
         where
-            E: FnOnce(&mut dyn Encoder) -> Vec<&'static str>,
+            E: FnOnce(&mut dyn Encoder) -> Vec<Class>,
         {
             let dict = PlistValue::Dictionary(PlistDictionary::new());
             self.objects.push(dict);
@@ -131,55 +1036,153 @@ mod __impl {
 
             self.with_active_object(new_object_index, |archiver| {
                 let classes = encode_f(archiver);
-
-                // Encodes the class info.
-                let class = *classes.first().expect("the type should have a class");
-                let mut class_info = PlistDictionary::new();
-                class_info.insert(
-                    "$classes".to_owned(),
-                    PlistValue::Array(
-                        classes
-                            .into_iter()
-                            .map(|s| PlistValue::String(s.to_owned()))
-                            .collect(),
-                    ),
-                );
-                class_info.insert(
-                    "$classname".to_owned(),
-                    PlistValue::String(class.to_owned()),
-                );
-                archiver.objects.push(PlistValue::Dictionary(class_info));
-                let class_info_index = archiver.objects.len() - 1;
+                let class_info = archiver.encode_class_info(classes);
 
                 let dict = archiver.ensure_active_object();
-                dict.insert(
-                    "$class".to_owned(),
-                    PlistValue::Uid(PlistUid::new(class_info_index as _)),
-                );
+                dict.insert(CLASS_KEY.to_owned(), PlistValue::Uid(class_info));
             });
 
             PlistUid::new(new_object_index as _)
         }
 
-        pub fn seal(self, root_object: PlistUid) -> ArchiveDict {
+        pub fn push_object(&mut self, value: PlistValue) -> PlistUid {
+            if let PlistValue::String(string) = &value {
+                if let Some(uid) = self.strings.get(string) {
+                    return *uid;
+                }
+            }
+
+            self.objects.push(value);
+            let uid = PlistUid::new((self.objects.len() - 1) as _);
+            if let Some(PlistValue::String(string)) = self.objects.last() {
+                self.strings.insert(string.clone(), uid);
+            }
+            uid
+        }
+
+        fn encode_class_info(&mut self, classes: Vec<Class>) -> PlistUid {
+            if let Some(uid) = self.class_infos.get(&classes) {
+                return *uid;
+            }
+
+            let class_names: Vec<_> = classes
+                .iter()
+                .map(|class| match self.class_names.get(class) {
+                    Some(class_name) => class_name.clone(),
+                    None => class.name().to_owned(),
+                })
+                .collect();
+            if let Some(uid) = self.archived_class_infos.get(&class_names) {
+                let uid = *uid;
+                self.class_infos.insert(classes, uid);
+                return uid;
+            }
+            let class = class_names
+                .first()
+                .expect("the type should have a class")
+                .clone();
+            let mut class_info = PlistDictionary::new();
+            class_info.insert(
+                "$classes".to_owned(),
+                PlistValue::Array(
+                    class_names
+                        .iter()
+                        .cloned()
+                        .map(PlistValue::String)
+                        .collect(),
+                ),
+            );
+            class_info.insert("$classname".to_owned(), PlistValue::String(class));
+            self.objects.push(PlistValue::Dictionary(class_info));
+            let uid = PlistUid::new((self.objects.len() - 1) as _);
+            self.class_infos.insert(classes, uid);
+            self.archived_class_infos.insert(class_names, uid);
+            uid
+        }
+
+        /// Copies an object of unknown fields into the archive, unless it is
+        /// a string or class info that has been archived already.
+        fn push_unknown_object(&mut self, value: &PlistValue) -> Option<PlistUid> {
+            match value {
+                PlistValue::String(_) => return Some(self.push_object(value.clone())),
+                PlistValue::Dictionary(dict) => {
+                    if let Some(class_names) = archived_class_names(dict) {
+                        if let Some(uid) = self.archived_class_infos.get(&class_names) {
+                            return Some(*uid);
+                        }
+                        self.objects.push(value.clone());
+                        let uid = PlistUid::new((self.objects.len() - 1) as _);
+                        self.archived_class_infos.insert(class_names, uid);
+                        return Some(uid);
+                    }
+                }
+                _ => {}
+            }
+            None
+        }
+
+        pub fn encode_any_object(&mut self, object: &dyn EncodeObject) -> PlistUid {
+            let id = object.object_id();
+            if let Some(uid) = id.and_then(|id| self.object_uids.get(&id)) {
+                return *uid;
+            }
+            if let Some(value) = object.inline_value() {
+                let uid = self.push_object(value);
+                if let Some(id) = id {
+                    self.object_uids.insert(id, uid);
+                }
+                return uid;
+            }
+            // Register the object before encoding it, so that cyclic references
+            // of shared objects resolve to the object itself.
+            if let Some(id) = id {
+                let uid = PlistUid::new(self.objects.len() as _);
+                self.object_uids.insert(id, uid);
+            }
+            self.encode_new_object(|archiver| {
+                object.encode_with(archiver);
+                object.classes()
+            })
+        }
+
+        /// Returns the UID of an object of unknown fields that is encoded by
+        /// other means, if it can be found.
+        fn resolve_alias(&mut self, alias: &Alias) -> Option<PlistUid> {
+            match alias {
+                Alias::Ancestor(level) => {
+                    let index = self.active_objects.len().checked_sub(level + 1)?;
+                    Some(PlistUid::new(self.active_objects[index] as _))
+                }
+                Alias::Value(key, index) => {
+                    let value = self.ensure_active_object().get(key)?;
+                    match index {
+                        Some(index) => value.as_array()?.get(*index)?.as_uid().copied(),
+                        None => value.as_uid().copied(),
+                    }
+                }
+                Alias::Shared(object) => Some(self.encode_any_object(&**object)),
+            }
+        }
+
+        pub fn seal(self, top_objects: BTreeMap<String, PlistUid>) -> ArchiveDict {
             ArchiveDict {
                 archiver_class_name: KEYED_ARCHIVER_CLASS_NAME.to_owned(),
                 objects: self.objects,
-                top_objects: HashMap::from([("root".to_owned(), root_object)]),
+                top_objects,
                 version: 100000,
             }
         }
 
         fn with_active_object<F: FnOnce(&mut Self)>(&mut self, index: usize, f: F) {
-            let last_index = self.active_object_index.take();
-            self.active_object_index = Some(index);
+            self.active_objects.push(index);
             f(self);
-            self.active_object_index = last_index;
+            self.active_objects.pop();
         }
 
         fn ensure_active_object(&mut self) -> &mut PlistDictionary {
-            let index = self
-                .active_object_index
+            let index = *self
+                .active_objects
+                .last()
                 .expect("expected an active object index");
             if self.objects.len() <= index {
                 panic!("internal state of archiver is inconsistent");
@@ -200,62 +1203,282 @@ mod __impl {
             dict.insert(key.to_owned(), PlistValue::Integer(value.into()));
         }
 
+        fn encode_bool(&mut self, value: bool, key: &str) {
+            let dict = self.ensure_active_object();
+            dict.insert(key.to_owned(), PlistValue::Boolean(value));
+        }
+
+        fn encode_f32(&mut self, value: f32, key: &str) {
+            self.encode_f64(value as f64, key)
+        }
+
+        fn encode_f64(&mut self, value: f64, key: &str) {
+            let dict = self.ensure_active_object();
+            dict.insert(key.to_owned(), PlistValue::Real(value));
+        }
+
         fn encode_string(&mut self, value: &str, key: &str) {
-            self.objects.push(PlistValue::String(value.to_owned()));
-            let index = self.objects.len() - 1;
+            let string = self.push_object(PlistValue::String(value.to_owned()));
 
             let dict = self.ensure_active_object();
-            dict.insert(key.to_owned(), PlistValue::Uid(PlistUid::new(index as _)));
+            dict.insert(key.to_owned(), PlistValue::Uid(string));
         }
 
-        fn encode_object(&mut self, object: &AnyObject, key: &str) {
-            let object = self.encode_new_object(|archiver| {
-                object.encode(archiver);
-                object.get_classes()
-            });
+        fn encode_bytes(&mut self, value: &[u8], key: &str) {
+            // Unlike strings, bytes are stored inline in the object.
+            let dict = self.ensure_active_object();
+            dict.insert(key.to_owned(), PlistValue::Data(value.to_owned()));
+        }
+
+        fn encode_value(&mut self, value: &dyn EncodeObject, key: &str) {
+            let object = self.encode_any_object(value);
+
+            let dict = self.ensure_active_object();
+            dict.insert(key.to_owned(), PlistValue::Uid(object));
+        }
+
+        fn encode_shared_object(&mut self, object: &ObjectRef, key: &str) {
+            let object = self.encode_any_object(&**object);
 
             let dict = self.ensure_active_object();
             dict.insert(key.to_owned(), PlistValue::Uid(object));
         }
+
+        fn encode_values(&mut self, values: &[&dyn EncodeObject], key: &str) {
+            let objects = values
+                .iter()
+                .map(|value| PlistValue::Uid(self.encode_any_object(*value)))
+                .collect();
+
+            let dict = self.ensure_active_object();
+            dict.insert(key.to_owned(), PlistValue::Array(objects));
+        }
+
+        fn encode_shared_objects(&mut self, objects: &[ObjectRef], key: &str) {
+            let objects = objects
+                .iter()
+                .map(|object| PlistValue::Uid(self.encode_any_object(&**object)))
+                .collect();
+
+            let dict = self.ensure_active_object();
+            dict.insert(key.to_owned(), PlistValue::Array(objects));
+        }
+
+        fn encode_unknown_fields(&mut self, fields: &UnknownFields) {
+            if fields.is_empty() {
+                return;
+            }
+            let objects = fields.objects();
+            let mut uids: Vec<Option<PlistUid>> = vec![None; objects.len()];
+            uids[0] = Some(PlistUid::new(0));
+            for (local_uid, uid) in uids.iter_mut().enumerate().skip(1) {
+                if let Some(alias) = fields.alias_of(local_uid as u64) {
+                    *uid = match (self.resolve_alias(alias), alias) {
+                        (None, Alias::Ancestor(_)) => Some(PlistUid::new(0)),
+                        (uid, _) => uid,
+                    };
+                }
+            }
+
+            // Only the objects that are not found by their aliases are
+            // copied, along with the objects reachable from them.
+            let mut reachable = vec![false; objects.len()];
+            let mut pending: Vec<_> = fields
+                .fields()
+                .iter()
+                .flat_map(|(_, value)| references_of(value))
+                .collect();
+            while let Some(local_uid) = pending.pop() {
+                let local_uid = local_uid as usize;
+                if local_uid >= objects.len() || reachable[local_uid] || uids[local_uid].is_some() {
+                    continue;
+                }
+                reachable[local_uid] = true;
+                pending.extend(references_of(&objects[local_uid]));
+            }
+
+            // Strings and class info are leaves, they are uniqued first. Every
+            // other object gets a UID before it is copied, since objects may
+            // reference each other in any order.
+            let mut copied_objects = vec![];
+            for (local_uid, value) in objects.iter().enumerate() {
+                if !reachable[local_uid] {
+                    continue;
+                }
+                uids[local_uid] = Some(match self.push_unknown_object(value) {
+                    Some(uid) => uid,
+                    None => {
+                        self.objects.push(PlistValue::Boolean(false));
+                        copied_objects.push(local_uid);
+                        PlistUid::new((self.objects.len() - 1) as _)
+                    }
+                });
+            }
+            let uid_of = |uid: PlistUid| {
+                uids.get(uid.get() as usize)
+                    .copied()
+                    .flatten()
+                    .unwrap_or(PlistUid::new(0))
+            };
+            for local_uid in copied_objects {
+                let value = map_references(&objects[local_uid], &mut |uid| uid_of(uid));
+                let uid = uid_of(PlistUid::new(local_uid as _));
+                self.objects[uid.get() as usize] = value;
+            }
+
+            for (key, value) in fields.fields() {
+                let value = map_references(value, &mut |uid| uid_of(uid));
+                let dict = self.ensure_active_object();
+                if !dict.contains_key(key) {
+                    dict.insert(key.clone(), value);
+                }
+            }
+        }
+    }
+
+    /// Returns the class names of archived class info, or `None` if the
+    /// dictionary is not class info.
+    fn archived_class_names(dict: &PlistDictionary) -> Option<Vec<String>> {
+        dict.get("$classname")?;
+        dict.get("$classes")?
+            .as_array()?
+            .iter()
+            .map(|class| class.as_string().map(str::to_owned))
+            .collect()
     }
 
     pub struct Unarchiver<'t> {
         dict: ArchiveDict,
-        active_object: Cell<Option<PlistUid>>,
-        type_registry: &'t TypeRegistry,
+        state: DecodingState<'t>,
+        // Keys of the active object that have been read, the rest are
+        // unknown fields.
+        read_keys: RefCell<HashSet<String>>,
     }
 
     impl<'t> Unarchiver<'t> {
-        pub fn new(dict: ArchiveDict, registry: &'t TypeRegistry) -> Self {
+        pub fn new(
+            dict: ArchiveDict,
+            registry: &'t TypeRegistry,
+            options: &'t UnarchiveOptions,
+        ) -> Self {
             Self {
                 dict,
-                active_object: Cell::new(None),
-                type_registry: registry,
+                state: DecodingState::new(registry, options),
+                read_keys: RefCell::new(HashSet::new()),
             }
         }
 
         pub fn unarchive_root_object(&self) -> Result<AnyObject, Error> {
             // Validate the archiver class before actually unarchiving.
-            let archiver_class_name = &self.dict.archiver_class_name;
-            if archiver_class_name != KEYED_ARCHIVER_CLASS_NAME {
-                return Err(Error::UnsupportedArchiver(archiver_class_name.clone()));
-            }
+            let root_key = &self.state.options().root_key;
+            let root_object = self.dict.top_object(root_key)?;
+            self.state
+                .decode_top_object(root_key, || self.decode_object_at(root_object))
+        }
 
-            let Some(root_object) = self.dict.top_objects.get("root") else {
-                return Err(Error::NoRootObject);
-            };
-            if self.dict.objects.len() <= root_object.get() as usize {
+        pub fn unarchive_top_objects(&self) -> Result<BTreeMap<String, AnyObject>, Error> {
+            self.dict.validate_archiver()?;
+            self.dict
+                .top_objects
+                .iter()
+                .map(|(key, uid)| {
+                    let object = self
+                        .state
+                        .decode_top_object(key, || self.decode_object_at(*uid))?;
+                    Ok((key.clone(), object))
+                })
+                .collect()
+        }
+
+        /// Decodes the object with the given UID, which is either a plain value
+        /// or a keyed object.
+        fn decode_object_at(&self, uid: PlistUid) -> Result<AnyObject, Error> {
+            // UID 0 is always `$null`, which cannot be represented as an object.
+            let index = uid.get() as usize;
+            if index == 0 || self.dict.objects.len() <= index {
                 return Err(Error::MalformedObject);
             }
+            self.state.count_object()?;
+            let value = &self.dict.objects[index];
+            if let Some(object) = from_inline_value(value) {
+                self.check_length(value, || self.state.path())?;
+                self.state.check_allowed_class(object.class_name())?;
+                return Ok(object);
+            }
+
+            self.state.decode_object(uid.get(), || {
+                let last_read_keys = self.read_keys.take();
+                let decoded_object = self.decode_active_object();
+                self.read_keys.replace(last_read_keys);
+                decoded_object
+            })
+        }
+
+        fn decode_shared_object_at(&self, uid: PlistUid) -> Result<ObjectRef, Error> {
+            // Plain values are uniqued by value when archived, so they have no
+            // identity to keep.
+            if self.is_plain_value(uid) {
+                return self.decode_object_at(uid).map(Rc::new);
+            }
+            self.state
+                .decode_shared_object(uid.get(), || self.decode_object_at(uid))
+        }
+
+        fn decode_weak_object_at(&self, uid: PlistUid) -> Result<WeakObjectRef, Error> {
+            if self.is_plain_value(uid) {
+                return self.decode_object_at(uid).map(|_| WeakObjectRef::new());
+            }
+            self.state
+                .decode_weak_object(uid.get(), || self.decode_object_at(uid))
+        }
+
+        fn is_plain_value(&self, uid: PlistUid) -> bool {
+            self.dict
+                .objects
+                .get(uid.get() as usize)
+                .is_some_and(|value| value.as_dictionary().is_none())
+        }
 
-            // Set the root object as active and start decoding.
-            self.active_object.set(Some(*root_object));
-            self.decode_active_object()
+        /// Decodes the elements of an array of references with a given key,
+        /// each with the given function.
+        fn decode_elements<T, F>(&self, key: &str, decode_f: F) -> Result<Vec<T>, Error>
+        where
+            F: Fn(PlistUid) -> Result<T, Error>,
+        {
+            let objects = self.value_for_key(key).and_then(|value| {
+                value
+                    .as_array()
+                    .ok_or_else(|| self.type_mismatch(key, "array", value))
+            });
+            let objects = self.state.record(objects)?;
+
+            // Elements of collection classes are addressed like `.Files[3]`
+            // instead of `.Files.NS.objects[3]`.
+            let prefix = if key == NS_OBJECTS_KEY {
+                String::new()
+            } else {
+                format!(".{key}")
+            };
+            let decoded_objects = objects
+                .iter()
+                .enumerate()
+                .map(|(index, object)| {
+                    self.state
+                        .with_path_segment(format!("{prefix}[{index}]"), || {
+                            let uid = object.as_uid().ok_or_else(|| Error::TypeMismatch {
+                                path: self.state.path(),
+                                expected: "reference",
+                                actual: value_type_name(object),
+                            })?;
+                            decode_f(*uid)
+                        })
+                })
+                .collect();
+            self.state.record(decoded_objects)
         }
 
         fn ensure_active_object(&self) -> &PlistValue {
-            let uid = self.active_object.get().expect("expected an active object");
-            let index = uid.get() as usize;
+            let index = self.state.active_object() as usize;
             if self.dict.objects.len() <= index {
                 panic!("internal state of unarchiver is inconsistent");
             }
@@ -266,78 +1489,215 @@ mod __impl {
             let Some(dict) = self.ensure_active_object().as_dictionary() else {
                 return Err(Error::MalformedObject);
             };
-            let Some(class) = dict.get("$class").and_then(|value| value.as_uid()) else {
-                return Err(Error::MalformedObject);
-            };
+            let classes = self.dict.classes_of(dict)?;
+            self.state.decode_registered_object(&classes, self)
+        }
 
-            let class_index = class.get() as usize;
-            if self.dict.objects.len() <= class_index {
-                return Err(Error::MalformedObject);
+        /// Checks the length of a string or a buffer of bytes, the key path is
+        /// only evaluated when the limit is exceeded.
+        fn check_length<P: FnOnce() -> String>(
+            &self,
+            value: &PlistValue,
+            path: P,
+        ) -> Result<(), Error> {
+            match value {
+                PlistValue::String(string) => self.state.check_length(string.len(), path),
+                PlistValue::Data(data) => self.state.check_length(data.len(), path),
+                _ => Ok(()),
+            }
+        }
+
+        fn value_for_key(&self, key: &str) -> Result<&PlistValue, Error> {
+            if !self.read_keys.borrow().contains(key) {
+                self.read_keys.borrow_mut().insert(key.to_owned());
             }
-            let Some(class_name) = self.dict.objects[class_index]
+            self.ensure_active_object()
                 .as_dictionary()
-                .and_then(|dict| dict.get("$classname"))
-                .and_then(|value| value.as_string())
-            else {
-                return Err(Error::MalformedObject);
-            };
+                .and_then(|dict| dict.get(key))
+                .ok_or_else(|| Error::MissingKey {
+                    path: self.state.key_path(key),
+                })
+        }
 
-            let Some(unarchive_fn) = self.type_registry.get_unarchive_fn(class_name) else {
-                return Err(Error::UnknownClass(class_name.to_owned()));
-            };
+        fn uid_for_key(&self, key: &str) -> Result<PlistUid, Error> {
+            let value = self.value_for_key(key)?;
+            value
+                .as_uid()
+                .copied()
+                .ok_or_else(|| self.type_mismatch(key, "reference", value))
+        }
 
-            match unarchive_fn(self) {
-                Some(object) => Ok(object),
-                None => Err(Error::MalformedObject),
+        /// Returns the value associated with a given key, or the object it
+        /// references.
+        fn resolved_value_for_key(&self, key: &str) -> Result<&PlistValue, Error> {
+            let value = self.value_for_key(key)?;
+            match value.as_uid() {
+                Some(uid) => self
+                    .dict
+                    .objects
+                    .get(uid.get() as usize)
+                    .ok_or(Error::MalformedObject),
+                None => Ok(value),
+            }
+        }
+
+        fn type_mismatch(&self, key: &str, expected: &'static str, actual: &PlistValue) -> Error {
+            Error::TypeMismatch {
+                path: self.state.key_path(key),
+                expected,
+                actual: value_type_name(actual),
             }
         }
     }
 
     impl<'t> traits::Decoder for Unarchiver<'t> {
-        fn decode_i32(&self, key: &str) -> i32 {
-            self.decode_i64(key) as i32
+        fn contains_key(&self, key: &str) -> bool {
+            key != CLASS_KEY
+                && self
+                    .ensure_active_object()
+                    .as_dictionary()
+                    .is_some_and(|dict| dict.contains_key(key))
         }
 
-        fn decode_i64(&self, key: &str) -> i64 {
+        fn keys(&self) -> Vec<String> {
             let Some(dict) = self.ensure_active_object().as_dictionary() else {
-                return 0;
+                return vec![];
             };
-            dict.get(key)
-                .and_then(|value| value.as_signed_integer())
-                .unwrap_or(0)
+            dict.keys()
+                .filter(|key| *key != CLASS_KEY)
+                .cloned()
+                .collect()
         }
 
-        fn decode_string(&self, key: &str) -> Option<String> {
+        fn unknown_fields(&self) -> UnknownFields {
             let Some(dict) = self.ensure_active_object().as_dictionary() else {
-                return None;
+                return UnknownFields::default();
             };
-            let Some(object) = dict.get(key).and_then(|value| value.as_uid()) else {
-                return None;
+            let read_keys = self.read_keys.borrow();
+            let fields = dict
+                .iter()
+                .filter(|(key, _)| *key != CLASS_KEY && !read_keys.contains(*key));
+
+            // Keyed objects that are decoded anyway are referenced rather than
+            // copied, plain values are compared by value.
+            let active_objects = self.state.active_objects();
+            let shared_objects = self.state.shared_objects();
+            let alias_of = |uid: u64| {
+                self.dict.objects.get(uid as usize)?.as_dictionary()?;
+                if let Some(level) = active_objects.iter().rev().position(|a| *a == uid) {
+                    return Some(Alias::Ancestor(level));
+                }
+                let is_uid = |value: &PlistValue| value.as_uid().is_some_and(|u| u.get() == uid);
+                for (key, value) in dict.iter().filter(|(key, _)| read_keys.contains(*key)) {
+                    if is_uid(value) {
+                        return Some(Alias::Value(key.clone(), None));
+                    }
+                    if let Some(index) = value.as_array().and_then(|a| a.iter().position(is_uid)) {
+                        return Some(Alias::Value(key.clone(), Some(index)));
+                    }
+                }
+                let object = shared_objects.get(&uid)?.upgrade()?;
+                Some(Alias::Shared(object))
             };
-            let index = object.get() as usize;
-            if self.dict.objects.len() <= index {
-                return None;
-            }
+            UnknownFields::capture(&self.dict, fields, alias_of)
+        }
 
-            self.dict.objects[index].as_string().map(str::to_owned)
+        fn try_decode_i64(&self, key: &str) -> Result<i64, Error> {
+            self.state.record(self.value_for_key(key).and_then(|value| {
+                value
+                    .as_signed_integer()
+                    .ok_or_else(|| self.type_mismatch(key, "integer", value))
+            }))
         }
 
-        fn decode_object(&self, key: &str) -> Option<AnyObject> {
-            let Some(dict) = self.ensure_active_object().as_dictionary() else {
-                return None;
-            };
-            let Some(object) = dict.get(key).and_then(|value| value.as_uid()) else {
-                return None;
-            };
-            if self.dict.objects.len() <= object.get() as usize {
-                return None;
-            }
+        fn try_decode_i32(&self, key: &str) -> Result<i32, Error> {
+            self.state
+                .record(self.try_decode_i64(key).and_then(|value| {
+                    i32::try_from(value).map_err(|_| Error::TypeMismatch {
+                        path: self.state.key_path(key),
+                        expected: "32-bit integer",
+                        actual: "integer out of range",
+                    })
+                }))
+        }
+
+        fn try_decode_bool(&self, key: &str) -> Result<bool, Error> {
+            self.state.record(self.value_for_key(key).and_then(|value| {
+                value
+                    .as_boolean()
+                    .ok_or_else(|| self.type_mismatch(key, "boolean", value))
+            }))
+        }
+
+        fn try_decode_f64(&self, key: &str) -> Result<f64, Error> {
+            // Like `NSKeyedUnarchiver`, integers are also accepted here.
+            self.state.record(self.value_for_key(key).and_then(|value| {
+                value
+                    .as_real()
+                    .or_else(|| value.as_signed_integer().map(|i| i as f64))
+                    .ok_or_else(|| self.type_mismatch(key, "real", value))
+            }))
+        }
+
+        fn try_decode_string(&self, key: &str) -> Result<String, Error> {
+            // Strings are archived as separate objects, except the contents
+            // of `NSMutableString` objects, which are stored inline.
+            self.state
+                .record(self.resolved_value_for_key(key).and_then(|value| {
+                    self.check_length(value, || self.state.key_path(key))?;
+                    value
+                        .as_string()
+                        .map(str::to_owned)
+                        .ok_or_else(|| self.type_mismatch(key, "string", value))
+                }))
+        }
 
-            let last_object = self.active_object.replace(Some(*object));
-            let decoded_object = self.decode_active_object().ok();
-            self.active_object.set(last_object);
+        fn try_decode_bytes(&self, key: &str) -> Result<Vec<u8>, Error> {
+            self.state.record(self.value_for_key(key).and_then(|value| {
+                self.check_length(value, || self.state.key_path(key))?;
+                value
+                    .as_data()
+                    .map(<[u8]>::to_owned)
+                    .ok_or_else(|| self.type_mismatch(key, "data", value))
+            }))
+        }
+
+        fn try_decode_object(&self, key: &str) -> Result<AnyObject, Error> {
+            self.state.record(self.uid_for_key(key).and_then(|uid| {
+                self.state
+                    .with_path_segment(format!(".{key}"), || self.decode_object_at(uid))
+            }))
+        }
+
+        fn try_decode_object_of_classes(
+            &self,
+            classes: &[&str],
+            key: &str,
+        ) -> Result<AnyObject, Error> {
+            self.state
+                .with_allowed_classes(classes, || self.try_decode_object(key))
+        }
+
+        fn try_decode_shared_object(&self, key: &str) -> Result<ObjectRef, Error> {
+            self.state.record(self.uid_for_key(key).and_then(|uid| {
+                self.state
+                    .with_path_segment(format!(".{key}"), || self.decode_shared_object_at(uid))
+            }))
+        }
+
+        fn try_decode_weak_object(&self, key: &str) -> Result<WeakObjectRef, Error> {
+            self.state.record(self.uid_for_key(key).and_then(|uid| {
+                self.state
+                    .with_path_segment(format!(".{key}"), || self.decode_weak_object_at(uid))
+            }))
+        }
+
+        fn try_decode_objects(&self, key: &str) -> Result<Vec<AnyObject>, Error> {
+            self.decode_elements(key, |uid| self.decode_object_at(uid))
+        }
 
-            decoded_object
+        fn try_decode_shared_objects(&self, key: &str) -> Result<Vec<ObjectRef>, Error> {
+            self.decode_elements(key, |uid| self.decode_shared_object_at(uid))
         }
     }
 }
@@ -0,0 +1,223 @@
+//! A command-line tool to inspect and convert keyed archives.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use nscoder::{ArchiveDict, ArchiveNode, OutputFormat};
+use plist::Date as PlistDate;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// Inspects and converts keyed archives.
+///
+/// Archives can be read from binary or XML property lists, or from their JSON
+/// representation. Use `-` to read from the standard input.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Prints the object graph as a tree, with references resolved.
+    Dump { input: PathBuf },
+    /// Converts an archive to another format.
+    Convert {
+        input: PathBuf,
+        /// The format to convert to.
+        #[arg(short, long, value_enum)]
+        to: Format,
+        /// The file to write to, instead of the standard output.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Writes the archive in its canonical form, so that equal object
+        /// graphs are written byte for byte the same.
+        #[arg(long)]
+        canonical: bool,
+    },
+    /// Validates the structure of an archive.
+    Validate { input: PathBuf },
+    /// Prints the classes of all objects, with their superclasses and the
+    /// number of their instances.
+    Classes { input: PathBuf },
+    /// Prints the values that are added, removed or changed between two
+    /// archives, regardless of how their objects are numbered.
+    ///
+    /// Like `diff(1)`, exits with status 1 if the archives are different, and
+    /// with status 2 if they cannot be compared.
+    Diff { old: PathBuf, new: PathBuf },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Binary,
+    Xml,
+    /// The JSON representation, with references resolved into nested values.
+    Json,
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse().command) {
+        Ok(exit_code) => exit_code,
+        // Status 1 means different archives for `diff`, errors use 2 like
+        // `diff(1)` and argument errors do.
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run(command: Command) -> Result<ExitCode> {
+    match command {
+        Command::Dump { input } => {
+            let dict = read_archive(&input)?;
+            let root = ArchiveNode::from_archive_dict(&dict)?;
+            let mut output = String::new();
+            dump_node(&mut output, &root, 0, &mut HashSet::new());
+            io::stdout().write_all(output.as_bytes())?;
+        }
+        Command::Convert {
+            input,
+            to,
+            output,
+            canonical,
+        } => {
+            let mut dict = read_archive(&input)?;
+            if canonical {
+                dict.canonicalize();
+            }
+            let mut bytes = vec![];
+            match to {
+                Format::Binary => dict.to_writer(&mut bytes, OutputFormat::Binary)?,
+                Format::Xml => dict.to_writer(&mut bytes, OutputFormat::Xml)?,
+                Format::Json => {
+                    bytes.extend(nscoder::json::to_string(&dict)?.as_bytes());
+                    bytes.push(b'\n');
+                }
+            }
+            match output {
+                Some(path) => fs::write(path, bytes)?,
+                None => io::stdout().write_all(&bytes)?,
+            }
+        }
+        Command::Validate { input } => {
+            let dict = read_archive(&input)?;
+            dict.validate()?;
+            println!("{} objects, no errors found", dict.objects.len());
+        }
+        Command::Classes { input } => {
+            let dict = read_archive(&input)?;
+            print_classes(&dict)?;
+        }
+        Command::Diff { old, new } => {
+            let changes = nscoder::diff(&read_archive(&old)?, &read_archive(&new)?)?;
+            for change in &changes {
+                println!("{change}");
+            }
+            if !changes.is_empty() {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn read_archive(path: &Path) -> Result<ArchiveDict> {
+    let bytes = if path.as_os_str() == "-" {
+        let mut bytes = vec![];
+        io::stdin().read_to_end(&mut bytes)?;
+        bytes
+    } else {
+        fs::read(path)?
+    };
+
+    if bytes.trim_ascii_start().starts_with(b"{") {
+        return Ok(nscoder::json::from_str(std::str::from_utf8(&bytes)?)?);
+    }
+    Ok(ArchiveDict::from_bytes(&bytes)?)
+}
+
+fn dump_node(output: &mut String, node: &ArchiveNode, depth: usize, seen: &mut HashSet<u64>) {
+    let indent = "  ".repeat(depth + 1);
+    match node {
+        ArchiveNode::Null => output.push_str("nil\n"),
+        ArchiveNode::Boolean(boolean) => _ = writeln!(output, "{boolean}"),
+        ArchiveNode::Integer(integer) => _ = writeln!(output, "{integer}"),
+        ArchiveNode::UnsignedInteger(integer) => _ = writeln!(output, "{integer}"),
+        ArchiveNode::Real(real) => _ = writeln!(output, "{real:?}"),
+        ArchiveNode::Date(date) => {
+            _ = writeln!(output, "{}", PlistDate::from(*date).to_xml_format());
+        }
+        ArchiveNode::String(string) => _ = writeln!(output, "{string:?}"),
+        ArchiveNode::Data(data) => _ = writeln!(output, "<{} bytes>", data.len()),
+        ArchiveNode::Array(array) => {
+            _ = writeln!(output, "({} elements)", array.len());
+            for (index, node) in array.iter().enumerate() {
+                _ = write!(output, "{indent}[{index}]: ");
+                dump_node(output, node, depth + 1, seen);
+            }
+        }
+        ArchiveNode::Dictionary(dict) => {
+            _ = writeln!(output, "({} entries)", dict.len());
+            for (key, node) in dict {
+                _ = write!(output, "{indent}{key}: ");
+                dump_node(output, node, depth + 1, seen);
+            }
+        }
+        ArchiveNode::Object(object) => {
+            _ = write!(output, "{} #{}", object.class_name(), object.uid);
+            // Shared objects are only expanded once.
+            if !seen.insert(object.uid) {
+                output.push_str(" (see above)\n");
+                return;
+            }
+            output.push('\n');
+            for (key, node) in &object.fields {
+                _ = write!(output, "{indent}{key}: ");
+                dump_node(output, node, depth + 1, seen);
+            }
+        }
+        ArchiveNode::Reference(uid) => _ = writeln!(output, "#{uid} (cyclic reference)"),
+    }
+}
+
+fn print_classes(dict: &ArchiveDict) -> Result<()> {
+    let mut classes: Vec<(usize, Vec<&str>, usize)> = vec![];
+    for (uid, value) in dict.objects.iter().enumerate() {
+        let Some(object) = value.as_dictionary() else {
+            continue;
+        };
+        if object.contains_key("$classname") {
+            continue;
+        }
+        let class = dict.classes_of(object)?;
+        let class_uid = object
+            .get("$class")
+            .and_then(|value| value.as_uid())
+            .map_or(uid, |uid| uid.get() as usize);
+        match classes.iter_mut().find(|(uid, ..)| *uid == class_uid) {
+            Some((.., count)) => *count += 1,
+            None => classes.push((class_uid, class, 1)),
+        }
+    }
+
+    classes.sort_by(|(_, a, _), (_, b, _)| a.cmp(b));
+    for (_, class, count) in classes {
+        let (class_name, superclasses) = class.split_first().expect("expected a class name");
+        print!("{class_name} ({count})");
+        if !superclasses.is_empty() {
+            print!(": {}", superclasses.join(", "));
+        }
+        println!();
+    }
+    Ok(())
+}
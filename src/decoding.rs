@@ -0,0 +1,320 @@
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::{Rc, Weak};
+
+use crate::archiver::{Decoder, UnarchiveOptions};
+use crate::object::{AnyObject, ObjectRef, RootObject, TypeRegistry, WeakObjectRef};
+use crate::types::Error;
+
+/// The state of a decoding that does not depend on the archive format, which
+/// is shared by the unarchivers of keyed archives, typedstream and NIB files.
+///
+/// Objects are identified by their indices in the archive, whatever they are
+/// in the format.
+pub(crate) struct DecodingState<'t> {
+    type_registry: &'t TypeRegistry,
+    options: &'t UnarchiveOptions,
+    // Indices of the objects being decoded, the active one is the last.
+    active_objects: RefCell<Vec<u64>>,
+    // The same objects as `active_objects`, used to detect cycles.
+    decoding_objects: RefCell<HashSet<u64>>,
+    // Objects that are decoded via `decode_shared_object`, keyed by index.
+    // They are registered before being decoded, so that their children can
+    // reference them weakly.
+    shared_objects: RefCell<HashMap<u64, WeakObjectRef>>,
+    // The first fatal error that occurred in a nested decoding, which
+    // cannot be propagated through the `Decoder` methods.
+    fatal_error: RefCell<Option<Error>>,
+    // The most recent error of the active object, which is reported when
+    // the object fails to decode.
+    last_error: RefCell<Option<Error>>,
+    // Segments of the key path to the active object, like `root`,
+    // `.Files` and `[3]`.
+    path: RefCell<Vec<String>>,
+    // Classes that are allowed to be decoded in the active secure decoding.
+    allowed_classes: RefCell<Option<Vec<String>>>,
+    // The number of objects that have been decoded so far.
+    object_count: Cell<usize>,
+}
+
+impl<'t> DecodingState<'t> {
+    pub fn new(registry: &'t TypeRegistry, options: &'t UnarchiveOptions) -> Self {
+        Self {
+            type_registry: registry,
+            options,
+            active_objects: RefCell::new(vec![]),
+            decoding_objects: RefCell::new(HashSet::new()),
+            shared_objects: RefCell::new(HashMap::new()),
+            fatal_error: RefCell::new(None),
+            last_error: RefCell::new(None),
+            path: RefCell::new(vec![]),
+            allowed_classes: RefCell::new(None),
+            object_count: Cell::new(0),
+        }
+    }
+
+    pub fn options(&self) -> &UnarchiveOptions {
+        self.options
+    }
+
+    /// Decodes a top-level object with the given key, allowing only the
+    /// classes of the options (if any).
+    pub fn decode_top_object<F>(&self, key: &str, decode_f: F) -> Result<AnyObject, Error>
+    where
+        F: FnOnce() -> Result<AnyObject, Error>,
+    {
+        let decoded_object =
+            self.with_path_segment(key.to_owned(), || match &self.options.allowed_classes {
+                Some(classes) => {
+                    let classes: Vec<_> = classes.iter().map(String::as_str).collect();
+                    self.with_allowed_classes(&classes, decode_f)
+                }
+                None => decode_f(),
+            });
+
+        // Fatal errors take precedence, even if the top-level object
+        // itself managed to decode without the failed parts.
+        if let Some(error) = self.fatal_error.take() {
+            return Err(error);
+        }
+        decoded_object
+    }
+
+    /// Counts an object towards the object limit, including plain values
+    /// that are archived as objects.
+    pub fn count_object(&self) -> Result<(), Error> {
+        let object_count = self.object_count.get() + 1;
+        if let Some(max_objects) = self.options.max_objects {
+            if object_count > max_objects {
+                return Err(self.fatal(Error::ObjectLimitExceeded(max_objects)));
+            }
+        }
+        self.object_count.set(object_count);
+        Ok(())
+    }
+
+    /// Makes the object with the given index active while it is decoded, so
+    /// that the `Decoder` methods read its values.
+    pub fn decode_object<F>(&self, index: u64, decode_f: F) -> Result<AnyObject, Error>
+    where
+        F: FnOnce() -> Result<AnyObject, Error>,
+    {
+        // An object that references itself (directly or not) cannot be
+        // represented by owned values, bail out instead of recursing forever.
+        if self.decoding_objects.borrow().contains(&index) {
+            return Err(self.fatal(Error::CyclicReference(index)));
+        }
+        // Every object being decoded is an ancestor of this object.
+        let depth = self.decoding_objects.borrow().len() + 1;
+        if self
+            .options
+            .max_depth
+            .is_some_and(|max_depth| depth > max_depth)
+        {
+            return Err(self.fatal(Error::DepthLimitExceeded { path: self.path() }));
+        }
+
+        self.decoding_objects.borrow_mut().insert(index);
+        self.active_objects.borrow_mut().push(index);
+        let last_error = self.last_error.take();
+        let decoded_object = decode_f();
+        self.last_error.replace(last_error);
+        self.active_objects.borrow_mut().pop();
+        self.decoding_objects.borrow_mut().remove(&index);
+
+        decoded_object
+    }
+
+    /// Decodes the object with the given index only once, and returns the
+    /// same object for every later call while it is alive.
+    pub fn decode_shared_object<F>(&self, index: u64, decode_f: F) -> Result<ObjectRef, Error>
+    where
+        F: FnOnce() -> Result<AnyObject, Error>,
+    {
+        let cached_object = self.shared_objects.borrow().get(&index).map(Weak::upgrade);
+        match cached_object {
+            Some(Some(object)) => {
+                self.check_allowed_class(object.archived_class_name())?;
+                return Ok(object);
+            }
+            // The object is being decoded, only weak references to it can
+            // be resolved until it is complete.
+            Some(None) if self.decoding_objects.borrow().contains(&index) => {
+                return Err(self.fatal(Error::CyclicReference(index)));
+            }
+            _ => {}
+        }
+
+        let mut result = Ok(());
+        let object = Rc::new_cyclic(|weak_object| {
+            self.shared_objects
+                .borrow_mut()
+                .insert(index, Weak::clone(weak_object));
+            decode_f().unwrap_or_else(|error| {
+                result = Err(error);
+                AnyObject::erasing(RootObject)
+            })
+        });
+        if let Err(error) = result {
+            self.shared_objects.borrow_mut().remove(&index);
+            return Err(error);
+        }
+        Ok(object)
+    }
+
+    /// Returns a weak reference to the object with the given index, which is
+    /// decoded like [`DecodingState::decode_shared_object`] unless it is
+    /// already alive or being decoded.
+    pub fn decode_weak_object<F>(&self, index: u64, decode_f: F) -> Result<WeakObjectRef, Error>
+    where
+        F: FnOnce() -> Result<AnyObject, Error>,
+    {
+        if let Some(object) = self.shared_objects.borrow().get(&index) {
+            if object.strong_count() > 0 || self.decoding_objects.borrow().contains(&index) {
+                return Ok(Weak::clone(object));
+            }
+        }
+        self.decode_shared_object(index, decode_f)
+            .map(|object| Rc::downgrade(&object))
+    }
+
+    /// Decodes the active object with the type registered for the first of
+    /// its classes (the class and its superclasses) that has one.
+    pub fn decode_registered_object(
+        &self,
+        classes: &[&str],
+        decoder: &dyn Decoder,
+    ) -> Result<AnyObject, Error> {
+        // The archived class is checked rather than the type it decodes as,
+        // so that allowing a class does not allow its subclasses.
+        let class_name = classes[0];
+        self.check_allowed_class(class_name)?;
+        let Some((decoded_class_name, unarchive_fn)) = self.type_registry.get_unarchive_fn(classes)
+        else {
+            return Err(Error::UnknownClass(class_name.to_owned()));
+        };
+
+        match unarchive_fn(decoder) {
+            Some(mut object) => {
+                if decoded_class_name != class_name {
+                    object.set_archived_class_name(class_name.to_owned());
+                }
+                Ok(object)
+            }
+            // Report the most recent failure of this object if any, since
+            // it is most likely the reason.
+            None => Err(self
+                .last_error
+                .take()
+                .unwrap_or_else(|| Error::DecodeFailed {
+                    path: self.path(),
+                    class: class_name.to_owned(),
+                })),
+        }
+    }
+
+    /// Returns the index of the active object.
+    pub fn active_object(&self) -> u64 {
+        *self
+            .active_objects
+            .borrow()
+            .last()
+            .expect("expected an active object")
+    }
+
+    /// Returns the indices of the objects being decoded, the active one is
+    /// the last.
+    pub fn active_objects(&self) -> Ref<'_, Vec<u64>> {
+        self.active_objects.borrow()
+    }
+
+    pub fn shared_objects(&self) -> Ref<'_, HashMap<u64, WeakObjectRef>> {
+        self.shared_objects.borrow()
+    }
+
+    /// Decodes with the given classes allowed, and only them.
+    pub fn with_allowed_classes<T, F>(&self, classes: &[&str], f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        let allowed_classes = classes.iter().map(|class| (*class).to_owned()).collect();
+        let last_allowed_classes = self.allowed_classes.replace(Some(allowed_classes));
+        let result = f();
+        self.allowed_classes.replace(last_allowed_classes);
+        result
+    }
+
+    /// Checks whether objects of the given class are allowed to be decoded
+    /// by the active secure decoding (if any).
+    pub fn check_allowed_class(&self, class_name: &str) -> Result<(), Error> {
+        let allowed_classes = self.allowed_classes.borrow();
+        let Some(allowed_classes) = allowed_classes.as_ref() else {
+            return Ok(());
+        };
+        if allowed_classes.iter().any(|class| class == class_name) {
+            return Ok(());
+        }
+
+        // Disallowed classes are fatal, a secure decoding must not succeed
+        // by skipping them.
+        Err(self.fatal(Error::DisallowedClass {
+            path: self.path(),
+            class: class_name.to_owned(),
+        }))
+    }
+
+    /// Checks the length of a string or a buffer of bytes, the key path is
+    /// only evaluated when the limit is exceeded.
+    pub fn check_length<P: FnOnce() -> String>(&self, length: usize, path: P) -> Result<(), Error> {
+        match self.options.max_length {
+            Some(max_length) if length > max_length => {
+                Err(self.fatal(Error::LengthLimitExceeded {
+                    path: path(),
+                    max_length,
+                }))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Records an error that fails the whole archive, since it cannot be
+    /// propagated through the `Decoder` methods.
+    pub fn fatal(&self, error: Error) -> Error {
+        if let Some(error) = error.try_clone() {
+            self.fatal_error.borrow_mut().get_or_insert(error);
+        }
+        error
+    }
+
+    /// Remembers the error (if any) of a decoding attempt, so that it can
+    /// be reported when the active object fails to decode.
+    ///
+    /// A successful attempt forgets the previous error, since the object
+    /// went on decoding after it. This way a failed lookup of an optional
+    /// value is not blamed for a failure that happens later.
+    pub fn record<T>(&self, result: Result<T, Error>) -> Result<T, Error> {
+        let error = result.as_ref().err().and_then(Error::try_clone);
+        self.last_error.replace(error);
+        result
+    }
+
+    pub fn with_path_segment<T, F: FnOnce() -> T>(&self, segment: String, f: F) -> T {
+        self.path.borrow_mut().push(segment);
+        let result = f();
+        self.path.borrow_mut().pop();
+        result
+    }
+
+    /// Returns the key path to the active object.
+    pub fn path(&self) -> String {
+        self.path.borrow().concat()
+    }
+
+    /// Returns the key path to a given key of the active object.
+    pub fn key_path(&self, key: &str) -> String {
+        let mut path = self.path();
+        path.push('.');
+        path.push_str(key);
+        path
+    }
+}
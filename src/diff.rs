@@ -0,0 +1,263 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+use plist::Date as PlistDate;
+
+use crate::node::{top_nodes, ArchiveNode, ArchivedObject};
+use crate::object::NS_OBJECTS_KEY;
+use crate::types::{ArchiveDict, Error};
+
+/// A difference between two archives at a key path.
+///
+/// Key paths are in the same form as the ones of [`ArchiveEditor`], like
+/// `root.Files[3].RelativePath`.
+///
+/// [`ArchiveEditor`]: crate::ArchiveEditor
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArchiveChange {
+    /// A value that only exists in the new archive.
+    Added {
+        /// The key path of the value.
+        path: String,
+        /// The added value.
+        value: ArchiveNode,
+    },
+    /// A value that only exists in the old archive.
+    Removed {
+        /// The key path of the value.
+        path: String,
+        /// The removed value.
+        value: ArchiveNode,
+    },
+    /// A value that is different between the archives.
+    ///
+    /// Objects of the same class are compared by their values, so this is
+    /// only reported for objects whose classes are different.
+    Changed {
+        /// The key path of the value.
+        path: String,
+        /// The value in the old archive.
+        old: ArchiveNode,
+        /// The value in the new archive.
+        new: ArchiveNode,
+    },
+}
+
+impl ArchiveChange {
+    /// Returns the key path of the changed value.
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Added { path, .. } | Self::Removed { path, .. } | Self::Changed { path, .. } => {
+                path
+            }
+        }
+    }
+}
+
+impl fmt::Display for ArchiveChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added { path, value } => write!(f, "+ {path}: {}", Summary(value)),
+            Self::Removed { path, value } => write!(f, "- {path}: {}", Summary(value)),
+            Self::Changed { path, old, new } => {
+                write!(f, "~ {path}: {} -> {}", Summary(old), Summary(new))
+            }
+        }
+    }
+}
+
+/// Compares the object graphs of two archives, and returns their
+/// differences.
+///
+/// Both archives are resolved before comparing, so the differences do not
+/// depend on how the objects are numbered in `$objects`. Keys of objects are
+/// compared regardless of their order, and elements of arrays are compared
+/// by their indices. A shared object is compared once, at the first key path
+/// it is reachable from.
+pub fn diff(old: &ArchiveDict, new: &ArchiveDict) -> Result<Vec<ArchiveChange>, Error> {
+    let old_objects = top_nodes(old)?;
+    let new_objects = top_nodes(new)?;
+    let mut differ = Differ {
+        old_objects: objects_by_uid(&old_objects),
+        new_objects: objects_by_uid(&new_objects),
+        compared_objects: HashSet::new(),
+        changes: vec![],
+    };
+
+    for (key, old_object) in &old_objects {
+        match new_objects.iter().find(|(k, _)| k == key) {
+            Some((_, new_object)) => differ.compare(key, old_object, new_object),
+            None => differ.changes.push(ArchiveChange::Removed {
+                path: (*key).to_owned(),
+                value: old_object.clone(),
+            }),
+        }
+    }
+    for (key, new_object) in &new_objects {
+        if !old_objects.iter().any(|(k, _)| k == key) {
+            differ.changes.push(ArchiveChange::Added {
+                path: (*key).to_owned(),
+                value: new_object.clone(),
+            });
+        }
+    }
+    Ok(differ.changes)
+}
+
+/// Collects the objects of resolved top-level objects by their UIDs, so that
+/// cyclic references can be resolved to the objects they lead back to.
+fn objects_by_uid(top_objects: &[(&str, ArchiveNode)]) -> HashMap<u64, Rc<ArchivedObject>> {
+    let mut objects = HashMap::new();
+    let mut pending: Vec<_> = top_objects.iter().map(|(_, node)| node).collect();
+    while let Some(node) = pending.pop() {
+        match node {
+            ArchiveNode::Object(object)
+                if objects.insert(object.uid, Rc::clone(object)).is_none() =>
+            {
+                pending.extend(object.fields.iter().map(|(_, value)| value));
+            }
+            ArchiveNode::Array(array) => pending.extend(array),
+            ArchiveNode::Dictionary(dict) => pending.extend(dict.iter().map(|(_, value)| value)),
+            _ => {}
+        }
+    }
+    objects
+}
+
+struct Differ {
+    old_objects: HashMap<u64, Rc<ArchivedObject>>,
+    new_objects: HashMap<u64, Rc<ArchivedObject>>,
+    // Pairs of old and new UIDs of the objects that have been compared.
+    compared_objects: HashSet<(u64, u64)>,
+    changes: Vec<ArchiveChange>,
+}
+
+impl Differ {
+    fn compare(&mut self, path: &str, old: &ArchiveNode, new: &ArchiveNode) {
+        // A cyclic reference may be cut at different places in the two
+        // archives, since their objects may be archived in different orders.
+        // Resolve it to the object it leads back to, so that it is compared
+        // like any other object.
+        let old = resolve(old, &self.old_objects);
+        let new = resolve(new, &self.new_objects);
+        let (old, new) = (old.as_ref(), new.as_ref());
+        match (old, new) {
+            (ArchiveNode::Object(old_object), ArchiveNode::Object(new_object))
+                if old_object.classes == new_object.classes =>
+            {
+                if !self
+                    .compared_objects
+                    .insert((old_object.uid, new_object.uid))
+                {
+                    return;
+                }
+                self.compare_fields(path, &old_object.fields, &new_object.fields);
+            }
+            (ArchiveNode::Dictionary(old_dict), ArchiveNode::Dictionary(new_dict)) => {
+                self.compare_fields(path, old_dict, new_dict);
+            }
+            (ArchiveNode::Array(old_array), ArchiveNode::Array(new_array)) => {
+                for (index, old_element) in old_array.iter().enumerate() {
+                    let element_path = format!("{path}[{index}]");
+                    match new_array.get(index) {
+                        Some(new_element) => self.compare(&element_path, old_element, new_element),
+                        None => self.changes.push(ArchiveChange::Removed {
+                            path: element_path,
+                            value: old_element.clone(),
+                        }),
+                    }
+                }
+                for (index, new_element) in new_array.iter().enumerate().skip(old_array.len()) {
+                    self.changes.push(ArchiveChange::Added {
+                        path: format!("{path}[{index}]"),
+                        value: new_element.clone(),
+                    });
+                }
+            }
+            (ArchiveNode::Real(old_real), ArchiveNode::Real(new_real))
+                if old_real.is_nan() && new_real.is_nan() => {}
+            _ if old != new => self.changes.push(ArchiveChange::Changed {
+                path: path.to_owned(),
+                old: old.clone(),
+                new: new.clone(),
+            }),
+            _ => {}
+        }
+    }
+    /// Compares the fields of two objects (or dictionaries) by key.
+    fn compare_fields(
+        &mut self,
+        path: &str,
+        old_fields: &[(String, ArchiveNode)],
+        new_fields: &[(String, ArchiveNode)],
+    ) {
+        for (key, old_value) in old_fields {
+            match field(new_fields, key) {
+                // Elements of collection classes are addressed like
+                // `.Files[3]` instead of `.Files.NS.objects[3]`.
+                Some(new_value @ ArchiveNode::Array(_))
+                    if key == NS_OBJECTS_KEY && matches!(old_value, ArchiveNode::Array(_)) =>
+                {
+                    self.compare(path, old_value, new_value)
+                }
+                Some(new_value) => self.compare(&format!("{path}.{key}"), old_value, new_value),
+                None => self.changes.push(ArchiveChange::Removed {
+                    path: format!("{path}.{key}"),
+                    value: old_value.clone(),
+                }),
+            }
+        }
+        for (key, new_value) in new_fields {
+            if field(old_fields, key).is_none() {
+                self.changes.push(ArchiveChange::Added {
+                    path: format!("{path}.{key}"),
+                    value: new_value.clone(),
+                });
+            }
+        }
+    }
+}
+
+fn resolve<'n>(
+    node: &'n ArchiveNode,
+    objects: &HashMap<u64, Rc<ArchivedObject>>,
+) -> Cow<'n, ArchiveNode> {
+    match node {
+        ArchiveNode::Reference(uid) => match objects.get(uid) {
+            Some(object) => Cow::Owned(ArchiveNode::Object(Rc::clone(object))),
+            None => Cow::Borrowed(node),
+        },
+        _ => Cow::Borrowed(node),
+    }
+}
+
+fn field<'n>(fields: &'n [(String, ArchiveNode)], key: &str) -> Option<&'n ArchiveNode> {
+    fields
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, value)| value)
+}
+
+/// A short description of a value, which does not expand objects.
+struct Summary<'a>(&'a ArchiveNode);
+
+impl fmt::Display for Summary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            ArchiveNode::Null => f.write_str("nil"),
+            ArchiveNode::Boolean(boolean) => write!(f, "{boolean}"),
+            ArchiveNode::Integer(integer) => write!(f, "{integer}"),
+            ArchiveNode::UnsignedInteger(integer) => write!(f, "{integer}"),
+            ArchiveNode::Real(real) => write!(f, "{real:?}"),
+            ArchiveNode::Date(date) => f.write_str(&PlistDate::from(*date).to_xml_format()),
+            ArchiveNode::String(string) => write!(f, "{string:?}"),
+            ArchiveNode::Data(data) => write!(f, "<{} bytes>", data.len()),
+            ArchiveNode::Array(array) => write!(f, "({} elements)", array.len()),
+            ArchiveNode::Dictionary(dict) => write!(f, "({} entries)", dict.len()),
+            ArchiveNode::Object(object) => write!(f, "<{}>", object.class_name()),
+            ArchiveNode::Reference(_) => f.write_str("<cyclic reference>"),
+        }
+    }
+}
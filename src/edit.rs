@@ -0,0 +1,446 @@
+use std::collections::{HashSet, VecDeque};
+
+use plist::{Uid as PlistUid, Value as PlistValue};
+
+use crate::archiver::encode_into_objects;
+use crate::object::{EncodeObject, NS_KEYS_KEY, NS_OBJECTS_KEY};
+use crate::types::{references_of, value_type_name, ArchiveDict, Error, CLASS_KEY};
+
+/// An editable view over the raw structure of a keyed archive.
+///
+/// Values are addressed by key paths like `root.Files[3].RelativePath`,
+/// which start with a key of `$top` and follow references through keys and
+/// indices, the same way errors report their paths. An index of a collection
+/// object (like `NSArray`) addresses its elements, and keys that contain
+/// dots (like `NS.string`) are matched against the keys of the object.
+///
+/// This allows patching archives without decoding them into Rust types:
+///
+/// ```
+/// # use nscoder::{ArchiveDict, ArchiveEditor, Error};
+/// # fn migrate(dict: &mut ArchiveDict) -> Result<(), Error> {
+/// let mut editor = ArchiveEditor::new(dict);
+/// for path in editor.find_objects("MBFile") {
+///     editor.set_string(&format!("{path}.Domain"), "AppDomain")?;
+/// }
+/// editor.set_i64("root.Version", 2)?;
+/// editor.collect_garbage();
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Strings are shared by every value that references them, so setting a
+/// string value never changes other values. Objects that are no longer
+/// referenced stay in the archive until [`ArchiveEditor::collect_garbage`]
+/// is called.
+pub struct ArchiveEditor<'a> {
+    dict: &'a mut ArchiveDict,
+}
+
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+// A value in the object table, which is either an object itself or a value
+// nested in an object.
+enum Location {
+    Object(usize),
+    Value { uid: usize, steps: Vec<Step> },
+}
+
+impl<'a> ArchiveEditor<'a> {
+    /// Creates an editor that edits the given archive in place.
+    pub fn new(dict: &'a mut ArchiveDict) -> Self {
+        Self { dict }
+    }
+
+    /// Returns the value at a given key path.
+    ///
+    /// References are followed, so strings and objects are returned as they
+    /// are in the object table. A `nil` value is returned as the reference
+    /// to `$null`.
+    pub fn get(&self, path: &str) -> Result<&PlistValue, Error> {
+        let value = match self.resolve(path)? {
+            Location::Object(uid) => self.object_at(uid)?,
+            Location::Value { uid, steps } => {
+                self.value_at(uid, &steps)
+                    .ok_or_else(|| Error::MissingKey {
+                        path: path.to_owned(),
+                    })?
+            }
+        };
+        Ok(self
+            .follow(value)
+            .map_or(value, |uid| &self.dict.objects[uid]))
+    }
+
+    /// Sets an integer value at a given key path.
+    pub fn set_i64(&mut self, path: &str, value: i64) -> Result<(), Error> {
+        self.store(path, PlistValue::Integer(value.into()))
+    }
+
+    /// Sets a boolean value at a given key path.
+    pub fn set_bool(&mut self, path: &str, value: bool) -> Result<(), Error> {
+        self.store(path, PlistValue::Boolean(value))
+    }
+
+    /// Sets a floating-point value at a given key path.
+    pub fn set_f64(&mut self, path: &str, value: f64) -> Result<(), Error> {
+        self.store(path, PlistValue::Real(value))
+    }
+
+    /// Sets a string value at a given key path.
+    ///
+    /// The string is added to the object table, unless an equal string
+    /// exists already.
+    pub fn set_string(&mut self, path: &str, value: &str) -> Result<(), Error> {
+        // Resolve first, so that nothing is added to invalid paths.
+        self.resolve_value(path)?;
+        let existing_uid = self
+            .dict
+            .objects
+            .iter()
+            .skip(1)
+            .position(|object| object.as_string() == Some(value));
+        let uid = match existing_uid {
+            Some(index) => index + 1,
+            None => {
+                self.dict.objects.push(PlistValue::String(value.to_owned()));
+                self.dict.objects.len() - 1
+            }
+        };
+        self.store(path, PlistValue::Uid(PlistUid::new(uid as _)))
+    }
+
+    /// Sets a buffer of bytes at a given key path.
+    pub fn set_bytes(&mut self, path: &str, value: &[u8]) -> Result<(), Error> {
+        self.store(path, PlistValue::Data(value.to_owned()))
+    }
+
+    /// Sets a `nil` value at a given key path.
+    pub fn set_null(&mut self, path: &str) -> Result<(), Error> {
+        self.store(path, PlistValue::Uid(PlistUid::new(0)))
+    }
+
+    /// Encodes an object into the archive, and sets the reference to it at a
+    /// given key path. Returns the UID of the object.
+    pub fn insert_object(
+        &mut self,
+        path: &str,
+        object: &dyn EncodeObject,
+    ) -> Result<PlistUid, Error> {
+        self.resolve_value(path)?;
+        let uid = encode_into_objects(&mut self.dict.objects, object);
+        self.store(path, PlistValue::Uid(uid))?;
+        Ok(uid)
+    }
+
+    /// Removes the value at a given key path.
+    ///
+    /// Removing an element of an array shifts the elements after it, and
+    /// removing an entry of a dictionary object (like `NSDictionary`) by its
+    /// index removes its key too. The objects referenced by the value stay in
+    /// the archive until [`ArchiveEditor::collect_garbage`] is called.
+    pub fn remove(&mut self, path: &str) -> Result<(), Error> {
+        let (uid, steps) = self.resolve_value(path)?;
+        let (last_step, steps) = steps.split_last().expect("expected a step");
+        let missing_key = || Error::MissingKey {
+            path: path.to_owned(),
+        };
+        let parent = self.value_at_mut(uid, steps).ok_or_else(missing_key)?;
+        match (last_step, parent) {
+            (Step::Key(key), PlistValue::Dictionary(dict)) => {
+                dict.remove(key).ok_or_else(missing_key)?;
+            }
+            (Step::Index(index), PlistValue::Array(array)) => {
+                array.remove(*index);
+                self.remove_dictionary_key(uid, steps, *index);
+            }
+            _ => return Err(missing_key()),
+        }
+        Ok(())
+    }
+
+    /// Returns the key paths of all objects of a given class, which are
+    /// reachable from the top-level objects.
+    ///
+    /// Every object is reported once by its shortest key path, objects are
+    /// visited in the order of the keys of `$top`.
+    pub fn find_objects(&self, class_name: &str) -> Vec<String> {
+        let mut visited = HashSet::new();
+        let mut queue: VecDeque<_> = self
+            .dict
+            .top_objects
+            .iter()
+            .map(|(key, uid)| (uid.get() as usize, key.clone()))
+            .collect();
+        let mut found = vec![];
+
+        while let Some((uid, path)) = queue.pop_front() {
+            if uid == 0 || visited.contains(&uid) {
+                continue;
+            }
+            let Some(dict) = self
+                .dict
+                .objects
+                .get(uid)
+                .and_then(PlistValue::as_dictionary)
+            else {
+                continue;
+            };
+            visited.insert(uid);
+            if self
+                .dict
+                .classes_of(dict)
+                .is_ok_and(|classes| classes[0] == class_name)
+            {
+                found.push(path.clone());
+            }
+
+            for (key, value) in dict.iter().filter(|(key, _)| *key != CLASS_KEY) {
+                match value {
+                    PlistValue::Uid(child) => {
+                        queue.push_back((child.get() as usize, format!("{path}.{key}")));
+                    }
+                    PlistValue::Array(array) => {
+                        // Elements of collection classes are addressed like
+                        // `.Files[3]` instead of `.Files.NS.objects[3]`.
+                        let prefix = if key == NS_OBJECTS_KEY {
+                            path.clone()
+                        } else {
+                            format!("{path}.{key}")
+                        };
+                        for (index, element) in array.iter().enumerate() {
+                            if let Some(child) = element.as_uid() {
+                                queue.push_back((
+                                    child.get() as usize,
+                                    format!("{prefix}[{index}]"),
+                                ));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        found
+    }
+
+    /// Removes all objects that are not reachable from the top-level
+    /// objects, and renumbers the rest. Returns the number of removed
+    /// objects.
+    ///
+    /// References to objects that do not exist are kept dangling (past the
+    /// remaining objects), so [`ArchiveDict::validate`] still reports them.
+    pub fn collect_garbage(&mut self) -> usize {
+        let objects = &self.dict.objects;
+        let mut reachable = vec![false; objects.len()];
+        let mut pending: Vec<_> = self
+            .dict
+            .top_objects
+            .values()
+            .map(|uid| uid.get())
+            .chain([0])
+            .collect();
+        while let Some(uid) = pending.pop() {
+            let uid = uid as usize;
+            if uid >= objects.len() || reachable[uid] {
+                continue;
+            }
+            reachable[uid] = true;
+            pending.extend(references_of(&objects[uid]));
+        }
+
+        let order: Vec<_> = (0..objects.len()).filter(|uid| reachable[*uid]).collect();
+        let removed_count = objects.len() - order.len();
+        self.dict.renumber_objects(&order);
+        removed_count
+    }
+
+    fn store(&mut self, path: &str, value: PlistValue) -> Result<(), Error> {
+        let (uid, steps) = self.resolve_value(path)?;
+        let (last_step, steps) = steps.split_last().expect("expected a step");
+        let parent = self
+            .value_at_mut(uid, steps)
+            .ok_or_else(|| Error::MissingKey {
+                path: path.to_owned(),
+            })?;
+        match (last_step, parent) {
+            (Step::Key(key), PlistValue::Dictionary(dict)) => {
+                dict.insert(key.clone(), value);
+            }
+            (Step::Index(index), PlistValue::Array(array)) => array[*index] = value,
+            _ => {
+                return Err(Error::MissingKey {
+                    path: path.to_owned(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the key of a removed dictionary entry, if the array the entry
+    /// was removed from is the `NS.objects` of a dictionary object.
+    fn remove_dictionary_key(&mut self, uid: usize, steps: &[Step], index: usize) {
+        let [object_steps @ .., Step::Key(key)] = steps else {
+            return;
+        };
+        if key != NS_OBJECTS_KEY {
+            return;
+        }
+        if let Some(PlistValue::Array(keys)) = self
+            .value_at_mut(uid, object_steps)
+            .and_then(PlistValue::as_dictionary_mut)
+            .and_then(|object| object.get_mut(NS_KEYS_KEY))
+        {
+            if index < keys.len() {
+                keys.remove(index);
+            }
+        }
+    }
+
+    /// Resolves a key path that addresses a value in an object, rather than
+    /// a top-level object.
+    fn resolve_value(&self, path: &str) -> Result<(usize, Vec<Step>), Error> {
+        match self.resolve(path)? {
+            Location::Value { uid, steps } => Ok((uid, steps)),
+            Location::Object(_) => Err(Error::InvalidKeyPath {
+                path: path.to_owned(),
+            }),
+        }
+    }
+
+    fn resolve(&self, path: &str) -> Result<Location, Error> {
+        let invalid_path = || Error::InvalidKeyPath {
+            path: path.to_owned(),
+        };
+        let top_key = longest_key(path, self.dict.top_objects.keys())
+            .or_else(|| path.split(['.', '[', ']']).next())
+            .filter(|key| !key.is_empty())
+            .ok_or_else(invalid_path)?;
+        let Some(uid) = self.dict.top_objects.get(top_key) else {
+            return Err(Error::MissingKey {
+                path: top_key.to_owned(),
+            });
+        };
+        let mut location = Location::Object(uid.get() as usize);
+        let mut consumed = top_key.len();
+
+        while consumed < path.len() {
+            let rest = &path[consumed..];
+            // Follow the reference (if any) to the object the step applies to.
+            let (uid, mut steps, value) = match location {
+                Location::Object(uid) => (uid, vec![], self.object_at(uid)?),
+                Location::Value { uid, steps } => {
+                    let value = self
+                        .value_at(uid, &steps)
+                        .ok_or_else(|| Error::MissingKey {
+                            path: path[..consumed].to_owned(),
+                        })?;
+                    match self.follow(value) {
+                        Some(uid) => (uid, vec![], &self.dict.objects[uid]),
+                        None => (uid, steps, value),
+                    }
+                }
+            };
+            let type_mismatch = |expected| Error::TypeMismatch {
+                path: path[..consumed].to_owned(),
+                expected,
+                actual: value_type_name(value),
+            };
+
+            if let Some(rest) = rest.strip_prefix('.') {
+                let PlistValue::Dictionary(dict) = value else {
+                    return Err(type_mismatch("object"));
+                };
+                let key = longest_key(rest, dict.keys())
+                    .or_else(|| rest.split(['.', '[', ']']).next())
+                    .filter(|key| !key.is_empty() && *key != CLASS_KEY)
+                    .ok_or_else(invalid_path)?;
+                consumed += 1 + key.len();
+                // Only the last key of a path may not exist, to be set.
+                if !dict.contains_key(key) && consumed < path.len() {
+                    return Err(Error::MissingKey {
+                        path: path[..consumed].to_owned(),
+                    });
+                }
+                steps.push(Step::Key(key.to_owned()));
+            } else if let Some(rest) = rest.strip_prefix('[') {
+                let (digits, _) = rest.split_once(']').ok_or_else(invalid_path)?;
+                let index: usize = digits.parse().map_err(|_| invalid_path())?;
+                let array = match value {
+                    PlistValue::Array(array) => array,
+                    PlistValue::Dictionary(dict) => {
+                        let Some(PlistValue::Array(array)) = dict.get(NS_OBJECTS_KEY) else {
+                            return Err(type_mismatch("array"));
+                        };
+                        steps.push(Step::Key(NS_OBJECTS_KEY.to_owned()));
+                        array
+                    }
+                    _ => return Err(type_mismatch("array")),
+                };
+                consumed += digits.len() + 2;
+                if index >= array.len() {
+                    return Err(Error::MissingKey {
+                        path: path[..consumed].to_owned(),
+                    });
+                }
+                steps.push(Step::Index(index));
+            } else {
+                return Err(invalid_path());
+            }
+            location = Location::Value { uid, steps };
+        }
+        Ok(location)
+    }
+
+    /// Returns the object with a given UID, which may be a dangling UID of
+    /// `$top` since archives are not validated when they are read.
+    fn object_at(&self, uid: usize) -> Result<&PlistValue, Error> {
+        self.dict.objects.get(uid).ok_or(Error::InvalidObject {
+            uid: uid as u64,
+            reason: "is referenced but does not exist",
+        })
+    }
+
+    /// Returns the UID of the object a value references, unless it is
+    /// `$null` or does not exist.
+    fn follow(&self, value: &PlistValue) -> Option<usize> {
+        let uid = value.as_uid()?.get() as usize;
+        (uid != 0 && uid < self.dict.objects.len()).then_some(uid)
+    }
+
+    fn value_at(&self, uid: usize, steps: &[Step]) -> Option<&PlistValue> {
+        steps
+            .iter()
+            .try_fold(self.dict.objects.get(uid)?, |value, step| match step {
+                Step::Key(key) => value.as_dictionary()?.get(key),
+                Step::Index(index) => value.as_array()?.get(*index),
+            })
+    }
+
+    fn value_at_mut(&mut self, uid: usize, steps: &[Step]) -> Option<&mut PlistValue> {
+        steps
+            .iter()
+            .try_fold(self.dict.objects.get_mut(uid)?, |value, step| match step {
+                Step::Key(key) => value.as_dictionary_mut()?.get_mut(key),
+                Step::Index(index) => value.as_array_mut()?.get_mut(*index),
+            })
+    }
+}
+
+/// Returns the longest key that a key path starts with, followed by the end
+/// of the path or another step.
+fn longest_key<'k, I>(path: &str, keys: I) -> Option<&'k str>
+where
+    I: IntoIterator<Item = &'k String>,
+{
+    keys.into_iter()
+        .map(String::as_str)
+        .filter(|key| {
+            path.strip_prefix(key)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '[']))
+        })
+        .max_by_key(|key| key.len())
+}
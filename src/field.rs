@@ -0,0 +1,162 @@
+use crate::archiver::{Decoder, Encoder};
+use crate::object::{AnyObject, Archive, ArrayRef, ObjectRef};
+
+/// A type that can be a field of a struct deriving [`Archive`].
+///
+/// The trait is implemented for integers, floating-point numbers, booleans,
+/// strings, byte buffers, [`AnyObject`] and [`ObjectRef`], plus `Option`s of
+/// them and `Vec`s of archivable types. Types that derive `Archive`
+/// implement it automatically, so they can be nested in other types.
+///
+/// `Option` fields are not encoded when they are `None`, and are decoded as
+/// `None` when their keys do not exist.
+///
+/// To use a type that implements `Archive` manually as a field, implement
+/// this trait with [`Encoder::encode_value`] and `Decoder::decode_value`.
+pub trait ArchiveField: Sized {
+    /// Encodes the field value and associates it with a given key.
+    fn encode_field(&self, archiver: &mut dyn Encoder, key: &str);
+
+    /// Decodes and returns the field value associated with a given key.
+    ///
+    /// Returns `None` if key does not exist, or the value failed to decode.
+    fn decode_field(unarchiver: &dyn Decoder, key: &str) -> Option<Self>;
+}
+
+macro_rules! impl_archive_field_for_integer {
+    ($($ty:ty),*) => {
+        $(
+            impl ArchiveField for $ty {
+                fn encode_field(&self, archiver: &mut dyn Encoder, key: &str) {
+                    archiver.encode_i64(*self as i64, key);
+                }
+
+                // Values that do not fit in the type fail to decode, rather
+                // than being truncated.
+                fn decode_field(unarchiver: &dyn Decoder, key: &str) -> Option<Self> {
+                    let value = unarchiver.try_decode_i64(key).ok()?;
+                    <$ty>::try_from(value).ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_archive_field_for_integer!(i8, i16, i32, i64, isize, u8, u16, u32);
+
+// Like `NSKeyedArchiver` does for `unsigned long long`, 64-bit unsigned
+// integers are archived as the `i64` with the same bits.
+macro_rules! impl_archive_field_for_unsigned {
+    ($($ty:ty),*) => {
+        $(
+            impl ArchiveField for $ty {
+                fn encode_field(&self, archiver: &mut dyn Encoder, key: &str) {
+                    archiver.encode_i64(*self as u64 as i64, key);
+                }
+
+                fn decode_field(unarchiver: &dyn Decoder, key: &str) -> Option<Self> {
+                    let value = unarchiver.try_decode_i64(key).ok()?;
+                    <$ty>::try_from(value as u64).ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_archive_field_for_unsigned!(u64, usize);
+
+impl ArchiveField for bool {
+    fn encode_field(&self, archiver: &mut dyn Encoder, key: &str) {
+        archiver.encode_bool(*self, key);
+    }
+
+    fn decode_field(unarchiver: &dyn Decoder, key: &str) -> Option<Self> {
+        unarchiver.try_decode_bool(key).ok()
+    }
+}
+
+impl ArchiveField for f32 {
+    fn encode_field(&self, archiver: &mut dyn Encoder, key: &str) {
+        archiver.encode_f32(*self, key);
+    }
+
+    fn decode_field(unarchiver: &dyn Decoder, key: &str) -> Option<Self> {
+        unarchiver.try_decode_f32(key).ok()
+    }
+}
+
+impl ArchiveField for f64 {
+    fn encode_field(&self, archiver: &mut dyn Encoder, key: &str) {
+        archiver.encode_f64(*self, key);
+    }
+
+    fn decode_field(unarchiver: &dyn Decoder, key: &str) -> Option<Self> {
+        unarchiver.try_decode_f64(key).ok()
+    }
+}
+
+impl ArchiveField for String {
+    fn encode_field(&self, archiver: &mut dyn Encoder, key: &str) {
+        archiver.encode_string(self, key);
+    }
+
+    fn decode_field(unarchiver: &dyn Decoder, key: &str) -> Option<Self> {
+        unarchiver.try_decode_string(key).ok()
+    }
+}
+
+impl ArchiveField for Vec<u8> {
+    fn encode_field(&self, archiver: &mut dyn Encoder, key: &str) {
+        archiver.encode_bytes(self, key);
+    }
+
+    fn decode_field(unarchiver: &dyn Decoder, key: &str) -> Option<Self> {
+        unarchiver.try_decode_bytes(key).ok()
+    }
+}
+
+impl ArchiveField for AnyObject {
+    fn encode_field(&self, archiver: &mut dyn Encoder, key: &str) {
+        archiver.encode_object(self, key);
+    }
+
+    fn decode_field(unarchiver: &dyn Decoder, key: &str) -> Option<Self> {
+        unarchiver.try_decode_object(key).ok()
+    }
+}
+
+impl ArchiveField for ObjectRef {
+    fn encode_field(&self, archiver: &mut dyn Encoder, key: &str) {
+        archiver.encode_shared_object(self, key);
+    }
+
+    fn decode_field(unarchiver: &dyn Decoder, key: &str) -> Option<Self> {
+        unarchiver.try_decode_shared_object(key).ok()
+    }
+}
+
+/// Arrays of archivable types are encoded as `NSArray` objects.
+impl<T: Archive + 'static> ArchiveField for Vec<T> {
+    fn encode_field(&self, archiver: &mut dyn Encoder, key: &str) {
+        archiver.encode_value(&ArrayRef(self), key);
+    }
+
+    fn decode_field(unarchiver: &dyn Decoder, key: &str) -> Option<Self> {
+        unarchiver.decode_array_of(key)
+    }
+}
+
+impl<T: ArchiveField> ArchiveField for Option<T> {
+    fn encode_field(&self, archiver: &mut dyn Encoder, key: &str) {
+        if let Some(value) = self {
+            value.encode_field(archiver, key);
+        }
+    }
+
+    fn decode_field(unarchiver: &dyn Decoder, key: &str) -> Option<Self> {
+        if !unarchiver.contains_key(key) {
+            return Some(None);
+        }
+        T::decode_field(unarchiver, key).map(Some)
+    }
+}
@@ -0,0 +1,592 @@
+//! A lossless JSON representation of keyed archives.
+//!
+//! Unlike the raw structure of an [`ArchiveDict`], references are resolved
+//! into nested values, so the JSON is easy to consume by tools that know
+//! nothing about keyed archives:
+//!
+//! ```json
+//! {
+//!   "$archiver": "NSKeyedArchiver",
+//!   "$version": 100000,
+//!   "$top": {
+//!     "root": {
+//!       "$class": ["RCDPerson", "NSObject"],
+//!       "$id": 1,
+//!       "FirstName": "Cyan",
+//!       "Age": 26,
+//!       "Friend": { "$ref": 1 }
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! The representation is defined as follows:
+//!
+//! - A keyed object is a JSON object with its class name followed by its
+//!   superclasses in `$class`, and its values by their keys.
+//! - An object referenced multiple times is written in full only once, with
+//!   its UID in `$id`. Other references to it are `{"$ref": <UID>}`.
+//! - A referenced string is a JSON string, and a reference to `$null` is
+//!   `null`. Other referenced values (such as `NSNumber` and `NSData` objects)
+//!   are wrapped in `{"$value": ...}`.
+//! - Booleans and integers are JSON booleans and numbers, reals are JSON
+//!   numbers with a fraction (or `{"$real": "nan"}`, `{"$real": "inf"}` and
+//!   `{"$real": "-inf"}`), data are `{"$data": "<base64>"}` and dates are
+//!   `{"$date": "<RFC 3339>"}`.
+//! - Values stored inline that are not referenced, which are rare, are
+//!   `{"$string": ...}` for strings and `{"$dict": {...}}` for dictionaries.
+//!
+//! Since equal strings are uniqued when importing (like `NSKeyedArchiver`
+//! does), and objects are numbered in the order they appear, the imported
+//! archive may differ from the original one in UIDs, but never in the object
+//! graph it represents.
+//!
+//! Arrays and objects can be nested up to 256 levels deep in the JSON, which
+//! is deeper than what `serde_json` allows by default. Deeper archives fail
+//! with [`Error::DepthLimitExceeded`] rather than overflowing the stack.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use plist::{
+    Date as PlistDate, Dictionary as PlistDictionary, Uid as PlistUid, Value as PlistValue,
+};
+use serde::Deserialize;
+use serde_json::{Map as JsonMap, Number as JsonNumber, Value as JsonValue};
+
+use crate::types::{ArchiveDict, Error, CLASS_KEY};
+
+const ARCHIVER_KEY: &str = "$archiver";
+const VERSION_KEY: &str = "$version";
+const TOP_KEY: &str = "$top";
+const ID_KEY: &str = "$id";
+const REF_KEY: &str = "$ref";
+const VALUE_KEY: &str = "$value";
+const REAL_KEY: &str = "$real";
+const DATA_KEY: &str = "$data";
+const DATE_KEY: &str = "$date";
+const STRING_KEY: &str = "$string";
+const DICT_KEY: &str = "$dict";
+
+const CLASS_NAME_KEY: &str = "$classname";
+const CLASSES_KEY: &str = "$classes";
+
+// Arrays and objects nested deeper than this are rejected, since they are
+// parsed and converted recursively.
+const MAX_DEPTH: usize = 256;
+
+/// Converts an archive to its JSON representation.
+pub fn to_value(dict: &ArchiveDict) -> Result<JsonValue, Error> {
+    let mut exporter = Exporter::new(dict);
+    for uid in dict.top_objects.values() {
+        exporter.count_references(*uid);
+    }
+
+    let mut top_objects = JsonMap::new();
+    for (key, uid) in &dict.top_objects {
+        let object = exporter.reference(*uid, &format!("{TOP_KEY}.{key}"))?;
+        top_objects.insert(key.clone(), object);
+    }
+
+    let mut value = JsonMap::new();
+    value.insert(
+        ARCHIVER_KEY.to_owned(),
+        dict.archiver_class_name.clone().into(),
+    );
+    value.insert(VERSION_KEY.to_owned(), dict.version.into());
+    value.insert(TOP_KEY.to_owned(), JsonValue::Object(top_objects));
+    Ok(JsonValue::Object(value))
+}
+
+// The error of `serde_json` is kept as a message, so that `Error` does not
+// depend on the `json` feature.
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Json(error.to_string())
+    }
+}
+
+/// Converts an archive to a pretty-printed JSON string.
+pub fn to_string(dict: &ArchiveDict) -> Result<String, Error> {
+    Ok(serde_json::to_string_pretty(&to_value(dict)?)?)
+}
+
+/// Converts the JSON representation of an archive back to an archive.
+pub fn from_value(value: &JsonValue) -> Result<ArchiveDict, Error> {
+    let malformed = |path: &str| Error::MalformedJson {
+        path: path.to_owned(),
+    };
+    let Some(value) = value.as_object() else {
+        return Err(malformed(""));
+    };
+    let Some(archiver_class_name) = value.get(ARCHIVER_KEY).and_then(|v| v.as_str()) else {
+        return Err(malformed(ARCHIVER_KEY));
+    };
+    let Some(version) = value
+        .get(VERSION_KEY)
+        .and_then(|v| v.as_u64())
+        .and_then(|v| u32::try_from(v).ok())
+    else {
+        return Err(malformed(VERSION_KEY));
+    };
+    let Some(top) = value.get(TOP_KEY).and_then(|v| v.as_object()) else {
+        return Err(malformed(TOP_KEY));
+    };
+
+    let mut importer = Importer::new();
+    let mut top_objects = BTreeMap::new();
+    for (key, object) in top {
+        let uid = importer.reference(object, &format!("{TOP_KEY}.{key}"))?;
+        top_objects.insert(key.clone(), uid);
+    }
+
+    Ok(ArchiveDict {
+        archiver_class_name: archiver_class_name.to_owned(),
+        objects: importer.objects,
+        top_objects,
+        version,
+    })
+}
+
+/// Converts the JSON representation of an archive from a string back to an
+/// archive.
+pub fn from_str(string: &str) -> Result<ArchiveDict, Error> {
+    // The recursion limit of `serde_json` is too low for object graphs, it
+    // is replaced with our own limit that is checked before parsing.
+    check_depth(string)?;
+    let mut deserializer = serde_json::Deserializer::from_str(string);
+    deserializer.disable_recursion_limit();
+    let value = JsonValue::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    from_value(&value)
+}
+
+/// Checks how deep arrays and objects are nested in a JSON string, without
+/// parsing it.
+fn check_depth(string: &str) -> Result<(), Error> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for byte in string.bytes() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'[' | b'{' => {
+                depth += 1;
+                if depth > MAX_DEPTH {
+                    return Err(Error::DepthLimitExceeded {
+                        path: TOP_KEY.to_owned(),
+                    });
+                }
+            }
+            // Unbalanced closing brackets are rejected here, since the depth
+            // cannot go below zero.
+            b']' | b'}' => match depth.checked_sub(1) {
+                Some(new_depth) => depth = new_depth,
+                None => {
+                    return Err(Error::MalformedJson {
+                        path: String::new(),
+                    })
+                }
+            },
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+struct Exporter<'a> {
+    dict: &'a ArchiveDict,
+    reference_counts: HashMap<u64, usize>,
+    exported_objects: HashSet<u64>,
+    // How deep the JSON value being exported is nested.
+    depth: usize,
+}
+
+impl<'a> Exporter<'a> {
+    fn new(dict: &'a ArchiveDict) -> Self {
+        Self {
+            dict,
+            reference_counts: HashMap::new(),
+            exported_objects: HashSet::new(),
+            // Top-level objects are nested in the archive and in `$top`.
+            depth: 2,
+        }
+    }
+
+    /// Exports a JSON array or object, which is nested one level deeper.
+    fn nested<F>(&mut self, path: &str, f: F) -> Result<JsonValue, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<JsonValue, Error>,
+    {
+        if self.depth >= MAX_DEPTH {
+            return Err(Error::DepthLimitExceeded {
+                path: path.to_owned(),
+            });
+        }
+        self.depth += 1;
+        let json = f(self);
+        self.depth -= 1;
+        json
+    }
+
+    fn object_at(&self, uid: u64) -> Result<&'a PlistValue, Error> {
+        self.dict
+            .objects
+            .get(uid as usize)
+            .ok_or(Error::InvalidObject {
+                uid,
+                reason: "is referenced but does not exist",
+            })
+    }
+
+    /// Counts the references to reachable objects, except class info.
+    fn count_references(&mut self, uid: PlistUid) {
+        // Values are visited in a loop rather than recursively, so that long
+        // chains of objects cannot overflow the stack.
+        let mut values = vec![];
+        let mut uid = Some(uid);
+        loop {
+            if let Some(uid) = uid.take() {
+                let count = self.reference_counts.entry(uid.get()).or_default();
+                *count += 1;
+                if *count == 1 {
+                    values.extend(self.dict.objects.get(uid.get() as usize));
+                }
+            }
+            let Some(value) = values.pop() else {
+                break;
+            };
+            match value {
+                PlistValue::Uid(value) => uid = Some(*value),
+                PlistValue::Array(array) => values.extend(array),
+                PlistValue::Dictionary(dict) => values.extend(
+                    dict.iter()
+                        .filter(|(key, _)| *key != CLASS_KEY)
+                        .map(|(_, value)| value),
+                ),
+                _ => (),
+            }
+        }
+    }
+
+    /// Returns the class info of a keyed object, if the value is one.
+    fn class_info_of(&self, value: &'a PlistValue) -> Option<&'a PlistDictionary> {
+        let uid = value.as_dictionary()?.get(CLASS_KEY)?.as_uid()?;
+        self.dict.objects.get(uid.get() as usize)?.as_dictionary()
+    }
+
+    fn reference(&mut self, uid: PlistUid, path: &str) -> Result<JsonValue, Error> {
+        let uid = uid.get();
+        if uid == 0 {
+            return Ok(JsonValue::Null);
+        }
+        let object = self.object_at(uid)?;
+        if let PlistValue::String(string) = object {
+            return Ok(JsonValue::String(string.clone()));
+        }
+
+        self.nested(path, |exporter| exporter.object(uid, object, path))
+    }
+
+    fn object(&mut self, uid: u64, object: &'a PlistValue, path: &str) -> Result<JsonValue, Error> {
+        let shared = self.reference_counts.get(&uid).copied().unwrap_or(0) > 1;
+        if shared && !self.exported_objects.insert(uid) {
+            return Ok(tagged(REF_KEY, uid.into()));
+        }
+
+        let mut json = JsonMap::new();
+        match (object, self.class_info_of(object)) {
+            (PlistValue::Dictionary(dict), Some(class_info)) => {
+                json.insert(CLASS_KEY.to_owned(), self.class_info(class_info, path)?);
+                if shared {
+                    json.insert(ID_KEY.to_owned(), uid.into());
+                }
+                for (key, value) in dict.iter().filter(|(key, _)| *key != CLASS_KEY) {
+                    json.insert(key.clone(), self.inline(value, &format!("{path}.{key}"))?);
+                }
+            }
+            _ => {
+                if shared {
+                    json.insert(ID_KEY.to_owned(), uid.into());
+                }
+                json.insert(VALUE_KEY.to_owned(), self.inline(object, path)?);
+            }
+        }
+        Ok(JsonValue::Object(json))
+    }
+
+    fn class_info(&mut self, class_info: &PlistDictionary, path: &str) -> Result<JsonValue, Error> {
+        let class_name = class_info.get(CLASS_NAME_KEY).and_then(|v| v.as_string());
+        let classes = class_info.get(CLASSES_KEY).and_then(|v| v.as_array());
+        if let (Some(class_name), Some(classes), 2) = (class_name, classes, class_info.len()) {
+            let classes: Option<Vec<_>> = classes.iter().map(|v| v.as_string()).collect();
+            if let Some(classes) = classes.filter(|c| c.first() == Some(&class_name)) {
+                return self.nested(path, |_| Ok(classes.into()));
+            }
+        }
+        // Keep unusual class info as is.
+        self.inline(&PlistValue::Dictionary(class_info.clone()), path)
+    }
+
+    fn inline(&mut self, value: &PlistValue, path: &str) -> Result<JsonValue, Error> {
+        let json = match value {
+            PlistValue::Uid(uid) => return self.reference(*uid, path),
+            PlistValue::Boolean(boolean) => JsonValue::Bool(*boolean),
+            PlistValue::Integer(integer) => match integer.as_signed() {
+                Some(integer) => integer.into(),
+                None => integer.as_unsigned().expect("expected an integer").into(),
+            },
+            PlistValue::Real(real) => match JsonNumber::from_f64(*real) {
+                Some(number) => JsonValue::Number(number),
+                None => {
+                    return self.nested(path, |exporter| exporter.inline_container(value, path))
+                }
+            },
+            _ => return self.nested(path, |exporter| exporter.inline_container(value, path)),
+        };
+        Ok(json)
+    }
+
+    /// Exports an inline value that is an array or an object in JSON.
+    fn inline_container(&mut self, value: &PlistValue, path: &str) -> Result<JsonValue, Error> {
+        let json = match value {
+            PlistValue::Real(real) if real.is_nan() => tagged(REAL_KEY, "nan".into()),
+            PlistValue::Real(real) if *real > 0.0 => tagged(REAL_KEY, "inf".into()),
+            PlistValue::Real(_) => tagged(REAL_KEY, "-inf".into()),
+            PlistValue::String(string) => tagged(STRING_KEY, string.clone().into()),
+            PlistValue::Data(data) => tagged(DATA_KEY, BASE64.encode(data).into()),
+            PlistValue::Date(date) => tagged(DATE_KEY, date.to_xml_format().into()),
+            // Loops are used instead of iterator adapters here and below,
+            // which take a lot more stack space per level of nesting in debug
+            // builds.
+            PlistValue::Array(array) => {
+                let mut json = Vec::with_capacity(array.len());
+                for (index, value) in array.iter().enumerate() {
+                    json.push(self.inline(value, &format!("{path}[{index}]"))?);
+                }
+                JsonValue::Array(json)
+            }
+            PlistValue::Dictionary(dict) => {
+                let json = self.nested(path, |exporter| {
+                    let mut json = JsonMap::new();
+                    for (key, value) in dict {
+                        json.insert(
+                            key.clone(),
+                            exporter.inline(value, &format!("{path}.{key}"))?,
+                        );
+                    }
+                    Ok(JsonValue::Object(json))
+                })?;
+                tagged(DICT_KEY, json)
+            }
+            _ => {
+                return Err(Error::TypeMismatch {
+                    path: path.to_owned(),
+                    expected: "property list value",
+                    actual: "unknown value",
+                })
+            }
+        };
+        Ok(json)
+    }
+}
+
+fn tagged(key: &str, value: JsonValue) -> JsonValue {
+    let mut json = JsonMap::new();
+    json.insert(key.to_owned(), value);
+    JsonValue::Object(json)
+}
+
+/// Returns the key and the value if the object is tagged.
+fn tag_of(json: &JsonMap<String, JsonValue>) -> Option<(&str, &JsonValue)> {
+    match json.iter().next() {
+        Some((key, value)) if json.len() == 1 => Some((key, value)),
+        _ => None,
+    }
+}
+
+struct Importer {
+    objects: Vec<PlistValue>,
+    strings: HashMap<String, PlistUid>,
+    class_infos: HashMap<String, PlistUid>,
+    ids: HashMap<u64, PlistUid>,
+    // How deep the JSON value being imported is nested.
+    depth: usize,
+}
+
+impl Importer {
+    fn new() -> Self {
+        Self {
+            objects: vec![PlistValue::String("$null".to_owned())],
+            strings: HashMap::new(),
+            class_infos: HashMap::new(),
+            ids: HashMap::new(),
+            // Top-level objects are nested in the archive and in `$top`.
+            depth: 2,
+        }
+    }
+
+    /// Imports a JSON array or object, which is nested one level deeper.
+    fn nested<T, F>(&mut self, path: &str, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error>,
+    {
+        if self.depth >= MAX_DEPTH {
+            return Err(Error::DepthLimitExceeded {
+                path: path.to_owned(),
+            });
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn push_object(&mut self, object: PlistValue) -> PlistUid {
+        self.objects.push(object);
+        PlistUid::new(self.objects.len() as u64 - 1)
+    }
+
+    fn reference(&mut self, json: &JsonValue, path: &str) -> Result<PlistUid, Error> {
+        let malformed = || Error::MalformedJson {
+            path: path.to_owned(),
+        };
+        let object = match json {
+            JsonValue::Null => return Ok(PlistUid::new(0)),
+            JsonValue::String(string) => {
+                if let Some(uid) = self.strings.get(string) {
+                    return Ok(*uid);
+                }
+                let uid = self.push_object(PlistValue::String(string.clone()));
+                self.strings.insert(string.clone(), uid);
+                return Ok(uid);
+            }
+            JsonValue::Object(object) => object,
+            _ => return Err(malformed()),
+        };
+        self.nested(path, |importer| importer.object(object, path))
+    }
+
+    fn object(
+        &mut self,
+        object: &JsonMap<String, JsonValue>,
+        path: &str,
+    ) -> Result<PlistUid, Error> {
+        let malformed = || Error::MalformedJson {
+            path: path.to_owned(),
+        };
+        if let Some((REF_KEY, id)) = tag_of(object) {
+            let id = id.as_u64().ok_or_else(malformed)?;
+            return self.ids.get(&id).copied().ok_or_else(malformed);
+        }
+
+        // Register the object before importing its values, so that cyclic
+        // references resolve to the object itself.
+        let uid = self.push_object(PlistValue::Boolean(false));
+        if let Some(id) = object.get(ID_KEY) {
+            let id = id.as_u64().ok_or_else(malformed)?;
+            self.ids.insert(id, uid);
+        }
+
+        let value = if let Some(class) = object.get(CLASS_KEY) {
+            let mut dict = PlistDictionary::new();
+            for (key, value) in object {
+                if key != CLASS_KEY && key != ID_KEY {
+                    let value = self.inline(value, &format!("{path}.{key}"))?;
+                    dict.insert(key.clone(), value);
+                }
+            }
+            let class = self.class_info(class, &format!("{path}.{CLASS_KEY}"))?;
+            dict.insert(CLASS_KEY.to_owned(), PlistValue::Uid(class));
+            PlistValue::Dictionary(dict)
+        } else {
+            let value = object.get(VALUE_KEY).ok_or_else(malformed)?;
+            self.inline(value, path)?
+        };
+        self.objects[uid.get() as usize] = value;
+        Ok(uid)
+    }
+
+    fn class_info(&mut self, json: &JsonValue, path: &str) -> Result<PlistUid, Error> {
+        let key = json.to_string();
+        if let Some(uid) = self.class_infos.get(&key) {
+            return Ok(*uid);
+        }
+
+        let class_info = match json.as_array() {
+            Some(classes) => {
+                let classes: Option<Vec<_>> = classes.iter().map(|v| v.as_str()).collect();
+                let Some(class_name) = classes.as_ref().and_then(|c| c.first()) else {
+                    return Err(Error::MalformedJson {
+                        path: path.to_owned(),
+                    });
+                };
+                let mut class_info = PlistDictionary::new();
+                class_info.insert(CLASS_NAME_KEY.to_owned(), (*class_name).into());
+                let classes = classes.iter().flatten().map(|c| (*c).into()).collect();
+                class_info.insert(CLASSES_KEY.to_owned(), PlistValue::Array(classes));
+                PlistValue::Dictionary(class_info)
+            }
+            None => self.inline(json, path)?,
+        };
+        let uid = self.push_object(class_info);
+        self.class_infos.insert(key, uid);
+        Ok(uid)
+    }
+
+    fn inline(&mut self, json: &JsonValue, path: &str) -> Result<PlistValue, Error> {
+        let malformed = || Error::MalformedJson {
+            path: path.to_owned(),
+        };
+        let value = match json {
+            JsonValue::Bool(boolean) => PlistValue::Boolean(*boolean),
+            JsonValue::Number(number) => match (number.as_i64(), number.as_u64()) {
+                (Some(integer), _) => PlistValue::Integer(integer.into()),
+                (_, Some(integer)) => PlistValue::Integer(integer.into()),
+                _ => PlistValue::Real(number.as_f64().ok_or_else(malformed)?),
+            },
+            JsonValue::Array(array) => self.nested(path, |importer| {
+                let mut values = Vec::with_capacity(array.len());
+                for (index, json) in array.iter().enumerate() {
+                    values.push(importer.inline(json, &format!("{path}[{index}]"))?);
+                }
+                Ok(PlistValue::Array(values))
+            })?,
+            JsonValue::Object(object) => match tag_of(object) {
+                Some((REAL_KEY, real)) => match real.as_str() {
+                    Some("nan") => PlistValue::Real(f64::NAN),
+                    Some("inf") => PlistValue::Real(f64::INFINITY),
+                    Some("-inf") => PlistValue::Real(f64::NEG_INFINITY),
+                    _ => return Err(malformed()),
+                },
+                Some((STRING_KEY, JsonValue::String(string))) => string.clone().into(),
+                Some((DATA_KEY, JsonValue::String(data))) => {
+                    PlistValue::Data(BASE64.decode(data).map_err(|_| malformed())?)
+                }
+                Some((DATE_KEY, JsonValue::String(date))) => {
+                    PlistValue::Date(PlistDate::from_xml_format(date).map_err(|_| malformed())?)
+                }
+                Some((DICT_KEY, JsonValue::Object(object))) => self.nested(path, |importer| {
+                    let mut dict = PlistDictionary::new();
+                    for (key, json) in object {
+                        let value = importer.inline(json, &format!("{path}.{key}"))?;
+                        dict.insert(key.clone(), value);
+                    }
+                    Ok(PlistValue::Dictionary(dict))
+                })?,
+                _ => PlistValue::Uid(self.reference(json, path)?),
+            },
+            JsonValue::Null | JsonValue::String(_) => PlistValue::Uid(self.reference(json, path)?),
+        };
+        Ok(value)
+    }
+}
@@ -3,15 +3,47 @@
 #![deny(missing_docs)]
 
 mod archiver;
+mod decoding;
+mod diff;
+mod edit;
+mod field;
+#[cfg(feature = "json")]
+pub mod json;
+mod nib;
+mod node;
 mod object;
+pub mod serde;
+pub mod typedstream;
 mod types;
+mod unknown;
 
 pub use self::{
-    archiver::{from_bytes, from_file, from_plist_value, to_bytes, Decoder, Encoder},
-    object::{AnyObject, Archive, RootObject, TypeRegistry},
-    types::Error,
+    archiver::{
+        from_bytes, from_bytes_top_objects, from_bytes_with_options, from_file,
+        from_file_top_objects, from_file_with_options, from_plist_value,
+        from_plist_value_top_objects, from_plist_value_with_options, to_bytes,
+        to_bytes_top_objects, to_bytes_with_options, to_file, to_file_with_options, to_plist_value,
+        to_plist_value_with_options, to_writer, to_writer_top_objects, to_writer_with_options,
+        ArchiveOptions, Decoder, Encoder, UnarchiveOptions,
+    },
+    diff::{diff, ArchiveChange},
+    edit::ArchiveEditor,
+    field::ArchiveField,
+    nib::{from_nib_bytes, from_nib_bytes_with_options, from_nib_file, from_nib_file_with_options},
+    node::{ArchiveNode, ArchivedObject},
+    object::{
+        AnyObject, Archive, EncodeObject, NSArray, NSDictionary, NSMutableArray,
+        NSMutableDictionary, NSMutableOrderedSet, NSMutableSet, NSOrderedSet, NSSet, ObjectRef,
+        RootObject, TypeRegistry, WeakObjectRef,
+    },
+    types::{ArchiveDict, Error, OutputFormat},
+    unknown::UnknownFields,
 };
 
+// Optionally exporting the derive macro of `Archive` trait.
+#[cfg(feature = "derive")]
+pub use nscoder_derive::Archive;
+
 // Optionally exporting `plist` crate.
 #[cfg(feature = "export_plist")]
 pub use plist;
@@ -0,0 +1,558 @@
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::archiver::{Decoder, UnarchiveOptions};
+use crate::decoding::DecodingState;
+use crate::object::{
+    AnyObject, NSArray, NSDictionary, NSMutableArray, NSMutableDictionary, NSMutableSet, NSSet,
+    ObjectRef, TypeRegistry, WeakObjectRef,
+};
+use crate::types::Error;
+
+/// Decodes a compiled NIB file in the `NIBArchive` format, and returns its
+/// root object.
+pub fn from_nib_file<P: AsRef<Path>>(path: P, registry: &TypeRegistry) -> Result<AnyObject, Error> {
+    from_nib_file_with_options(path, registry, &UnarchiveOptions::default())
+}
+
+/// Like [`from_nib_file`], but decodes the NIB file with the given options.
+pub fn from_nib_file_with_options<P: AsRef<Path>>(
+    path: P,
+    registry: &TypeRegistry,
+    options: &UnarchiveOptions,
+) -> Result<AnyObject, Error> {
+    let bytes = options.read_file(path.as_ref())?;
+    from_nib_bytes_with_options(&bytes, registry, options)
+}
+
+/// Decodes a compiled NIB file in the `NIBArchive` format from a byte slice,
+/// and returns its root object.
+///
+/// `NIBArchive` is a keyed format, objects are decoded with the same
+/// [`Archive`](crate::Archive) implementations and [`TypeRegistry`] as keyed
+/// archives. Strings, data and collections are decoded as built-in types
+/// ([`String`], `Vec<u8>`, [`NSArray`], [`NSSet`], [`NSDictionary`] and their
+/// mutable subclasses), since their NIB forms differ from their keyed forms.
+///
+/// Unlike keyed archives, classes in NIB files have no superclass lists, so
+/// superclass fallback is not available.
+pub fn from_nib_bytes(bytes: &[u8], registry: &TypeRegistry) -> Result<AnyObject, Error> {
+    from_nib_bytes_with_options(bytes, registry, &UnarchiveOptions::default())
+}
+
+/// Like [`from_nib_bytes`], but decodes the NIB file with the given options.
+///
+/// The limits of the options apply like they do to keyed archives, which
+/// is useful to inspect untrusted NIB files. The root key is not used.
+pub fn from_nib_bytes_with_options(
+    bytes: &[u8],
+    registry: &TypeRegistry,
+    options: &UnarchiveOptions,
+) -> Result<AnyObject, Error> {
+    options.check_size(bytes.len())?;
+    let archive = Parser::new(bytes).parse()?;
+    let unarchiver = Unarchiver::new(archive, registry, options);
+    unarchiver.unarchive_root_object()
+}
+
+const MAGIC: &[u8] = b"NIBArchive";
+
+// Elements of collections are stored with this key repeatedly.
+const EMPTY_KEY: &str = "UINibEncoderEmptyKey";
+
+#[derive(Debug)]
+enum NibValue {
+    Integer(i64),
+    Boolean(bool),
+    Real(f64),
+    Data(Vec<u8>),
+    Null,
+    /// An object with its index in `NibArchive::objects`.
+    Object(usize),
+}
+
+impl NibValue {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Self::Integer(_) => "integer",
+            Self::Boolean(_) => "boolean",
+            Self::Real(_) => "real",
+            Self::Data(_) => "data",
+            Self::Null => "nil",
+            Self::Object(_) => "object",
+        }
+    }
+}
+
+struct NibObject {
+    class: usize,
+    values: Range<usize>,
+}
+
+struct NibArchive {
+    objects: Vec<NibObject>,
+    keys: Vec<String>,
+    // Values with the indices of their keys.
+    values: Vec<(usize, NibValue)>,
+    class_names: Vec<String>,
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    fn parse(mut self) -> Result<NibArchive, Error> {
+        if self.read_exact(MAGIC.len())? != MAGIC {
+            return Err(Error::UnsupportedArchiver(
+                String::from_utf8_lossy(&self.bytes[..MAGIC.len()]).into_owned(),
+            ));
+        }
+        // The format version and the coder version are not used.
+        self.read_u32()?;
+        self.read_u32()?;
+
+        let mut sections = [(0, 0); 4];
+        for section in &mut sections {
+            *section = (self.read_u32()? as usize, self.read_u32()? as usize);
+        }
+        let [(object_count, objects_offset), (key_count, keys_offset), (value_count, values_offset), (class_count, classes_offset)] =
+            sections;
+
+        self.offset = objects_offset;
+        let objects = (0..object_count)
+            .map(|_| {
+                let class = self.read_varint()?;
+                let start = self.read_varint()?;
+                let count = self.read_varint()?;
+                let end = start.checked_add(count).ok_or_else(|| self.malformed())?;
+                if class >= class_count || end > value_count {
+                    return Err(self.malformed());
+                }
+                Ok(NibObject {
+                    class,
+                    values: start..end,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        self.offset = keys_offset;
+        let keys = (0..key_count)
+            .map(|_| {
+                let len = self.read_varint()?;
+                self.read_string(len)
+            })
+            .collect::<Result<_, _>>()?;
+
+        self.offset = values_offset;
+        let values = (0..value_count)
+            .map(|_| {
+                let key = self.read_varint()?;
+                if key >= key_count {
+                    return Err(self.malformed());
+                }
+                let value = self.read_value()?;
+                if matches!(value, NibValue::Object(index) if index >= object_count) {
+                    return Err(self.malformed());
+                }
+                Ok((key, value))
+            })
+            .collect::<Result<_, _>>()?;
+
+        self.offset = classes_offset;
+        let class_names = (0..class_count)
+            .map(|_| {
+                let len = self.read_varint()?;
+                // Fallback classes are not used.
+                let extra_count = self.read_varint()?;
+                self.read_exact(extra_count.checked_mul(4).ok_or_else(|| self.malformed())?)?;
+                let name = self.read_string(len)?;
+                Ok::<_, Error>(name.trim_end_matches('\0').to_owned())
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(NibArchive {
+            objects,
+            keys,
+            values,
+            class_names,
+        })
+    }
+
+    fn malformed(&self) -> Error {
+        Error::MalformedNibArchive(self.offset)
+    }
+
+    fn read_exact(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .ok_or_else(|| self.malformed())?;
+        let Some(bytes) = self.bytes.get(self.offset..end) else {
+            return Err(self.malformed());
+        };
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.read_exact(N)?);
+        Ok(array)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    /// Reads a variable-length integer, whose last byte has the high bit set.
+    fn read_varint(&mut self) -> Result<usize, Error> {
+        let mut integer = 0usize;
+        for shift in (0..usize::BITS).step_by(7) {
+            let byte = self.read_exact(1)?[0];
+            integer |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 != 0 {
+                return Ok(integer);
+            }
+        }
+        Err(self.malformed())
+    }
+
+    fn read_string(&mut self, len: usize) -> Result<String, Error> {
+        let bytes = self.read_exact(len)?;
+        String::from_utf8(bytes.to_owned()).map_err(|_| self.malformed())
+    }
+
+    fn read_value(&mut self) -> Result<NibValue, Error> {
+        let value = match self.read_exact(1)?[0] {
+            0 => NibValue::Integer(i8::from_le_bytes(self.read_array()?).into()),
+            1 => NibValue::Integer(i16::from_le_bytes(self.read_array()?).into()),
+            2 => NibValue::Integer(i32::from_le_bytes(self.read_array()?).into()),
+            3 => NibValue::Integer(i64::from_le_bytes(self.read_array()?)),
+            4 => NibValue::Boolean(false),
+            5 => NibValue::Boolean(true),
+            6 => NibValue::Real(f32::from_le_bytes(self.read_array()?).into()),
+            7 => NibValue::Real(f64::from_le_bytes(self.read_array()?)),
+            8 => {
+                let len = self.read_varint()?;
+                NibValue::Data(self.read_exact(len)?.to_owned())
+            }
+            9 => NibValue::Null,
+            10 => NibValue::Object(self.read_u32()? as usize),
+            _ => return Err(self.malformed()),
+        };
+        Ok(value)
+    }
+}
+
+struct Unarchiver<'t> {
+    archive: NibArchive,
+    state: DecodingState<'t>,
+}
+
+impl<'t> Unarchiver<'t> {
+    fn new(archive: NibArchive, registry: &'t TypeRegistry, options: &'t UnarchiveOptions) -> Self {
+        Self {
+            archive,
+            state: DecodingState::new(registry, options),
+        }
+    }
+
+    fn unarchive_root_object(&self) -> Result<AnyObject, Error> {
+        if self.archive.objects.is_empty() {
+            return Err(Error::NoRootObject);
+        }
+        self.state
+            .decode_top_object("root", || self.decode_object_at(0))
+    }
+
+    fn decode_object_at(&self, index: usize) -> Result<AnyObject, Error> {
+        self.state.count_object()?;
+        self.state
+            .decode_object(index as u64, || self.decode_active_object())
+    }
+
+    fn decode_shared_object_at(&self, index: usize) -> Result<ObjectRef, Error> {
+        // Like in keyed archives, plain values have no identity to keep.
+        if self.is_plain_value(index) {
+            return self.decode_object_at(index).map(Rc::new);
+        }
+        self.state
+            .decode_shared_object(index as u64, || self.decode_object_at(index))
+    }
+
+    fn decode_weak_object_at(&self, index: usize) -> Result<WeakObjectRef, Error> {
+        if self.is_plain_value(index) {
+            return self.decode_object_at(index).map(|_| WeakObjectRef::new());
+        }
+        self.state
+            .decode_weak_object(index as u64, || self.decode_object_at(index))
+    }
+
+    fn is_plain_value(&self, index: usize) -> bool {
+        self.archive.objects.get(index).is_some_and(|object| {
+            matches!(
+                self.archive.class_names[object.class].as_str(),
+                "NSString" | "NSMutableString" | "NSData" | "NSMutableData"
+            )
+        })
+    }
+
+    /// Returns the values of the active object with their keys.
+    fn active_values(&self) -> impl Iterator<Item = (&str, &NibValue)> {
+        let index = self.state.active_object() as usize;
+        let range = self.archive.objects[index].values.clone();
+        self.archive.values[range]
+            .iter()
+            .map(|(key, value)| (self.archive.keys[*key].as_str(), value))
+    }
+
+    fn decode_active_object(&self) -> Result<AnyObject, Error> {
+        let index = self.state.active_object() as usize;
+        let class_name = self.archive.class_names[self.archive.objects[index].class].as_str();
+
+        self.state.check_allowed_class(class_name)?;
+        if let Some(object) = self.decode_builtin_object(class_name) {
+            return self.state.record(object);
+        }
+        self.state.decode_registered_object(&[class_name], self)
+    }
+
+    /// Decodes the active object if it is of a built-in class, whose NIB
+    /// form differs from its keyed form.
+    fn decode_builtin_object(&self, class_name: &str) -> Option<Result<AnyObject, Error>> {
+        let object = match class_name {
+            "NSString" | "NSMutableString" => self
+                .try_decode_bytes("NS.bytes")
+                .and_then(|bytes| {
+                    String::from_utf8(bytes).map_err(|_| Error::TypeMismatch {
+                        path: self.state.key_path("NS.bytes"),
+                        expected: "string",
+                        actual: "data",
+                    })
+                })
+                .map(AnyObject::erasing),
+            "NSData" | "NSMutableData" => self.try_decode_bytes("NS.bytes").map(AnyObject::erasing),
+            "NSArray" => self
+                .elements()
+                .map(|objects| AnyObject::erasing(NSArray { objects })),
+            "NSMutableArray" => self
+                .elements()
+                .map(|objects| AnyObject::erasing(NSMutableArray(NSArray { objects }))),
+            "NSSet" => self
+                .elements()
+                .map(|objects| AnyObject::erasing(NSSet { objects })),
+            "NSMutableSet" => self
+                .elements()
+                .map(|objects| AnyObject::erasing(NSMutableSet(NSSet { objects }))),
+            "NSDictionary" => self.dictionary().map(AnyObject::erasing),
+            "NSMutableDictionary" => self
+                .dictionary()
+                .map(|dictionary| AnyObject::erasing(NSMutableDictionary(dictionary))),
+            _ => return None,
+        };
+        Some(object)
+    }
+
+    fn elements(&self) -> Result<Vec<ObjectRef>, Error> {
+        self.try_decode_shared_objects(EMPTY_KEY)
+    }
+
+    /// Decodes the entries of a dictionary, whose keys and objects are
+    /// stored alternately.
+    fn dictionary(&self) -> Result<NSDictionary, Error> {
+        let elements = self.elements()?;
+        if elements.len() % 2 != 0 {
+            return Err(Error::MalformedObject);
+        }
+        let mut dictionary = NSDictionary::new();
+        let mut elements = elements.into_iter();
+        while let (Some(key), Some(object)) = (elements.next(), elements.next()) {
+            dictionary.insert_shared(key, object);
+        }
+        Ok(dictionary)
+    }
+
+    /// Decodes all objects associated with a given key, each with the given
+    /// function.
+    fn decode_elements<T, F>(&self, key: &str, decode_f: F) -> Result<Vec<T>, Error>
+    where
+        F: Fn(usize) -> Result<T, Error>,
+    {
+        // Elements of collection classes are addressed like `.Items[3]`.
+        let prefix = if key == EMPTY_KEY {
+            String::new()
+        } else {
+            format!(".{key}")
+        };
+        let decoded_objects = self
+            .active_values()
+            .filter(|(k, _)| *k == key)
+            .enumerate()
+            .map(|(index, (_, value))| {
+                self.state
+                    .with_path_segment(format!("{prefix}[{index}]"), || match value {
+                        NibValue::Object(object) => decode_f(*object),
+                        value => Err(Error::TypeMismatch {
+                            path: self.state.path(),
+                            expected: "object",
+                            actual: value.type_name(),
+                        }),
+                    })
+            })
+            .collect();
+        self.state.record(decoded_objects)
+    }
+
+    fn value_for_key(&self, key: &str) -> Result<&NibValue, Error> {
+        self.active_values()
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| value)
+            .ok_or_else(|| Error::MissingKey {
+                path: self.state.key_path(key),
+            })
+    }
+
+    fn object_for_key(&self, key: &str) -> Result<usize, Error> {
+        match self.value_for_key(key)? {
+            NibValue::Object(index) => Ok(*index),
+            value => Err(self.type_mismatch(key, "object", value)),
+        }
+    }
+
+    fn type_mismatch(&self, key: &str, expected: &'static str, actual: &NibValue) -> Error {
+        Error::TypeMismatch {
+            path: self.state.key_path(key),
+            expected,
+            actual: actual.type_name(),
+        }
+    }
+}
+
+impl<'t> Decoder for Unarchiver<'t> {
+    fn contains_key(&self, key: &str) -> bool {
+        self.active_values().any(|(k, _)| k == key)
+    }
+
+    fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = vec![];
+        for (key, _) in self.active_values() {
+            if !keys.iter().any(|k| k == key) {
+                keys.push(key.to_owned());
+            }
+        }
+        keys
+    }
+
+    fn try_decode_i64(&self, key: &str) -> Result<i64, Error> {
+        self.state
+            .record(self.value_for_key(key).and_then(|value| match value {
+                NibValue::Integer(integer) => Ok(*integer),
+                value => Err(self.type_mismatch(key, "integer", value)),
+            }))
+    }
+
+    fn try_decode_bool(&self, key: &str) -> Result<bool, Error> {
+        self.state
+            .record(self.value_for_key(key).and_then(|value| match value {
+                NibValue::Boolean(boolean) => Ok(*boolean),
+                value => Err(self.type_mismatch(key, "boolean", value)),
+            }))
+    }
+
+    fn try_decode_f64(&self, key: &str) -> Result<f64, Error> {
+        self.state
+            .record(self.value_for_key(key).and_then(|value| match value {
+                NibValue::Real(real) => Ok(*real),
+                NibValue::Integer(integer) => Ok(*integer as f64),
+                value => Err(self.type_mismatch(key, "real", value)),
+            }))
+    }
+
+    fn try_decode_string(&self, key: &str) -> Result<String, Error> {
+        // Strings are always archived as `NSString` objects.
+        let string = self.object_for_key(key).and_then(|index| {
+            let object = self
+                .state
+                .with_path_segment(format!(".{key}"), || self.decode_object_at(index))?;
+            match object.downcast::<String>() {
+                Ok(string) => Ok(*string),
+                Err(_) => Err(self.type_mismatch(key, "string", &NibValue::Object(index))),
+            }
+        });
+        self.state.record(string)
+    }
+
+    fn try_decode_bytes(&self, key: &str) -> Result<Vec<u8>, Error> {
+        let bytes = self.value_for_key(key).and_then(|value| match value {
+            NibValue::Data(data) => {
+                self.state
+                    .check_length(data.len(), || self.state.key_path(key))?;
+                Ok(data.clone())
+            }
+            // Data can also be archived as `NSData` objects.
+            NibValue::Object(index) => {
+                let object = self
+                    .state
+                    .with_path_segment(format!(".{key}"), || self.decode_object_at(*index))?;
+                match object.downcast::<Vec<u8>>() {
+                    Ok(data) => Ok(*data),
+                    Err(_) => Err(self.type_mismatch(key, "data", value)),
+                }
+            }
+            value => Err(self.type_mismatch(key, "data", value)),
+        });
+        self.state.record(bytes)
+    }
+
+    fn try_decode_object(&self, key: &str) -> Result<AnyObject, Error> {
+        self.state
+            .record(self.object_for_key(key).and_then(|index| {
+                self.state
+                    .with_path_segment(format!(".{key}"), || self.decode_object_at(index))
+            }))
+    }
+
+    fn try_decode_object_of_classes(
+        &self,
+        classes: &[&str],
+        key: &str,
+    ) -> Result<AnyObject, Error> {
+        self.state
+            .with_allowed_classes(classes, || self.try_decode_object(key))
+    }
+
+    fn try_decode_shared_object(&self, key: &str) -> Result<ObjectRef, Error> {
+        self.state
+            .record(self.object_for_key(key).and_then(|index| {
+                self.state
+                    .with_path_segment(format!(".{key}"), || self.decode_shared_object_at(index))
+            }))
+    }
+
+    fn try_decode_weak_object(&self, key: &str) -> Result<WeakObjectRef, Error> {
+        self.state
+            .record(self.object_for_key(key).and_then(|index| {
+                self.state
+                    .with_path_segment(format!(".{key}"), || self.decode_weak_object_at(index))
+            }))
+    }
+
+    /// Decodes all objects associated with a given key, since keys can be
+    /// repeated in NIB files.
+    fn try_decode_objects(&self, key: &str) -> Result<Vec<AnyObject>, Error> {
+        self.decode_elements(key, |index| self.decode_object_at(index))
+    }
+
+    /// Decodes all shared objects associated with a given key, like
+    /// [`Decoder::try_decode_objects`].
+    fn try_decode_shared_objects(&self, key: &str) -> Result<Vec<ObjectRef>, Error> {
+        self.decode_elements(key, |index| self.decode_shared_object_at(index))
+    }
+}
@@ -0,0 +1,407 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use plist::{Dictionary as PlistDictionary, Uid as PlistUid, Value as PlistValue};
+
+use crate::archiver::UnarchiveOptions;
+use crate::types::{classes_of_class_info, ArchiveDict, Error, CLASS_KEY};
+
+// Values nested deeper than this are rejected, since they are resolved
+// recursively.
+const MAX_DEPTH: usize = 512;
+
+/// A value in an untyped object graph of a keyed archive.
+///
+/// Untyped object graphs can be decoded from any keyed archive without
+/// registering types, which is useful to inspect third-party archives.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArchiveNode {
+    /// The `nil` value, archived as `$null`.
+    Null,
+    /// A boolean value.
+    Boolean(bool),
+    /// An integer value.
+    Integer(i64),
+    /// An unsigned integer value that does not fit in an `i64`.
+    UnsignedInteger(u64),
+    /// A floating-point value.
+    Real(f64),
+    /// A date value.
+    Date(SystemTime),
+    /// A string value.
+    String(String),
+    /// A buffer of bytes.
+    Data(Vec<u8>),
+    /// A list of values, typically the elements of a collection object
+    /// (such as the `NS.objects` key of `NSArray`).
+    Array(Vec<ArchiveNode>),
+    /// A dictionary that is not a keyed object, with its values in the
+    /// order they were archived.
+    ///
+    /// `NSKeyedArchiver` never writes these, but other archivers may store
+    /// dictionaries inline in objects, or reference dictionaries without a
+    /// class.
+    Dictionary(Vec<(String, ArchiveNode)>),
+    /// A keyed object.
+    ///
+    /// Objects are shared, all references to the same archived object
+    /// resolve to the same [`ArchivedObject`]. A reference to a class info
+    /// is resolved as an object too, whose classes are the ones it describes
+    /// and whose fields are its raw values (like `$classname`).
+    Object(Rc<ArchivedObject>),
+    /// A reference to an object that is still being decoded, with its UID.
+    ///
+    /// Since object graphs are represented as trees, references that form
+    /// cycles cannot be resolved, they are preserved as this variant instead.
+    Reference(u64),
+}
+
+impl ArchiveNode {
+    /// Decodes the untyped object graph of an archive from a file, and
+    /// returns its root object.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_file_with_options(path, &UnarchiveOptions::default())
+    }
+
+    /// Like [`ArchiveNode::from_file`], but decodes the object graph with
+    /// the given options.
+    pub fn from_file_with_options<P: AsRef<Path>>(
+        path: P,
+        options: &UnarchiveOptions,
+    ) -> Result<Self, Error> {
+        let bytes = options.read_file(path.as_ref())?;
+        Self::from_bytes_with_options(&bytes, options)
+    }
+
+    /// Decodes the untyped object graph of an archive from a byte slice, and
+    /// returns its root object.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Self::from_bytes_with_options(bytes, &UnarchiveOptions::default())
+    }
+
+    /// Like [`ArchiveNode::from_bytes`], but decodes the object graph with
+    /// the given options.
+    ///
+    /// The limits of the options apply like they do to typed object graphs.
+    pub fn from_bytes_with_options(
+        bytes: &[u8],
+        options: &UnarchiveOptions,
+    ) -> Result<Self, Error> {
+        options.check_size(bytes.len())?;
+        let dict = ArchiveDict::from_bytes(bytes)?;
+        Self::from_archive_dict_with_options(&dict, options)
+    }
+
+    /// Decodes the untyped object graph of an archive from a [`plist::Value`],
+    /// and returns its root object.
+    pub fn from_plist_value(value: &PlistValue) -> Result<Self, Error> {
+        let dict = ArchiveDict::from_plist_value(value)?;
+        Self::from_archive_dict(&dict)
+    }
+
+    /// Resolves the root object of an archive.
+    pub fn from_archive_dict(dict: &ArchiveDict) -> Result<Self, Error> {
+        Self::from_archive_dict_with_options(dict, &UnarchiveOptions::default())
+    }
+
+    /// Like [`ArchiveNode::from_archive_dict`], but resolves the object
+    /// graph with the given options.
+    pub fn from_archive_dict_with_options(
+        dict: &ArchiveDict,
+        options: &UnarchiveOptions,
+    ) -> Result<Self, Error> {
+        let root_object = dict.top_object(&options.root_key)?;
+        GraphBuilder::new(dict, options).top_node_at(&options.root_key, root_object)
+    }
+
+    /// Returns the string if the value is a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer if the value is an integer that fits in an `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Integer(integer) => Some(*integer),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer if the value is a non-negative integer.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Self::Integer(integer) => u64::try_from(*integer).ok(),
+            Self::UnsignedInteger(integer) => Some(*integer),
+            _ => None,
+        }
+    }
+
+    /// Returns the object if the value is a keyed object.
+    pub fn as_object(&self) -> Option<&ArchivedObject> {
+        match self {
+            Self::Object(object) => Some(object),
+            _ => None,
+        }
+    }
+
+    /// Returns the list of values if the value is an array.
+    pub fn as_array(&self) -> Option<&[ArchiveNode]> {
+        match self {
+            Self::Array(array) => Some(array),
+            _ => None,
+        }
+    }
+}
+
+/// A keyed object in an untyped object graph.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArchivedObject {
+    /// The UID of the object in the archive.
+    pub uid: u64,
+    /// The class name of the object, followed by its superclasses, as
+    /// stored in `$classes`.
+    pub classes: Vec<String>,
+    /// The values of the object in the order they were archived, excluding
+    /// the class reference.
+    pub fields: Vec<(String, ArchiveNode)>,
+}
+
+impl ArchivedObject {
+    /// Returns the class name of the object.
+    pub fn class_name(&self) -> &str {
+        &self.classes[0]
+    }
+
+    /// Returns the value associated with a given key.
+    pub fn get(&self, key: &str) -> Option<&ArchiveNode> {
+        self.fields.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+}
+
+/// Resolves all top-level objects of an archive in the order of their keys.
+/// Objects are shared between them, like in a single object graph.
+pub(crate) fn top_nodes(dict: &ArchiveDict) -> Result<Vec<(&str, ArchiveNode)>, Error> {
+    dict.validate_archiver()?;
+    let options = UnarchiveOptions::default();
+    let mut builder = GraphBuilder::new(dict, &options);
+    dict.top_objects
+        .iter()
+        .map(|(key, uid)| Ok((key.as_str(), builder.top_node_at(key, *uid)?)))
+        .collect()
+}
+
+struct GraphBuilder<'a> {
+    dict: &'a ArchiveDict,
+    options: &'a UnarchiveOptions,
+    objects: HashMap<u64, Rc<ArchivedObject>>,
+    building_objects: HashSet<u64>,
+    // Segments of the path to the value being resolved, like `root`,
+    // `.Files` and `[3]`.
+    path: Vec<String>,
+    // The number of values that have been resolved so far, shared objects
+    // are counted once.
+    object_count: usize,
+}
+
+impl<'a> GraphBuilder<'a> {
+    fn new(dict: &'a ArchiveDict, options: &'a UnarchiveOptions) -> Self {
+        Self {
+            dict,
+            options,
+            objects: HashMap::new(),
+            building_objects: HashSet::new(),
+            path: vec![],
+            object_count: 0,
+        }
+    }
+
+    fn top_node_at(&mut self, key: &str, uid: PlistUid) -> Result<ArchiveNode, Error> {
+        self.with_path_segment(key.to_owned(), |builder| builder.node_at(uid))
+    }
+
+    fn with_path_segment<T, F>(&mut self, segment: String, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error>,
+    {
+        self.path.push(segment);
+        let result = if self.path.len() > MAX_DEPTH {
+            Err(Error::DepthLimitExceeded {
+                path: self.path.concat(),
+            })
+        } else {
+            f(self)
+        };
+        self.path.pop();
+        result
+    }
+
+    fn node_at(&mut self, uid: PlistUid) -> Result<ArchiveNode, Error> {
+        let index = uid.get();
+        if index == 0 {
+            return Ok(ArchiveNode::Null);
+        }
+        if let Some(object) = self.objects.get(&index) {
+            return Ok(ArchiveNode::Object(Rc::clone(object)));
+        }
+        if self.building_objects.contains(&index) {
+            return Ok(ArchiveNode::Reference(index));
+        }
+
+        let dict = self.dict;
+        let Some(value) = dict.objects.get(index as usize) else {
+            return Err(Error::MalformedObject);
+        };
+        self.object_count += 1;
+        if let Some(max_objects) = self.options.max_objects {
+            if self.object_count > max_objects {
+                return Err(Error::ObjectLimitExceeded(max_objects));
+            }
+        }
+        let Some(fields) = value.as_dictionary() else {
+            return self.node(value);
+        };
+
+        let classes = self.classes_of(fields)?;
+        // Every object being built is an ancestor of this object.
+        if let Some(max_depth) = self.options.max_depth {
+            if self.building_objects.len() + 1 > max_depth {
+                return Err(Error::DepthLimitExceeded {
+                    path: self.path.concat(),
+                });
+            }
+        }
+
+        self.building_objects.insert(index);
+        let fields = self.fields(fields, true);
+        self.building_objects.remove(&index);
+        Ok(self.object_node(index, classes, fields?))
+    }
+
+    /// Returns the classes of an object in the object table, or the ones
+    /// described by a class info, which is not an instance and is not subject
+    /// to the allowed classes. Other dictionaries have no classes.
+    fn classes_of(&self, fields: &PlistDictionary) -> Result<Option<Vec<String>>, Error> {
+        let classes = if fields.contains_key(CLASS_KEY) {
+            let classes = self.dict.classes_of(fields)?;
+            self.check_allowed_class(classes[0])?;
+            classes
+        } else {
+            match classes_of_class_info(fields) {
+                Some(classes) => classes,
+                None => return Ok(None),
+            }
+        };
+        Ok(Some(classes.into_iter().map(str::to_owned).collect()))
+    }
+
+    // This is kept out of `node_at`, which is called recursively, to save
+    // stack space.
+    fn object_node(
+        &mut self,
+        index: u64,
+        classes: Option<Vec<String>>,
+        fields: Vec<(String, ArchiveNode)>,
+    ) -> ArchiveNode {
+        let Some(classes) = classes else {
+            return ArchiveNode::Dictionary(fields);
+        };
+        let object = Rc::new(ArchivedObject {
+            uid: index,
+            classes,
+            fields,
+        });
+        self.objects.insert(index, Rc::clone(&object));
+        ArchiveNode::Object(object)
+    }
+
+    // Loops are used instead of iterator adapters here and below, which take
+    // a lot more stack space per level of nesting in debug builds. The class
+    // reference of an object is not one of its fields, while inline
+    // dictionaries keep all their values.
+    fn fields(
+        &mut self,
+        dict: &PlistDictionary,
+        is_object: bool,
+    ) -> Result<Vec<(String, ArchiveNode)>, Error> {
+        let mut fields = Vec::with_capacity(dict.len());
+        for (key, value) in dict {
+            if is_object && key == CLASS_KEY {
+                continue;
+            }
+            let node = self.with_path_segment(format!(".{key}"), |b| b.node(value))?;
+            fields.push((key.clone(), node));
+        }
+        Ok(fields)
+    }
+
+    fn node(&mut self, value: &PlistValue) -> Result<ArchiveNode, Error> {
+        match value {
+            PlistValue::Uid(uid) => self.node_at(*uid),
+            PlistValue::Array(array) => {
+                let mut nodes = Vec::with_capacity(array.len());
+                for (index, value) in array.iter().enumerate() {
+                    nodes.push(self.with_path_segment(format!("[{index}]"), |b| b.node(value))?);
+                }
+                Ok(ArchiveNode::Array(nodes))
+            }
+            PlistValue::Dictionary(dict) => self.dictionary_node(dict),
+            _ => self.plain_node(value),
+        }
+    }
+
+    // Like `plain_node`, this is kept out of `node` to save stack space.
+    fn dictionary_node(&mut self, dict: &PlistDictionary) -> Result<ArchiveNode, Error> {
+        Ok(ArchiveNode::Dictionary(self.fields(dict, false)?))
+    }
+
+    // This is kept out of `node`, which is called recursively, since it takes
+    // a lot of stack space in debug builds.
+    fn plain_node(&self, value: &PlistValue) -> Result<ArchiveNode, Error> {
+        let node = match value {
+            PlistValue::Boolean(boolean) => ArchiveNode::Boolean(*boolean),
+            PlistValue::Integer(integer) => match integer.as_signed() {
+                Some(integer) => ArchiveNode::Integer(integer),
+                None => ArchiveNode::UnsignedInteger(integer.as_unsigned().unwrap_or_default()),
+            },
+            PlistValue::Real(real) => ArchiveNode::Real(*real),
+            PlistValue::Date(date) => ArchiveNode::Date((*date).into()),
+            PlistValue::String(string) => {
+                self.check_length(string.len())?;
+                ArchiveNode::String(string.clone())
+            }
+            PlistValue::Data(data) => {
+                self.check_length(data.len())?;
+                ArchiveNode::Data(data.clone())
+            }
+            _ => return Err(Error::MalformedObject),
+        };
+        Ok(node)
+    }
+
+    fn check_allowed_class(&self, class_name: &str) -> Result<(), Error> {
+        match &self.options.allowed_classes {
+            Some(classes) if !classes.iter().any(|class| class == class_name) => {
+                Err(Error::DisallowedClass {
+                    path: self.path.concat(),
+                    class: class_name.to_owned(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_length(&self, length: usize) -> Result<(), Error> {
+        match self.options.max_length {
+            Some(max_length) if length > max_length => Err(Error::LengthLimitExceeded {
+                path: self.path.concat(),
+                max_length,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
@@ -1,11 +1,15 @@
-use std::any::Any;
+use std::any::{Any, TypeId};
 use std::collections::{hash_map::Entry as HashMapEntry, HashMap};
 use std::fmt::{Debug, Formatter, Result as FmtResult};
-use std::ops::Deref;
+use std::ops::{Deref, DerefMut};
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use plist::Value as PlistValue;
 
 use crate::archiver::{Decoder, Encoder};
 
-mod __private {
+pub(crate) mod __private {
     pub struct DummyMarker;
 }
 
@@ -84,6 +88,11 @@ pub trait Archive: Debug + Sized {
         false
     }
 
+    #[doc(hidden)]
+    fn inline_value(&self, _marker: __private::DummyMarker) -> Option<PlistValue> {
+        None
+    }
+
     /// Encodes this object with the given archiver.
     ///
     /// See the [Implementing `Archive`][impl-archive] section of the
@@ -128,16 +137,525 @@ impl Archive for RootObject {
     }
 }
 
-pub(crate) fn get_classes<T: Archive>() -> Vec<&'static str> {
+/// Strings are archived inline as plain values, but they can also be decoded
+/// from the keyed form that `NSMutableString` uses, which is registered by
+/// [`TypeRegistry::new`].
+impl Archive for String {
+    type Super = RootObject;
+
+    fn class_name() -> &'static str {
+        "NSString"
+    }
+
+    fn inline_value(&self, _marker: __private::DummyMarker) -> Option<PlistValue> {
+        Some(PlistValue::String(self.clone()))
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        archiver.encode_string(self, "NS.string");
+    }
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        unarchiver.decode_string("NS.string")
+    }
+}
+
+/// Byte buffers are archived inline as plain values, but they can also be
+/// decoded from the keyed form that `NSMutableData` uses, which is registered
+/// by [`TypeRegistry::new`].
+impl Archive for Vec<u8> {
+    type Super = RootObject;
+
+    fn class_name() -> &'static str {
+        "NSData"
+    }
+
+    fn inline_value(&self, _marker: __private::DummyMarker) -> Option<PlistValue> {
+        Some(PlistValue::Data(self.clone()))
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        archiver.encode_bytes(self, "NS.data");
+    }
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        unarchiver.decode_bytes("NS.data")
+    }
+}
+
+macro_rules! impl_archive_for_number {
+    ($ty:ty, $variant:ident) => {
+        /// Numbers are always archived inline as plain values, there is no
+        /// keyed form to decode them from.
+        impl Archive for $ty {
+            type Super = RootObject;
+
+            fn class_name() -> &'static str {
+                "NSNumber"
+            }
+
+            fn inline_value(&self, _marker: __private::DummyMarker) -> Option<PlistValue> {
+                Some(PlistValue::$variant((*self).into()))
+            }
+
+            fn encode(&self, _archiver: &mut dyn Encoder) {}
+
+            fn decode(_unarchiver: &dyn Decoder) -> Option<Self> {
+                None
+            }
+        }
+    };
+}
+
+impl_archive_for_number!(bool, Boolean);
+impl_archive_for_number!(i64, Integer);
+impl_archive_for_number!(u64, Integer);
+impl_archive_for_number!(f64, Real);
+
+macro_rules! mutable_subclass {
+    ($name:ident, $super:ident) => {
+        #[doc = concat!("A built-in type that represents an archived `", stringify!($name), "` object.")]
+        ///
+        #[doc = concat!("The type dereferences to [`", stringify!($super), "`], its Cocoa superclass.")]
+        #[derive(Debug, Default)]
+        pub struct $name(pub $super);
+
+        impl Archive for $name {
+            type Super = $super;
+
+            fn class_name() -> &'static str {
+                stringify!($name)
+            }
+
+            fn encode(&self, archiver: &mut dyn Encoder) {
+                self.0.encode(archiver);
+            }
+
+            fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+                $super::decode(unarchiver).map(Self)
+            }
+        }
+
+        impl Deref for $name {
+            type Target = $super;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        impl DerefMut for $name {
+            fn deref_mut(&mut self) -> &mut Self::Target {
+                &mut self.0
+            }
+        }
+
+        impl $super {
+            /// Extracts the value from an object of either this type or its
+            /// mutable subclass.
+            pub(crate) fn from_object(object: AnyObject) -> Option<Self> {
+                if object.is::<$name>() {
+                    return object.downcast::<$name>().ok().map(|object| object.0);
+                }
+                object.downcast::<Self>().ok().map(|object| *object)
+            }
+        }
+    };
+}
+
+pub(crate) const NS_OBJECTS_KEY: &str = "NS.objects";
+pub(crate) const NS_KEYS_KEY: &str = "NS.keys";
+
+/// A built-in type that represents an archived `NSArray` object.
+///
+/// Elements are shared objects, so an object that is an element of multiple
+/// collections is decoded once (see
+/// [Object identity](Decoder#object-identity)).
+///
+/// The type is registered automatically by [`TypeRegistry::new`]. Its
+/// elements can also be decoded directly with [`Decoder::decode_array`].
+#[derive(Debug, Default)]
+pub struct NSArray {
+    /// The elements of the array.
+    pub objects: Vec<ObjectRef>,
+}
+
+impl NSArray {
+    /// Constructs an `NSArray` with the given elements.
+    pub fn new(objects: Vec<AnyObject>) -> Self {
+        Self {
+            objects: objects.into_iter().map(Rc::new).collect(),
+        }
+    }
+}
+
+impl Archive for NSArray {
+    type Super = RootObject;
+
+    fn class_name() -> &'static str {
+        "NSArray"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        archiver.encode_shared_objects(&self.objects, NS_OBJECTS_KEY);
+    }
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        let objects = unarchiver.decode_shared_objects(NS_OBJECTS_KEY)?;
+        Some(Self { objects })
+    }
+}
+
+mutable_subclass!(NSMutableArray, NSArray);
+
+/// A built-in type that represents an archived `NSDictionary` object.
+///
+/// Entries are kept in the order they were archived or inserted. Since most
+/// dictionaries in the wild are keyed by strings, there are convenience
+/// methods to look up entries with string keys. Like the elements of
+/// [`NSArray`], keys and objects are shared objects.
+///
+/// The type is registered automatically by [`TypeRegistry::new`].
+#[derive(Debug, Default)]
+pub struct NSDictionary {
+    keys: Vec<ObjectRef>,
+    objects: Vec<ObjectRef>,
+}
+
+impl NSDictionary {
+    /// Constructs an empty `NSDictionary`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of entries in the dictionary.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns `true` if the dictionary contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Inserts an entry into the dictionary.
+    ///
+    /// If the key is a string and the dictionary already has an entry with
+    /// an equal string key, the object of that entry is replaced. Otherwise,
+    /// the entry is appended to the end.
+    pub fn insert(&mut self, key: AnyObject, object: AnyObject) {
+        self.insert_shared(Rc::new(key), Rc::new(object));
+    }
+
+    /// Inserts an entry of shared objects into the dictionary, in the same
+    /// way as [`NSDictionary::insert`].
+    pub fn insert_shared(&mut self, key: ObjectRef, object: ObjectRef) {
+        if let Some(index) = key
+            .downcast_ref::<String>()
+            .and_then(|key| self.position_of(key))
+        {
+            self.objects[index] = object;
+            return;
+        }
+        self.keys.push(key);
+        self.objects.push(object);
+    }
+
+    /// Returns the object of the entry whose key is the given string.
+    pub fn get(&self, key: &str) -> Option<&AnyObject> {
+        self.position_of(key).map(|index| &*self.objects[index])
+    }
+
+    /// Returns an iterator over the entries of the dictionary.
+    pub fn iter(&self) -> impl Iterator<Item = (&AnyObject, &AnyObject)> {
+        self.keys
+            .iter()
+            .map(|key| &**key)
+            .zip(self.objects.iter().map(|o| &**o))
+    }
+
+    /// Returns an iterator over the entries whose keys are strings.
+    pub fn iter_str(&self) -> impl Iterator<Item = (&str, &AnyObject)> {
+        self.iter()
+            .filter_map(|(key, object)| Some((key.downcast_ref::<String>()?.as_str(), object)))
+    }
+
+    fn position_of(&self, key: &str) -> Option<usize> {
+        self.keys
+            .iter()
+            .position(|k| k.downcast_ref::<String>().is_some_and(|k| k == key))
+    }
+}
+
+impl IntoIterator for NSDictionary {
+    type Item = (ObjectRef, ObjectRef);
+    type IntoIter = std::iter::Zip<std::vec::IntoIter<ObjectRef>, std::vec::IntoIter<ObjectRef>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.keys.into_iter().zip(self.objects)
+    }
+}
+
+impl Archive for NSDictionary {
+    type Super = RootObject;
+
+    fn class_name() -> &'static str {
+        "NSDictionary"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        archiver.encode_shared_objects(&self.keys, NS_KEYS_KEY);
+        archiver.encode_shared_objects(&self.objects, NS_OBJECTS_KEY);
+    }
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        let keys = unarchiver.decode_shared_objects(NS_KEYS_KEY)?;
+        let objects = unarchiver.decode_shared_objects(NS_OBJECTS_KEY)?;
+        if keys.len() != objects.len() {
+            return None;
+        }
+        Some(Self { keys, objects })
+    }
+}
+
+mutable_subclass!(NSMutableDictionary, NSDictionary);
+
+/// A built-in type that represents an archived `NSSet` object.
+///
+/// Elements are kept in the order they were archived, no deduplication is
+/// performed. Like the elements of [`NSArray`], they are shared objects.
+///
+/// The type is registered automatically by [`TypeRegistry::new`].
+#[derive(Debug, Default)]
+pub struct NSSet {
+    /// The elements of the set.
+    pub objects: Vec<ObjectRef>,
+}
+
+impl NSSet {
+    /// Constructs an `NSSet` with the given elements.
+    pub fn new(objects: Vec<AnyObject>) -> Self {
+        Self {
+            objects: objects.into_iter().map(Rc::new).collect(),
+        }
+    }
+}
+
+impl Archive for NSSet {
+    type Super = RootObject;
+
+    fn class_name() -> &'static str {
+        "NSSet"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        archiver.encode_shared_objects(&self.objects, NS_OBJECTS_KEY);
+    }
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        let objects = unarchiver.decode_shared_objects(NS_OBJECTS_KEY)?;
+        Some(Self { objects })
+    }
+}
+
+mutable_subclass!(NSMutableSet, NSSet);
+
+/// A built-in type that represents an archived `NSOrderedSet` object.
+///
+/// Unlike other collections, ordered sets archive each element with its
+/// own key (`NS.object.0`, `NS.object.1` and so on). Like the elements of
+/// [`NSArray`], they are shared objects.
+///
+/// The type is registered automatically by [`TypeRegistry::new`].
+#[derive(Debug, Default)]
+pub struct NSOrderedSet {
+    /// The elements of the ordered set.
+    pub objects: Vec<ObjectRef>,
+}
+
+impl NSOrderedSet {
+    /// Constructs an `NSOrderedSet` with the given elements.
+    pub fn new(objects: Vec<AnyObject>) -> Self {
+        Self {
+            objects: objects.into_iter().map(Rc::new).collect(),
+        }
+    }
+}
+
+impl Archive for NSOrderedSet {
+    type Super = RootObject;
+
+    fn class_name() -> &'static str {
+        "NSOrderedSet"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        for (index, object) in self.objects.iter().enumerate() {
+            archiver.encode_shared_object(object, &format!("NS.object.{index}"));
+        }
+    }
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        // An element that fails to decode fails the whole set, rather than
+        // silently truncating it.
+        let mut objects = vec![];
+        while unarchiver.contains_key(&format!("NS.object.{}", objects.len())) {
+            let key = format!("NS.object.{}", objects.len());
+            objects.push(unarchiver.try_decode_shared_object(&key).ok()?);
+        }
+        Some(Self { objects })
+    }
+}
+
+mutable_subclass!(NSMutableOrderedSet, NSOrderedSet);
+
+/// A class in the class hierarchy of an archived object.
+///
+/// This is only public because hidden methods of [`EncodeObject`] return it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Class {
+    /// A class implemented by an `Archive` type.
+    Type(TypeId, &'static str),
+    /// A class without a type of its own, like a struct archived with serde.
+    Name(&'static str),
+}
+
+impl Class {
+    pub(crate) fn of<T: Archive + 'static>() -> Self {
+        Self::Type(TypeId::of::<T>(), T::class_name())
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Type(_, name) | Self::Name(name) => name,
+        }
+    }
+}
+
+pub(crate) fn get_classes<T: Archive>() -> Vec<Class> {
     let mut classes = if !T::is_root_class(__private::DummyMarker) {
         get_classes::<T::Super>()
     } else {
         vec![]
     };
-    classes.insert(0, T::class_name());
+    // Types encoded by reference need not be `'static`, and lifetimes do not
+    // matter to tell them apart, so this is the same as `Class::of`.
+    classes.insert(0, Class::Type(typeid::of::<T>(), T::class_name()));
     classes
 }
 
+pub(crate) fn get_inline_value<T: Archive>(object: &T) -> Option<PlistValue> {
+    object.inline_value(__private::DummyMarker)
+}
+
+/// Converts a plain value stored in the `$objects` array to an object.
+pub(crate) fn from_inline_value(value: &PlistValue) -> Option<AnyObject> {
+    let object = match value {
+        PlistValue::String(string) => AnyObject::erasing(string.clone()),
+        PlistValue::Data(data) => AnyObject::erasing(data.clone()),
+        PlistValue::Boolean(boolean) => AnyObject::erasing(*boolean),
+        // Only integers out of the range of `i64` are decoded as `u64`.
+        PlistValue::Integer(integer) => match integer.as_signed() {
+            Some(integer) => AnyObject::erasing(integer),
+            None => AnyObject::erasing(integer.as_unsigned()?),
+        },
+        PlistValue::Real(real) => AnyObject::erasing(*real),
+        _ => return None,
+    };
+    Some(object)
+}
+
+/// An object-safe counterpart of [`Archive`], which is used to encode objects
+/// by reference.
+///
+/// The trait is implemented automatically for all types that implement
+/// [`Archive`], as well as [`AnyObject`]. It allows encoding an object with
+/// [`Encoder::encode_value`] without erasing it into an `AnyObject` first.
+pub trait EncodeObject {
+    #[doc(hidden)]
+    fn erased_encode(&self, archiver: &mut dyn Encoder, _marker: __private::DummyMarker);
+
+    #[doc(hidden)]
+    fn erased_classes(&self, _marker: __private::DummyMarker) -> Vec<Class>;
+
+    #[doc(hidden)]
+    fn erased_inline_value(&self, _marker: __private::DummyMarker) -> Option<PlistValue>;
+
+    #[doc(hidden)]
+    fn erased_object_id(&self, _marker: __private::DummyMarker) -> Option<u64> {
+        None
+    }
+}
+
+impl<T: Archive> EncodeObject for T {
+    fn erased_encode(&self, archiver: &mut dyn Encoder, _marker: __private::DummyMarker) {
+        self.encode(archiver);
+    }
+
+    fn erased_classes(&self, _marker: __private::DummyMarker) -> Vec<Class> {
+        get_classes::<T>()
+    }
+
+    fn erased_inline_value(&self, _marker: __private::DummyMarker) -> Option<PlistValue> {
+        get_inline_value(self)
+    }
+}
+
+impl EncodeObject for AnyObject {
+    fn erased_encode(&self, archiver: &mut dyn Encoder, _marker: __private::DummyMarker) {
+        self.encode(archiver);
+    }
+
+    fn erased_classes(&self, _marker: __private::DummyMarker) -> Vec<Class> {
+        self.get_classes()
+    }
+
+    fn erased_inline_value(&self, _marker: __private::DummyMarker) -> Option<PlistValue> {
+        self.inline_value()
+    }
+
+    fn erased_object_id(&self, _marker: __private::DummyMarker) -> Option<u64> {
+        Some(self.id)
+    }
+}
+
+impl dyn EncodeObject + '_ {
+    pub(crate) fn encode_with(&self, archiver: &mut dyn Encoder) {
+        self.erased_encode(archiver, __private::DummyMarker)
+    }
+
+    pub(crate) fn classes(&self) -> Vec<Class> {
+        self.erased_classes(__private::DummyMarker)
+    }
+
+    pub(crate) fn inline_value(&self) -> Option<PlistValue> {
+        self.erased_inline_value(__private::DummyMarker)
+    }
+
+    /// Returns the identity of the object if it is an [`AnyObject`].
+    pub(crate) fn object_id(&self) -> Option<u64> {
+        self.erased_object_id(__private::DummyMarker)
+    }
+}
+
+/// A borrowed slice of objects that is encoded as an `NSArray` object.
+pub(crate) struct ArrayRef<'a, T>(pub &'a [T]);
+
+impl<T: EncodeObject> EncodeObject for ArrayRef<'_, T> {
+    fn erased_encode(&self, archiver: &mut dyn Encoder, _marker: __private::DummyMarker) {
+        let objects: Vec<_> = self.0.iter().map(|o| o as &dyn EncodeObject).collect();
+        archiver.encode_values(&objects, NS_OBJECTS_KEY);
+    }
+
+    fn erased_classes(&self, _marker: __private::DummyMarker) -> Vec<Class> {
+        get_classes::<NSArray>()
+    }
+
+    fn erased_inline_value(&self, _marker: __private::DummyMarker) -> Option<PlistValue> {
+        None
+    }
+}
+
 /// A type-erased container that holds an object that implements [`Archive`].
 ///
 /// `AnyObject` automatically dereferences to `dyn Any` (via the [`Deref`] trait),
@@ -148,9 +666,16 @@ pub struct AnyObject {
     ptr: Box<dyn Any>,
     debug_fn: fn(*const (), &mut Formatter) -> FmtResult,
     encode_fn: fn(*const (), &mut dyn Encoder),
-    get_classes_fn: fn() -> Vec<&'static str>,
+    inline_value_fn: fn(*const ()) -> Option<PlistValue>,
+    get_classes_fn: fn() -> Vec<Class>,
+    archived_class_name: Option<String>,
+    // Identifies the object while it is encoded. Unlike addresses, IDs are
+    // never reused by other objects.
+    id: u64,
 }
 
+static NEXT_OBJECT_ID: AtomicU64 = AtomicU64::new(0);
+
 impl AnyObject {
     /// Constructs an `AnyObject` by erasing a given object.
     pub fn erasing<T: Archive + 'static>(object: T) -> Self {
@@ -164,12 +689,20 @@ impl AnyObject {
             object.encode(archiver);
         }
 
+        fn typed_inline_value<T: Archive>(ptr: *const ()) -> Option<PlistValue> {
+            let object = unsafe { &*(ptr as *const T) };
+            get_inline_value(object)
+        }
+
         Self {
             class_name: T::class_name(),
             ptr: Box::new(object),
             debug_fn: typed_debug::<T>,
             encode_fn: typed_encode::<T>,
+            inline_value_fn: typed_inline_value::<T>,
             get_classes_fn: get_classes::<T>,
+            archived_class_name: None,
+            id: NEXT_OBJECT_ID.fetch_add(1, Ordering::Relaxed),
         }
     }
 
@@ -179,6 +712,26 @@ impl AnyObject {
         self.class_name
     }
 
+    /// Returns the class name stored in the archive if the object was decoded
+    /// as one of its superclasses, or `None` if no substitution happened.
+    ///
+    /// See [`TypeRegistry::set_superclass_fallback`] for more information.
+    pub fn substituted_class_name(&self) -> Option<&str> {
+        self.archived_class_name.as_deref()
+    }
+
+    /// Returns the class name stored in the archive, which is the class name
+    /// of the type unless it was substituted.
+    pub(crate) fn archived_class_name(&self) -> &str {
+        self.archived_class_name
+            .as_deref()
+            .unwrap_or(self.class_name)
+    }
+
+    pub(crate) fn set_archived_class_name(&mut self, class_name: String) {
+        self.archived_class_name = Some(class_name);
+    }
+
     /// Attempt to downcast the object to a concrete type.
     pub fn downcast<T: Any>(self) -> Result<Box<T>, Box<dyn Any + 'static>> {
         self.ptr.downcast()
@@ -189,9 +742,13 @@ impl AnyObject {
         (self.encode_fn)(&*self.ptr as *const _ as *const (), archiver);
     }
 
-    pub(crate) fn get_classes(&self) -> Vec<&'static str> {
+    pub(crate) fn get_classes(&self) -> Vec<Class> {
         (self.get_classes_fn)()
     }
+
+    pub(crate) fn inline_value(&self) -> Option<PlistValue> {
+        (self.inline_value_fn)(&*self.ptr as *const _ as *const ())
+    }
 }
 
 impl Debug for AnyObject {
@@ -208,19 +765,49 @@ impl Deref for AnyObject {
     }
 }
 
+/// A shared handle to an object, which preserves the object identity in
+/// the archive.
+///
+/// See [`Decoder::decode_shared_object`] for more information.
+pub type ObjectRef = Rc<AnyObject>;
+
+/// A weak handle to a shared object, which does not keep it alive.
+///
+/// This is typically used for back-pointers, like the parent of a child
+/// object. See [`Decoder::decode_weak_object`] for more information.
+pub type WeakObjectRef = Weak<AnyObject>;
+
 pub(crate) type UnarchiveFn = fn(&dyn Decoder) -> Option<AnyObject>;
 
+fn typed_unarchive<T: Archive + 'static>(unarchiver: &dyn Decoder) -> Option<AnyObject> {
+    let object = T::decode(unarchiver)?;
+    Some(AnyObject::erasing(object))
+}
+
 /// Registers custom types so that they can be instantiated by the
 /// unarchiver later.
-#[derive(Default)]
+///
+/// Built-in types like [`NSArray`] and [`NSDictionary`] are registered
+/// automatically.
 pub struct TypeRegistry {
-    unarchive_fns: HashMap<&'static str, UnarchiveFn>,
+    unarchive_fns: HashMap<String, UnarchiveFn>,
+    superclass_fallback: bool,
 }
 
 impl TypeRegistry {
-    /// Constructs a new `TypeRegistry`.
+    /// Constructs a new `TypeRegistry` with built-in types registered.
     pub fn new() -> Self {
-        Self::default()
+        let mut this = Self {
+            unarchive_fns: HashMap::new(),
+            superclass_fallback: false,
+        };
+        this.register_type::<NSMutableArray>();
+        this.register_type::<NSMutableDictionary>();
+        this.register_type::<NSMutableSet>();
+        this.register_type::<NSMutableOrderedSet>();
+        this.register_alias::<String>("NSMutableString");
+        this.register_alias::<Vec<u8>>("NSMutableData");
+        this
     }
 
     /// Registers a given type.
@@ -232,16 +819,8 @@ impl TypeRegistry {
             self.register_type::<T::Super>();
         }
 
-        fn typed_unarchive<T: Archive + 'static>(unarchiver: &dyn Decoder) -> Option<AnyObject> {
-            let Some(object) = T::decode(unarchiver) else {
-                return None;
-            };
-
-            Some(AnyObject::erasing(object))
-        }
-
         let class_name = T::class_name();
-        match self.unarchive_fns.entry(class_name) {
+        match self.unarchive_fns.entry(class_name.to_owned()) {
             HashMapEntry::Occupied(_) => (),
             HashMapEntry::Vacant(vacant_entry) => {
                 vacant_entry.insert(typed_unarchive::<T>);
@@ -249,7 +828,53 @@ impl TypeRegistry {
         }
     }
 
-    pub(crate) fn get_unarchive_fn(&self, class_name: &str) -> Option<&UnarchiveFn> {
-        self.unarchive_fns.get(class_name)
+    /// Registers a given type, and decodes objects archived with the given
+    /// class name as this type.
+    ///
+    /// This is useful to read archives of renamed classes, or Swift classes
+    /// archived with mangled names. Unlike [`TypeRegistry::register_type`],
+    /// the class name is always mapped to this type, replacing any type that
+    /// was registered with it before.
+    pub fn register_alias<T: Archive + 'static>(&mut self, class_name: &str) {
+        self.register_type::<T>();
+        self.unarchive_fns
+            .insert(class_name.to_owned(), typed_unarchive::<T>);
+    }
+
+    /// Sets whether objects of unregistered classes are decoded as their
+    /// nearest registered superclass, which is disabled by default.
+    ///
+    /// The superclasses are looked up from the `$classes` list stored in the
+    /// archive, except for the root class `NSObject`, which carries no data.
+    /// Objects decoded this way report their original class name with
+    /// [`AnyObject::substituted_class_name`].
+    pub fn set_superclass_fallback(&mut self, enabled: bool) {
+        self.superclass_fallback = enabled;
+    }
+
+    /// Returns the unarchive function for an object with the given class
+    /// chain, along with the name of the class it decodes.
+    pub(crate) fn get_unarchive_fn<'c>(
+        &self,
+        classes: &[&'c str],
+    ) -> Option<(&'c str, &UnarchiveFn)> {
+        let (class_name, super_classes) = classes.split_first()?;
+        if let Some(unarchive_fn) = self.unarchive_fns.get(*class_name) {
+            return Some((*class_name, unarchive_fn));
+        }
+        if !self.superclass_fallback {
+            return None;
+        }
+
+        super_classes
+            .iter()
+            .filter(|class_name| **class_name != RootObject::class_name())
+            .find_map(|class_name| Some((*class_name, self.unarchive_fns.get(*class_name)?)))
+    }
+}
+
+impl Default for TypeRegistry {
+    fn default() -> Self {
+        Self::new()
     }
 }
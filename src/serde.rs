@@ -0,0 +1,700 @@
+//! Archiving of types that implement [`Serialize`] and [`Deserialize`].
+//!
+//! Types that already derive serde traits can be archived without
+//! implementing [`Archive`]:
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! struct Person {
+//!     #[serde(rename = "Age")]
+//!     age: u32,
+//!     #[serde(rename = "FirstName")]
+//!     first_name: String,
+//!     #[serde(rename = "Nickname")]
+//!     nickname: Option<String>,
+//! }
+//!
+//! let person = Person {
+//!     age: 26,
+//!     first_name: "Cyan".to_owned(),
+//!     nickname: None,
+//! };
+//! let bytes = nscoder::serde::to_bytes(&person, "RCDPerson").unwrap();
+//! let decoded_person: Person = nscoder::serde::from_bytes(&bytes).unwrap();
+//! assert_eq!(decoded_person, person);
+//! ```
+//!
+//! Values are mapped to archived objects as follows:
+//!
+//! - Structs become objects whose class names are the struct names (which
+//!   can be changed with `#[serde(rename)]`), and whose fields are encoded
+//!   with the field names as keys. The root struct is archived with the
+//!   class name given to [`to_bytes`].
+//! - Sequences and tuples become `NSArray` objects, and maps become
+//!   `NSDictionary` objects.
+//! - `None` and `()` become absent keys, thus they cannot be elements of
+//!   sequences. Map entries whose values are `None` are skipped.
+//! - Unit variants become strings, and other variants become `NSDictionary`
+//!   objects with a single entry from the variant name to its content.
+//! - Byte buffers are only archived as data if they are serialized with
+//!   `serialize_bytes` (like what `serde_bytes` does), otherwise they become
+//!   arrays of numbers.
+//!
+//! Decoding accepts any keyed archive, objects of unknown classes are
+//! deserialized as maps of their values, and the built-in collection classes
+//! are deserialized as sequences and maps. Deserialized values cannot
+//! reference each other, so a cyclic reference fails with
+//! [`Error::CyclicReference`], unless it is ignored (like the value of an
+//! unknown field).
+//!
+//! [`Deserialize`]: ::serde::Deserialize
+
+use std::io::Write;
+
+use ::serde::de::value::{MapAccessDeserializer, MapDeserializer, SeqDeserializer};
+use ::serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use ::serde::ser::{self, Serialize};
+use plist::{Date as PlistDate, Value as PlistValue};
+
+use crate::archiver::{to_writer_top_objects, ArchiveOptions, Encoder, UnarchiveOptions};
+use crate::node::{ArchiveNode, ArchivedObject};
+use crate::object::{
+    __private, get_classes, Archive, Class, EncodeObject, NSArray, NSDictionary, NSOrderedSet,
+    NSSet, RootObject, NS_KEYS_KEY, NS_OBJECTS_KEY,
+};
+use crate::types::{Error, OutputFormat, ROOT_KEY};
+
+/// Archives a value as the root object with the given class name, and
+/// returns the archive data as bytes.
+///
+/// The value must be serialized as a struct.
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T, class_name: &str) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    to_writer(&mut buf, value, class_name, OutputFormat::Binary)?;
+    Ok(buf)
+}
+
+/// Archives a value as the root object with the given class name, and
+/// writes the archive data in the given format to a writer.
+///
+/// The value must be serialized as a struct.
+pub fn to_writer<W: Write, T: Serialize + ?Sized>(
+    writer: W,
+    value: &T,
+    class_name: &str,
+    format: OutputFormat,
+) -> Result<(), Error> {
+    let Some(object @ Value::Object { class, .. }) = &value.serialize(ValueSerializer)? else {
+        return Err(Error::Serde("root value must be a struct".to_owned()));
+    };
+
+    let mut options = ArchiveOptions::new();
+    options.set_class_name(class, class_name);
+    to_writer_top_objects(writer, &[(ROOT_KEY, object)], format, &options)
+}
+
+/// Decodes a value from the root object of an archive.
+pub fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+    from_bytes_with_options(bytes, &UnarchiveOptions::default())
+}
+
+/// Like [`from_bytes`], but decodes the archive with the given options.
+///
+/// The object graph is resolved with [`ArchiveNode::from_bytes_with_options`]
+/// before it is deserialized, so the limits of the options apply to the whole
+/// archive, including the values that are ignored.
+pub fn from_bytes_with_options<T: DeserializeOwned>(
+    bytes: &[u8],
+    options: &UnarchiveOptions,
+) -> Result<T, Error> {
+    let root = ArchiveNode::from_bytes_with_options(bytes, options)?;
+    T::deserialize(NodeDeserializer(&root))
+}
+
+impl ser::Error for Error {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        Self::Serde(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        Self::Serde(msg.to_string())
+    }
+}
+
+/// A serialized value, which is encoded by the archiver afterwards.
+#[derive(Debug)]
+enum Value {
+    Boolean(bool),
+    Integer(i64),
+    Real(f64),
+    String(String),
+    Data(Vec<u8>),
+    Array(Vec<Value>),
+    Dictionary(Vec<(Value, Value)>),
+    Object {
+        class: &'static str,
+        fields: Vec<(&'static str, Value)>,
+    },
+}
+
+impl Value {
+    fn variant(variant: &'static str, value: Value) -> Self {
+        Self::Dictionary(vec![(Self::String(variant.to_owned()), value)])
+    }
+}
+
+impl EncodeObject for Value {
+    fn erased_encode(&self, archiver: &mut dyn Encoder, _marker: __private::DummyMarker) {
+        match self {
+            Self::Array(elements) => {
+                archiver.encode_values(&erase(elements.iter()), NS_OBJECTS_KEY);
+            }
+            Self::Dictionary(entries) => {
+                let keys = erase(entries.iter().map(|(key, _)| key));
+                archiver.encode_values(&keys, NS_KEYS_KEY);
+                let objects = erase(entries.iter().map(|(_, object)| object));
+                archiver.encode_values(&objects, NS_OBJECTS_KEY);
+            }
+            Self::Object { fields, .. } => {
+                for (key, value) in fields {
+                    match value {
+                        Self::Boolean(boolean) => archiver.encode_bool(*boolean, key),
+                        Self::Integer(integer) => archiver.encode_i64(*integer, key),
+                        Self::Real(real) => archiver.encode_f64(*real, key),
+                        Self::String(string) => archiver.encode_string(string, key),
+                        Self::Data(data) => archiver.encode_bytes(data, key),
+                        _ => archiver.encode_value(value, key),
+                    }
+                }
+            }
+            // Plain values are always archived inline.
+            _ => (),
+        }
+    }
+
+    fn erased_classes(&self, _marker: __private::DummyMarker) -> Vec<Class> {
+        match self {
+            Self::Array(_) => get_classes::<NSArray>(),
+            Self::Dictionary(_) => get_classes::<NSDictionary>(),
+            Self::Object { class, .. } => vec![Class::Name(class), Class::of::<RootObject>()],
+            Self::String(_) => get_classes::<String>(),
+            Self::Data(_) => get_classes::<Vec<u8>>(),
+            Self::Boolean(_) | Self::Integer(_) | Self::Real(_) => get_classes::<i64>(),
+        }
+    }
+
+    fn erased_inline_value(&self, _marker: __private::DummyMarker) -> Option<PlistValue> {
+        let value = match self {
+            Self::Boolean(boolean) => PlistValue::Boolean(*boolean),
+            Self::Integer(integer) => PlistValue::Integer((*integer).into()),
+            Self::Real(real) => PlistValue::Real(*real),
+            Self::String(string) => PlistValue::String(string.clone()),
+            Self::Data(data) => PlistValue::Data(data.clone()),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+fn erase<'a>(values: impl Iterator<Item = &'a Value>) -> Vec<&'a dyn EncodeObject> {
+    values.map(|value| value as &dyn EncodeObject).collect()
+}
+
+/// Serializes values into [`Value`], or `None` for absent values.
+struct ValueSerializer;
+
+fn present(value: Option<Value>) -> Result<Value, Error> {
+    value.ok_or_else(|| Error::Serde("collections cannot contain `None` or `()`".to_owned()))
+}
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = StructSerializer;
+    type SerializeStructVariant = StructSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::Boolean(v)))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::Integer(v)))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Error> {
+        match i64::try_from(v) {
+            Ok(v) => self.serialize_i64(v),
+            Err(_) => Err(Error::Serde(format!("integer {v} is too large"))),
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Error> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::Real(v)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::String(v.to_owned())))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::Data(v.to_owned())))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::Object {
+            class: name,
+            fields: vec![],
+        }))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Error> {
+        let value = present(value.serialize(self)?)?;
+        Ok(Some(Value::variant(variant, value)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Ok(SeqSerializer {
+            variant: None,
+            elements: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Ok(SeqSerializer {
+            variant: Some(variant),
+            elements: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Ok(MapSerializer {
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            next_key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        Ok(StructSerializer {
+            variant: None,
+            class: name,
+            fields: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Ok(StructSerializer {
+            variant: Some(variant),
+            class: variant,
+            fields: Vec::with_capacity(len),
+        })
+    }
+}
+
+struct SeqSerializer {
+    variant: Option<&'static str>,
+    elements: Vec<Value>,
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.elements
+            .push(present(value.serialize(ValueSerializer)?)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<Value>, Error> {
+        let array = Value::Array(self.elements);
+        match self.variant {
+            Some(variant) => Ok(Some(Value::variant(variant, array))),
+            None => Ok(Some(array)),
+        }
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        self.finish()
+    }
+}
+
+struct MapSerializer {
+    entries: Vec<(Value, Value)>,
+    next_key: Option<Value>,
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.next_key = Some(present(key.serialize(ValueSerializer)?)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.next_key.take().expect("expected a key");
+        if let Some(value) = value.serialize(ValueSerializer)? {
+            self.entries.push((key, value));
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::Dictionary(self.entries)))
+    }
+}
+
+struct StructSerializer {
+    variant: Option<&'static str>,
+    class: &'static str,
+    fields: Vec<(&'static str, Value)>,
+}
+
+impl StructSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        if let Some(value) = value.serialize(ValueSerializer)? {
+            self.fields.push((key, value));
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<Value>, Error> {
+        let object = Value::Object {
+            class: self.class,
+            fields: self.fields,
+        };
+        match self.variant {
+            Some(variant) => Ok(Some(Value::variant(variant, object))),
+            None => Ok(Some(object)),
+        }
+    }
+}
+
+impl ser::SerializeStruct for StructSerializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for StructSerializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        self.finish()
+    }
+}
+
+/// Deserializes values from an untyped object graph.
+struct NodeDeserializer<'a>(&'a ArchiveNode);
+
+impl<'de> IntoDeserializer<'de, Error> for NodeDeserializer<'_> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// Returns the value of a built-in class whose superclass is `class`.
+fn builtin_value<'a, T: Archive>(object: &'a ArchivedObject, key: &str) -> Option<&'a ArchiveNode> {
+    if !object.classes.iter().any(|class| class == T::class_name()) {
+        return None;
+    }
+    object.get(key)
+}
+
+impl<'de> de::Deserializer<'de> for NodeDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            ArchiveNode::Null => visitor.visit_unit(),
+            ArchiveNode::Boolean(boolean) => visitor.visit_bool(*boolean),
+            ArchiveNode::Integer(integer) => visitor.visit_i64(*integer),
+            ArchiveNode::UnsignedInteger(integer) => visitor.visit_u64(*integer),
+            ArchiveNode::Real(real) => visitor.visit_f64(*real),
+            ArchiveNode::Date(date) => visitor.visit_string(PlistDate::from(*date).to_xml_format()),
+            ArchiveNode::String(string) => visitor.visit_str(string),
+            ArchiveNode::Data(data) => visitor.visit_bytes(data),
+            ArchiveNode::Array(nodes) => {
+                let mut seq = SeqDeserializer::new(nodes.iter().map(NodeDeserializer));
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            ArchiveNode::Object(object) => {
+                let elements = builtin_value::<NSArray>(object, NS_OBJECTS_KEY)
+                    .or_else(|| builtin_value::<NSSet>(object, NS_OBJECTS_KEY))
+                    .or_else(|| builtin_value::<NSOrderedSet>(object, NS_OBJECTS_KEY));
+                if let Some(elements) = elements {
+                    return NodeDeserializer(elements).deserialize_any(visitor);
+                }
+                if let (Some(keys), Some(objects)) = (
+                    builtin_value::<NSDictionary>(object, NS_KEYS_KEY),
+                    builtin_value::<NSDictionary>(object, NS_OBJECTS_KEY),
+                ) {
+                    let (Some(keys), Some(objects)) = (keys.as_array(), objects.as_array()) else {
+                        return Err(Error::MalformedObject);
+                    };
+                    let entries = keys.iter().zip(objects);
+                    let mut map =
+                        MapDeserializer::new(entries.map(|(key, object)| {
+                            (NodeDeserializer(key), NodeDeserializer(object))
+                        }));
+                    let value = visitor.visit_map(&mut map)?;
+                    map.end()?;
+                    return Ok(value);
+                }
+                let contents = builtin_value::<String>(object, "NS.string")
+                    .or_else(|| builtin_value::<Vec<u8>>(object, "NS.data"));
+                if let Some(contents) = contents {
+                    return NodeDeserializer(contents).deserialize_any(visitor);
+                }
+
+                let fields = object.fields.iter();
+                let mut map = MapDeserializer::new(
+                    fields.map(|(key, node)| (key.as_str(), NodeDeserializer(node))),
+                );
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            ArchiveNode::Dictionary(dict) => {
+                let mut map = MapDeserializer::new(
+                    dict.iter()
+                        .map(|(key, node)| (key.as_str(), NodeDeserializer(node))),
+                );
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            ArchiveNode::Reference(uid) => Err(Error::CyclicReference(*uid)),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            ArchiveNode::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            ArchiveNode::String(variant) => {
+                visitor.visit_enum(variant.as_str().into_deserializer())
+            }
+            _ => self.deserialize_map(EnumVisitor(visitor)),
+        }
+    }
+
+    // Ignored values are not visited, so that cyclic references in them do
+    // not fail the whole value.
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    ::serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier
+    }
+}
+
+/// Deserializes an enum from a dictionary with a single entry.
+struct EnumVisitor<V>(V);
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for EnumVisitor<V> {
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a dictionary with a single entry")
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        self.0.visit_enum(MapAccessDeserializer::new(map))
+    }
+}
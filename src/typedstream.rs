@@ -0,0 +1,877 @@
+//! Decoding of legacy archives written by `NSArchiver`, which are known as
+//! typedstream.
+//!
+//! Unlike keyed archives, values in typedstream are not associated with keys,
+//! they are decoded in the order they were encoded. Objects are decoded with
+//! the same [`Archive`](crate::Archive) implementations and [`TypeRegistry`]
+//! as keyed archives, and keys passed to the [`Decoder`] methods are only
+//! used to report errors. Types that are archived by both archivers need to
+//! decode their values in the order `NSArchiver` encodes them.
+//!
+//! Strings, numbers, data and dictionaries are decoded as built-in types
+//! ([`String`], [`i64`], [`f64`], `Vec<u8>` and [`NSDictionary`]) without
+//! registering, since their typedstream forms differ from their keyed forms.
+
+use std::cell::Cell;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::archiver::{Decoder, UnarchiveOptions};
+use crate::decoding::DecodingState;
+use crate::object::{
+    AnyObject, NSDictionary, NSMutableDictionary, ObjectRef, TypeRegistry, WeakObjectRef,
+    NS_OBJECTS_KEY,
+};
+use crate::types::Error;
+
+/// Decodes a previously-archived typedstream from a file, and returns its
+/// root object.
+pub fn from_file<P: AsRef<Path>>(path: P, registry: &TypeRegistry) -> Result<AnyObject, Error> {
+    from_file_with_options(path, registry, &UnarchiveOptions::default())
+}
+
+/// Like [`from_file`], but decodes the typedstream with the given options.
+pub fn from_file_with_options<P: AsRef<Path>>(
+    path: P,
+    registry: &TypeRegistry,
+    options: &UnarchiveOptions,
+) -> Result<AnyObject, Error> {
+    let bytes = options.read_file(path.as_ref())?;
+    from_bytes_with_options(&bytes, registry, options)
+}
+
+/// Decodes a previously-archived typedstream from a byte slice, and returns
+/// its root object.
+///
+/// The root object is the first object in the stream.
+pub fn from_bytes(bytes: &[u8], registry: &TypeRegistry) -> Result<AnyObject, Error> {
+    from_bytes_with_options(bytes, registry, &UnarchiveOptions::default())
+}
+
+/// Like [`from_bytes`], but decodes the typedstream with the given options.
+///
+/// The limits of the options apply like they do to keyed archives. The root
+/// key is not used.
+pub fn from_bytes_with_options(
+    bytes: &[u8],
+    registry: &TypeRegistry,
+    options: &UnarchiveOptions,
+) -> Result<AnyObject, Error> {
+    options.check_size(bytes.len())?;
+    let stream = Parser::new(bytes).parse()?;
+    let unarchiver = Unarchiver::new(stream, registry, options);
+    unarchiver.unarchive_root_object()
+}
+
+// Tags are stored in place of integers, they never collide with integers
+// since they are out of the range of 1-byte literals.
+const TAG_INTEGER_2: i8 = -127;
+const TAG_INTEGER_4: i8 = -126;
+const TAG_FLOATING_POINT: i8 = -125;
+const TAG_NEW: i8 = -124;
+const TAG_NIL: i8 = -123;
+const TAG_END_OF_OBJECT: i8 = -122;
+
+// References to shared strings and objects are numbered from this value.
+const FIRST_REFERENCE: i64 = -110;
+
+// The only streamer version that is produced by `NSArchiver`.
+const STREAMER_VERSION: u8 = 4;
+
+// Objects, superclasses and types nested deeper than this are rejected,
+// since they are parsed or decoded recursively.
+const MAX_DEPTH: usize = 512;
+
+/// A value decoded from a typedstream.
+#[derive(Debug)]
+enum StreamValue {
+    /// A `nil` object, class or string.
+    Null,
+    Integer(i64),
+    Real(f64),
+    /// A C string, selector or unshared string, which are usually UTF-8.
+    String(Vec<u8>),
+    /// An array of characters.
+    Data(Vec<u8>),
+    /// An object with its index in `TypedStream::objects`.
+    Object(usize),
+    /// A class, which cannot be decoded and only occupies its position.
+    Class,
+}
+
+impl StreamValue {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Self::Null => "nil",
+            Self::Integer(_) => "integer",
+            Self::Real(_) => "real",
+            Self::String(_) => "string",
+            Self::Data(_) => "data",
+            Self::Object(_) => "object",
+            Self::Class => "class",
+        }
+    }
+}
+
+struct StreamClass {
+    name: String,
+    version: i64,
+    super_class: Option<usize>,
+    // The number of classes in the chain of superclasses, including this one.
+    depth: usize,
+}
+
+struct StreamObject {
+    class: usize,
+    // Values of all groups of the object, structs and arrays are flattened.
+    values: Vec<StreamValue>,
+    // How deep decoding the object recurses into the objects it references,
+    // including this one. References to objects that are still being parsed
+    // are cyclic, and not counted.
+    depth: usize,
+}
+
+struct TypedStream {
+    classes: Vec<StreamClass>,
+    objects: Vec<StreamObject>,
+    // Top-level values of the stream.
+    values: Vec<StreamValue>,
+}
+
+impl TypedStream {
+    /// Returns the class name of a given object, followed by the names of
+    /// its superclasses.
+    fn classes_of(&self, object: &StreamObject) -> Vec<&str> {
+        let mut classes = vec![];
+        let mut class = Some(object.class);
+        while let Some(index) = class {
+            let stream_class = &self.classes[index];
+            classes.push(stream_class.name.as_str());
+            class = stream_class.super_class;
+        }
+        classes
+    }
+}
+
+/// The type of a value, parsed from an Objective-C type encoding.
+enum ValueType {
+    Char,
+    UnsignedChar,
+    Integer,
+    UnsignedInteger,
+    Float,
+    Double,
+    SharedString,
+    UnsharedString,
+    Object,
+    Class,
+    Array(usize, Box<ValueType>),
+    Struct(Vec<ValueType>),
+}
+
+fn parse_types(encoding: &[u8]) -> Option<Vec<ValueType>> {
+    let mut types = vec![];
+    let mut rest = encoding;
+    while !rest.is_empty() {
+        let (ty, next) = parse_type(rest, 1)?;
+        types.push(ty);
+        rest = next;
+    }
+    Some(types)
+}
+
+fn parse_type(encoding: &[u8], depth: usize) -> Option<(ValueType, &[u8])> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    let (first, rest) = encoding.split_first()?;
+    let ty = match first {
+        b'c' => ValueType::Char,
+        b'C' | b'B' => ValueType::UnsignedChar,
+        b's' | b'i' | b'l' | b'q' => ValueType::Integer,
+        b'S' | b'I' | b'L' | b'Q' => ValueType::UnsignedInteger,
+        b'f' => ValueType::Float,
+        b'd' => ValueType::Double,
+        b'*' | b':' => ValueType::SharedString,
+        b'+' => ValueType::UnsharedString,
+        b'@' => ValueType::Object,
+        b'#' => ValueType::Class,
+        b'[' => {
+            let digits = rest.iter().take_while(|c| c.is_ascii_digit()).count();
+            let len = std::str::from_utf8(&rest[..digits]).ok()?.parse().ok()?;
+            let (ty, rest) = parse_type(&rest[digits..], depth + 1)?;
+            let rest = rest.strip_prefix(b"]")?;
+            return Some((ValueType::Array(len, Box::new(ty)), rest));
+        }
+        b'{' => {
+            // The struct name is optional, and followed by `=` if any.
+            let name_len = rest.iter().position(|c| matches!(c, b'=' | b'}'))?;
+            let mut rest = &rest[name_len..];
+            if let Some(fields) = rest.strip_prefix(b"=") {
+                rest = fields;
+            }
+            let mut fields = vec![];
+            loop {
+                if let Some(rest) = rest.strip_prefix(b"}") {
+                    return Some((ValueType::Struct(fields), rest));
+                }
+                let (ty, next) = parse_type(rest, depth + 1)?;
+                fields.push(ty);
+                rest = next;
+            }
+        }
+        _ => return None,
+    };
+    Some((ty, rest))
+}
+
+enum TableEntry {
+    Class(usize),
+    Object(usize),
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    big_endian: bool,
+    strings: Vec<Vec<u8>>,
+    // Classes and objects share the same reference numbers.
+    entries: Vec<TableEntry>,
+    classes: Vec<StreamClass>,
+    objects: Vec<StreamObject>,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            offset: 0,
+            big_endian: false,
+            strings: vec![],
+            entries: vec![],
+            classes: vec![],
+            objects: vec![],
+            depth: 0,
+        }
+    }
+
+    fn parse(mut self) -> Result<TypedStream, Error> {
+        if self.read_u8()? != STREAMER_VERSION {
+            return Err(self.malformed());
+        }
+        let head = self.read_head()?;
+        let signature = self.read_unshared_string(head)?.unwrap_or_default();
+        self.big_endian = match signature.as_slice() {
+            b"streamtyped" => false,
+            b"typedstream" => true,
+            _ => {
+                let signature = String::from_utf8_lossy(&signature).into_owned();
+                return Err(Error::UnsupportedArchiver(signature));
+            }
+        };
+        // The system version is not used.
+        let head = self.read_head()?;
+        self.read_integer(head, true)?;
+
+        let mut values = vec![];
+        while self.offset < self.bytes.len() {
+            self.read_group(&mut values)?;
+        }
+        Ok(TypedStream {
+            classes: self.classes,
+            objects: self.objects,
+            values,
+        })
+    }
+
+    fn malformed(&self) -> Error {
+        Error::MalformedTypedStream(self.offset)
+    }
+
+    fn read_exact(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .ok_or_else(|| self.malformed())?;
+        let Some(bytes) = self.bytes.get(self.offset..end) else {
+            return Err(self.malformed());
+        };
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let bytes = self.read_exact(N)?;
+        let mut array = [0; N];
+        array.copy_from_slice(bytes);
+        if self.big_endian {
+            array.reverse();
+        }
+        Ok(array)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.read_exact(1)?[0])
+    }
+
+    fn read_head(&mut self) -> Result<i8, Error> {
+        Ok(self.read_u8()? as i8)
+    }
+
+    fn peek_head(&self) -> Option<i8> {
+        self.bytes.get(self.offset).map(|byte| *byte as i8)
+    }
+
+    fn read_integer(&mut self, head: i8, signed: bool) -> Result<i64, Error> {
+        let integer = match head {
+            TAG_INTEGER_2 => {
+                let integer = i16::from_le_bytes(self.read_array()?);
+                if signed {
+                    integer as i64
+                } else {
+                    integer as u16 as i64
+                }
+            }
+            TAG_INTEGER_4 => {
+                let integer = i32::from_le_bytes(self.read_array()?);
+                if signed {
+                    integer as i64
+                } else {
+                    integer as u32 as i64
+                }
+            }
+            TAG_FLOATING_POINT | TAG_NEW | TAG_NIL | TAG_END_OF_OBJECT => {
+                return Err(self.malformed())
+            }
+            _ if signed => head as i64,
+            _ => head as u8 as i64,
+        };
+        Ok(integer)
+    }
+
+    fn read_reference(&mut self, head: i8) -> Result<usize, Error> {
+        let reference = self.read_integer(head, true)? - FIRST_REFERENCE;
+        usize::try_from(reference).map_err(|_| self.malformed())
+    }
+
+    fn read_unshared_string(&mut self, head: i8) -> Result<Option<Vec<u8>>, Error> {
+        if head == TAG_NIL {
+            return Ok(None);
+        }
+        let len = self.read_integer(head, false)?;
+        let len = usize::try_from(len).map_err(|_| self.malformed())?;
+        Ok(Some(self.read_exact(len)?.to_owned()))
+    }
+
+    fn read_shared_string(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        match self.read_head()? {
+            TAG_NIL => Ok(None),
+            TAG_NEW => {
+                let head = self.read_head()?;
+                let string = self
+                    .read_unshared_string(head)?
+                    .ok_or_else(|| self.malformed())?;
+                self.strings.push(string.clone());
+                Ok(Some(string))
+            }
+            head => {
+                let reference = self.read_reference(head)?;
+                match self.strings.get(reference) {
+                    Some(string) => Ok(Some(string.clone())),
+                    None => Err(self.malformed()),
+                }
+            }
+        }
+    }
+
+    fn read_class(&mut self) -> Result<Option<usize>, Error> {
+        // New classes are followed by their superclasses, up to `nil` or a
+        // class that was read before. They are read in a loop rather than
+        // recursively, so that long chains cannot overflow the stack.
+        let mut new_classes = vec![];
+        let mut super_class = loop {
+            match self.read_head()? {
+                TAG_NIL => break None,
+                TAG_NEW => {
+                    if new_classes.len() >= MAX_DEPTH {
+                        return Err(self.malformed());
+                    }
+                    let name = self.read_shared_string()?.ok_or_else(|| self.malformed())?;
+                    let name = String::from_utf8(name).map_err(|_| self.malformed())?;
+                    let head = self.read_head()?;
+                    let version = self.read_integer(head, true)?;
+
+                    let index = self.classes.len();
+                    self.classes.push(StreamClass {
+                        name,
+                        version,
+                        super_class: None,
+                        depth: 0,
+                    });
+                    self.entries.push(TableEntry::Class(index));
+                    new_classes.push(index);
+                }
+                head => {
+                    let reference = self.read_reference(head)?;
+                    match self.entries.get(reference) {
+                        Some(TableEntry::Class(index)) => break Some(*index),
+                        _ => return Err(self.malformed()),
+                    }
+                }
+            }
+        };
+
+        for index in new_classes.into_iter().rev() {
+            let depth = super_class.map_or(0, |super_class| self.classes[super_class].depth) + 1;
+            if depth > MAX_DEPTH {
+                return Err(self.malformed());
+            }
+            self.classes[index].super_class = super_class;
+            self.classes[index].depth = depth;
+            super_class = Some(index);
+        }
+        Ok(super_class)
+    }
+
+    fn read_object(&mut self) -> Result<Option<usize>, Error> {
+        match self.read_head()? {
+            TAG_NIL => Ok(None),
+            TAG_NEW => {
+                if self.depth >= MAX_DEPTH {
+                    return Err(self.malformed());
+                }
+
+                // The object is numbered before its class.
+                let index = self.objects.len();
+                self.entries.push(TableEntry::Object(index));
+                let class = self.read_class()?.ok_or_else(|| self.malformed())?;
+                self.objects.push(StreamObject {
+                    class,
+                    values: vec![],
+                    depth: 0,
+                });
+
+                self.depth += 1;
+                let mut values = vec![];
+                while self.peek_head() != Some(TAG_END_OF_OBJECT) {
+                    self.read_group(&mut values)?;
+                }
+                self.offset += 1;
+                self.depth -= 1;
+
+                // Objects can reference their siblings that were read
+                // before, which are decoded recursively too.
+                let depth = values
+                    .iter()
+                    .filter_map(|value| match value {
+                        StreamValue::Object(index) => Some(self.objects[*index].depth),
+                        _ => None,
+                    })
+                    .max()
+                    .unwrap_or(0)
+                    + 1;
+                if depth > MAX_DEPTH {
+                    return Err(self.malformed());
+                }
+                self.objects[index].values = values;
+                self.objects[index].depth = depth;
+                Ok(Some(index))
+            }
+            head => {
+                let reference = self.read_reference(head)?;
+                match self.entries.get(reference) {
+                    Some(TableEntry::Object(index)) => Ok(Some(*index)),
+                    _ => Err(self.malformed()),
+                }
+            }
+        }
+    }
+
+    /// Reads a group of values, which starts with their type encoding.
+    fn read_group(&mut self, values: &mut Vec<StreamValue>) -> Result<(), Error> {
+        let encoding = self.read_shared_string()?.ok_or_else(|| self.malformed())?;
+        let types = parse_types(&encoding).ok_or_else(|| self.malformed())?;
+        for ty in &types {
+            self.read_value(ty, values)?;
+        }
+        Ok(())
+    }
+
+    fn read_value(&mut self, ty: &ValueType, values: &mut Vec<StreamValue>) -> Result<(), Error> {
+        let value = match ty {
+            ValueType::Char | ValueType::Integer => {
+                let head = self.read_head()?;
+                StreamValue::Integer(self.read_integer(head, true)?)
+            }
+            ValueType::UnsignedChar | ValueType::UnsignedInteger => {
+                let head = self.read_head()?;
+                StreamValue::Integer(self.read_integer(head, false)?)
+            }
+            ValueType::Float | ValueType::Double => match self.read_head()? {
+                TAG_FLOATING_POINT if matches!(ty, ValueType::Float) => {
+                    StreamValue::Real(f32::from_le_bytes(self.read_array()?) as f64)
+                }
+                TAG_FLOATING_POINT => StreamValue::Real(f64::from_le_bytes(self.read_array()?)),
+                head => StreamValue::Real(self.read_integer(head, true)? as f64),
+            },
+            ValueType::SharedString => match self.read_shared_string()? {
+                Some(string) => StreamValue::String(string),
+                None => StreamValue::Null,
+            },
+            ValueType::UnsharedString => {
+                let head = self.read_head()?;
+                match self.read_unshared_string(head)? {
+                    Some(string) => StreamValue::String(string),
+                    None => StreamValue::Null,
+                }
+            }
+            ValueType::Object => match self.read_object()? {
+                Some(index) => StreamValue::Object(index),
+                None => StreamValue::Null,
+            },
+            ValueType::Class => match self.read_class()? {
+                Some(_) => StreamValue::Class,
+                None => StreamValue::Null,
+            },
+            // Arrays of characters are stored as raw bytes.
+            ValueType::Array(len, ty)
+                if matches!(**ty, ValueType::Char | ValueType::UnsignedChar) =>
+            {
+                StreamValue::Data(self.read_exact(*len)?.to_owned())
+            }
+            ValueType::Array(len, ty) => {
+                for _ in 0..*len {
+                    self.read_value(ty, values)?;
+                }
+                return Ok(());
+            }
+            ValueType::Struct(fields) => {
+                for ty in fields {
+                    self.read_value(ty, values)?;
+                }
+                return Ok(());
+            }
+        };
+        values.push(value);
+        Ok(())
+    }
+}
+
+struct Unarchiver<'t> {
+    stream: TypedStream,
+    state: DecodingState<'t>,
+    // The index of the next value of the active object to decode.
+    cursor: Cell<usize>,
+}
+
+impl<'t> Unarchiver<'t> {
+    fn new(stream: TypedStream, registry: &'t TypeRegistry, options: &'t UnarchiveOptions) -> Self {
+        Self {
+            stream,
+            state: DecodingState::new(registry, options),
+            cursor: Cell::new(0),
+        }
+    }
+
+    fn unarchive_root_object(&self) -> Result<AnyObject, Error> {
+        let Some(StreamValue::Object(root_object)) = self.stream.values.first() else {
+            return Err(Error::NoRootObject);
+        };
+        self.state
+            .decode_top_object("root", || self.decode_object_at(*root_object))
+    }
+
+    fn decode_object_at(&self, index: usize) -> Result<AnyObject, Error> {
+        self.state.count_object()?;
+        self.state.decode_object(index as u64, || {
+            let last_cursor = self.cursor.replace(0);
+            let decoded_object = self.decode_active_object();
+            self.cursor.set(last_cursor);
+            decoded_object
+        })
+    }
+
+    fn decode_shared_object_at(&self, index: usize) -> Result<ObjectRef, Error> {
+        // Like in keyed archives, plain values have no identity to keep.
+        if self.is_plain_value(index) {
+            return self.decode_object_at(index).map(Rc::new);
+        }
+        self.state
+            .decode_shared_object(index as u64, || self.decode_object_at(index))
+    }
+
+    fn decode_weak_object_at(&self, index: usize) -> Result<WeakObjectRef, Error> {
+        if self.is_plain_value(index) {
+            return self.decode_object_at(index).map(|_| WeakObjectRef::new());
+        }
+        self.state
+            .decode_weak_object(index as u64, || self.decode_object_at(index))
+    }
+
+    fn is_plain_value(&self, index: usize) -> bool {
+        self.stream.objects.get(index).is_some_and(|object| {
+            let classes = self.stream.classes_of(object);
+            ["NSString", "NSData", "NSNumber"]
+                .iter()
+                .any(|class| classes.contains(class))
+        })
+    }
+
+    fn active_object(&self) -> &StreamObject {
+        &self.stream.objects[self.state.active_object() as usize]
+    }
+
+    fn decode_active_object(&self) -> Result<AnyObject, Error> {
+        let classes = self.stream.classes_of(self.active_object());
+        self.state.check_allowed_class(classes[0])?;
+        if let Some(object) = self.decode_builtin_object(&classes) {
+            return self.state.record(object);
+        }
+        self.state.decode_registered_object(&classes, self)
+    }
+
+    /// Decodes the active object if it is of a built-in class, whose
+    /// typedstream form differs from its keyed form.
+    fn decode_builtin_object(&self, classes: &[&str]) -> Option<Result<AnyObject, Error>> {
+        let object = if classes.contains(&"NSString") {
+            self.try_decode_string("NS.string").map(AnyObject::erasing)
+        } else if classes.contains(&"NSData") {
+            self.try_decode_i64("NS.length")
+                .and_then(|_| self.try_decode_bytes("NS.bytes"))
+                .map(AnyObject::erasing)
+        } else if classes.contains(&"NSNumber") {
+            // Numbers may be preceded by their type encoding.
+            let values = &self.active_object().values;
+            match values
+                .iter()
+                .find(|value| !matches!(value, StreamValue::String(_)))
+            {
+                Some(StreamValue::Integer(integer)) => Ok(AnyObject::erasing(*integer)),
+                Some(StreamValue::Real(real)) => Ok(AnyObject::erasing(*real)),
+                value => Err(Error::TypeMismatch {
+                    path: self.state.key_path("NS.number"),
+                    expected: "number",
+                    actual: value.map_or("nothing", StreamValue::type_name),
+                }),
+            }
+        } else if classes.contains(&"NSDictionary") {
+            self.decode_dictionary_entries().map(|dictionary| {
+                if classes[0] == "NSMutableDictionary" {
+                    AnyObject::erasing(NSMutableDictionary(dictionary))
+                } else {
+                    AnyObject::erasing(dictionary)
+                }
+            })
+        } else {
+            return None;
+        };
+        Some(object)
+    }
+
+    /// Decodes the entries of a dictionary, which are archived as a count
+    /// followed by pairs of keys and objects.
+    fn decode_dictionary_entries(&self) -> Result<NSDictionary, Error> {
+        let count = self.try_decode_i64("NS.count")?;
+        let mut dictionary = NSDictionary::new();
+        for index in 0..count {
+            let key = self.state.with_path_segment(format!("[{index}]"), || {
+                self.try_decode_shared_object("NS.key")
+            })?;
+            let object = self.state.with_path_segment(format!("[{index}]"), || {
+                self.try_decode_shared_object("NS.object")
+            })?;
+            dictionary.insert_shared(key, object);
+        }
+        Ok(dictionary)
+    }
+
+    /// Returns the next value of the active object, the key is only used to
+    /// report errors.
+    fn next_value(&self, key: &str) -> Result<&StreamValue, Error> {
+        let cursor = self.cursor.get();
+        let Some(value) = self.active_object().values.get(cursor) else {
+            return Err(Error::MissingKey {
+                path: self.state.key_path(key),
+            });
+        };
+        self.cursor.set(cursor + 1);
+        Ok(value)
+    }
+
+    /// Decodes a list of objects, which are archived as a count followed by
+    /// the objects, each with the given function.
+    fn decode_elements<T, F>(&self, key: &str, decode_f: F) -> Result<Vec<T>, Error>
+    where
+        F: Fn(usize) -> Result<T, Error>,
+    {
+        let count = self.try_decode_i64(key)?;
+
+        // Elements of collection classes are addressed like `.Files[3]`
+        // instead of `.Files.NS.objects[3]`.
+        let prefix = if key == NS_OBJECTS_KEY {
+            String::new()
+        } else {
+            format!(".{key}")
+        };
+        let decoded_objects = (0..count)
+            .map(|index| {
+                self.state
+                    .with_path_segment(format!("{prefix}[{index}]"), || {
+                        let index = self.next_object(key)?;
+                        decode_f(index)
+                    })
+            })
+            .collect();
+        self.state.record(decoded_objects)
+    }
+
+    fn next_object(&self, key: &str) -> Result<usize, Error> {
+        match self.next_value(key)? {
+            StreamValue::Object(index) => Ok(*index),
+            value => Err(self.type_mismatch(key, "object", value)),
+        }
+    }
+
+    fn type_mismatch(&self, key: &str, expected: &'static str, actual: &StreamValue) -> Error {
+        Error::TypeMismatch {
+            path: self.state.key_path(key),
+            expected,
+            actual: actual.type_name(),
+        }
+    }
+}
+
+impl<'t> Decoder for Unarchiver<'t> {
+    /// Returns `true` if the active object has values left to decode, since
+    /// values are not associated with keys in typedstream.
+    fn contains_key(&self, _key: &str) -> bool {
+        self.cursor.get() < self.active_object().values.len()
+    }
+
+    /// Returns no keys, since values are not associated with keys in
+    /// typedstream.
+    fn keys(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn class_version(&self, class_name: &str) -> Option<i64> {
+        self.stream
+            .classes
+            .iter()
+            .find(|class| class.name == class_name)
+            .map(|class| class.version)
+    }
+
+    fn try_decode_i64(&self, key: &str) -> Result<i64, Error> {
+        self.state
+            .record(self.next_value(key).and_then(|value| match value {
+                StreamValue::Integer(integer) => Ok(*integer),
+                value => Err(self.type_mismatch(key, "integer", value)),
+            }))
+    }
+
+    fn try_decode_bool(&self, key: &str) -> Result<bool, Error> {
+        self.state
+            .record(self.next_value(key).and_then(|value| match value {
+                StreamValue::Integer(integer) => Ok(*integer != 0),
+                value => Err(self.type_mismatch(key, "boolean", value)),
+            }))
+    }
+
+    fn try_decode_f64(&self, key: &str) -> Result<f64, Error> {
+        self.state
+            .record(self.next_value(key).and_then(|value| match value {
+                StreamValue::Real(real) => Ok(*real),
+                StreamValue::Integer(integer) => Ok(*integer as f64),
+                value => Err(self.type_mismatch(key, "real", value)),
+            }))
+    }
+
+    fn try_decode_string(&self, key: &str) -> Result<String, Error> {
+        let string = self.next_value(key).and_then(|value| match value {
+            StreamValue::String(string) => {
+                self.state
+                    .check_length(string.len(), || self.state.key_path(key))?;
+                String::from_utf8(string.clone())
+                    .map_err(|_| self.type_mismatch(key, "string", &StreamValue::Data(vec![])))
+            }
+            // Strings can also be archived as `NSString` objects.
+            StreamValue::Object(index) => {
+                let object = self
+                    .state
+                    .with_path_segment(format!(".{key}"), || self.decode_object_at(*index))?;
+                match object.downcast::<String>() {
+                    Ok(string) => Ok(*string),
+                    Err(_) => Err(self.type_mismatch(key, "string", value)),
+                }
+            }
+            value => Err(self.type_mismatch(key, "string", value)),
+        });
+        self.state.record(string)
+    }
+
+    fn try_decode_bytes(&self, key: &str) -> Result<Vec<u8>, Error> {
+        let bytes = self.next_value(key).and_then(|value| match value {
+            StreamValue::Data(data) => {
+                self.state
+                    .check_length(data.len(), || self.state.key_path(key))?;
+                Ok(data.clone())
+            }
+            // Data can also be archived as `NSData` objects.
+            StreamValue::Object(index) => {
+                let object = self
+                    .state
+                    .with_path_segment(format!(".{key}"), || self.decode_object_at(*index))?;
+                match object.downcast::<Vec<u8>>() {
+                    Ok(data) => Ok(*data),
+                    Err(_) => Err(self.type_mismatch(key, "data", value)),
+                }
+            }
+            value => Err(self.type_mismatch(key, "data", value)),
+        });
+        self.state.record(bytes)
+    }
+
+    fn try_decode_object(&self, key: &str) -> Result<AnyObject, Error> {
+        self.state.record(self.next_object(key).and_then(|index| {
+            self.state
+                .with_path_segment(format!(".{key}"), || self.decode_object_at(index))
+        }))
+    }
+
+    fn try_decode_object_of_classes(
+        &self,
+        classes: &[&str],
+        key: &str,
+    ) -> Result<AnyObject, Error> {
+        self.state
+            .with_allowed_classes(classes, || self.try_decode_object(key))
+    }
+
+    fn try_decode_shared_object(&self, key: &str) -> Result<ObjectRef, Error> {
+        self.state.record(self.next_object(key).and_then(|index| {
+            self.state
+                .with_path_segment(format!(".{key}"), || self.decode_shared_object_at(index))
+        }))
+    }
+
+    fn try_decode_weak_object(&self, key: &str) -> Result<WeakObjectRef, Error> {
+        self.state.record(self.next_object(key).and_then(|index| {
+            self.state
+                .with_path_segment(format!(".{key}"), || self.decode_weak_object_at(index))
+        }))
+    }
+
+    /// Decodes a list of objects, which are archived as a count followed by
+    /// the objects, like `NSArray` and `NSSet` do.
+    fn try_decode_objects(&self, key: &str) -> Result<Vec<AnyObject>, Error> {
+        self.decode_elements(key, |index| self.decode_object_at(index))
+    }
+
+    /// Decodes a list of shared objects, archived like the ones of
+    /// [`Decoder::try_decode_objects`].
+    fn try_decode_shared_objects(&self, key: &str) -> Result<Vec<ObjectRef>, Error> {
+        self.decode_elements(key, |index| self.decode_shared_object_at(index))
+    }
+}
@@ -1,27 +1,357 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::io::{Cursor, Write};
 
-use plist::{Uid as PlistUid, Value as PlistValue};
+use plist::{Dictionary as PlistDictionary, Uid as PlistUid, Value as PlistValue};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+// Non-keyed archivers are not supported since `NSArchiver` is deprecated
+// for better forward and backward compatibility.
+pub const KEYED_ARCHIVER_CLASS_NAME: &str = "NSKeyedArchiver";
+
+// The key of an object that references its class info.
+pub const CLASS_KEY: &str = "$class";
+
+// The key of the root object in `$top`.
+pub const ROOT_KEY: &str = "root";
+
+/// The raw structure of a keyed archive, whose objects are flattened into
+/// a table and reference each other by UIDs.
+///
+/// This is useful for inspecting or repairing archives without decoding
+/// them into Rust types. See [`ArchiveNode`](crate::ArchiveNode) for a
+/// resolved view of the object graph.
 #[derive(Deserialize, Serialize, Debug)]
 pub struct ArchiveDict {
+    /// The class name of the archiver, which is `NSKeyedArchiver` for all
+    /// supported archives.
     #[serde(rename = "$archiver")]
     pub archiver_class_name: String,
+    /// The object table, whose first object is always `$null`.
     #[serde(rename = "$objects")]
     pub objects: Vec<PlistValue>,
+    /// The UIDs of the top-level objects by their keys, which are sorted so
+    /// that archives are written deterministically.
     #[serde(rename = "$top")]
-    pub top_objects: HashMap<String, PlistUid>,
+    pub top_objects: BTreeMap<String, PlistUid>,
+    /// The version of the archive format.
     #[serde(rename = "$version")]
     pub version: u32,
 }
 
+// XML property lists have no UID type, they are represented as dictionaries
+// like `<dict><key>CF$UID</key><integer>1</integer></dict>` instead.
+const XML_UID_KEY: &str = "CF$UID";
+
+impl ArchiveDict {
+    /// Reads an archive from a byte slice of a binary or XML property list.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        // Binary property lists can be deserialized directly.
+        if bytes.starts_with(b"bplist") {
+            return Ok(plist::from_bytes(bytes)?);
+        }
+        let value = PlistValue::from_reader(Cursor::new(bytes))?;
+        Self::from_plist_value(&value)
+    }
+
+    /// Reads an archive from a property list value.
+    ///
+    /// UIDs represented as `CF$UID` dictionaries (like in XML property lists)
+    /// are restored.
+    pub fn from_plist_value(value: &PlistValue) -> Result<Self, Error> {
+        let mut value = value.clone();
+        restore_xml_uids(&mut value);
+        Ok(plist::from_value(&value)?)
+    }
+
+    /// Validates that the archive is produced by a supported archiver.
+    pub fn validate_archiver(&self) -> Result<(), Error> {
+        if self.archiver_class_name != KEYED_ARCHIVER_CLASS_NAME {
+            return Err(Error::UnsupportedArchiver(self.archiver_class_name.clone()));
+        }
+        Ok(())
+    }
+
+    /// Validates the archiver class, and returns the UID of the root object.
+    pub fn root_object(&self) -> Result<PlistUid, Error> {
+        self.top_object(ROOT_KEY)
+    }
+
+    /// Validates the archiver class, and returns the UID of the top-level
+    /// object with the given key.
+    pub fn top_object(&self, key: &str) -> Result<PlistUid, Error> {
+        self.validate_archiver()?;
+        let Some(object) = self.top_objects.get(key) else {
+            return Err(Error::NoRootObject);
+        };
+        Ok(*object)
+    }
+
+    /// Returns the class name of a given object, followed by the names of
+    /// its superclasses (if any).
+    pub fn classes_of(&self, object: &PlistDictionary) -> Result<Vec<&str>, Error> {
+        let Some(class) = object.get(CLASS_KEY).and_then(|value| value.as_uid()) else {
+            return Err(Error::MalformedObject);
+        };
+        self.objects
+            .get(class.get() as usize)
+            .and_then(|value| value.as_dictionary())
+            .and_then(classes_of_class_info)
+            .ok_or(Error::MalformedObject)
+    }
+
+    /// Validates the whole structure of the archive, including the archiver
+    /// class, the UIDs of all references and the class info of all objects.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.validate_archiver()?;
+        if self.objects.first().and_then(|value| value.as_string()) != Some("$null") {
+            return Err(Error::InvalidObject {
+                uid: 0,
+                reason: "expected `$null`",
+            });
+        }
+        for uid in self.top_objects.values() {
+            if uid.get() == 0 || uid.get() as usize >= self.objects.len() {
+                return Err(Error::NoRootObject);
+            }
+        }
+
+        for (uid, value) in self.objects.iter().enumerate() {
+            let invalid = |reason| Error::InvalidObject {
+                uid: uid as u64,
+                reason,
+            };
+            if !references_of(value).all(|r| (r as usize) < self.objects.len()) {
+                return Err(invalid("references a nonexistent object"));
+            }
+            let Some(dict) = value.as_dictionary() else {
+                continue;
+            };
+            // Class info has no class.
+            if dict.contains_key("$classname") {
+                continue;
+            }
+            self.classes_of(dict)
+                .map_err(|_| invalid("has malformed class info"))?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites the archive into its canonical form, so that equal object
+    /// graphs are always written byte for byte the same.
+    ///
+    /// The keys of all dictionaries are sorted, and objects are renumbered
+    /// in breadth-first order from the top-level objects (in the order of
+    /// their keys). The class info of an object comes before the other
+    /// objects it references. Objects that are not reachable are removed,
+    /// and dangling references are kept dangling.
+    pub fn canonicalize(&mut self) {
+        if self.objects.is_empty() {
+            return;
+        }
+        self.objects.iter_mut().for_each(sort_keys);
+
+        let mut order = vec![0];
+        let mut ordered = vec![false; self.objects.len()];
+        ordered[0] = true;
+        let mut push = |uid: u64, order: &mut Vec<usize>| {
+            let uid = uid as usize;
+            if uid < ordered.len() && !ordered[uid] {
+                ordered[uid] = true;
+                order.push(uid);
+            }
+        };
+        for uid in self.top_objects.values() {
+            push(uid.get(), &mut order);
+        }
+        // The order doubles as the queue of the breadth-first traversal.
+        let mut index = 0;
+        while let Some(uid) = order.get(index).copied() {
+            index += 1;
+            let value = &self.objects[uid];
+            let class = value
+                .as_dictionary()
+                .and_then(|dict| dict.get(CLASS_KEY))
+                .and_then(PlistValue::as_uid)
+                .map(|class| class.get());
+            for reference in class.into_iter().chain(references_of(value)) {
+                push(reference, &mut order);
+            }
+        }
+        self.renumber_objects(&order);
+    }
+
+    /// Keeps the objects with the given UIDs in the given order, and updates
+    /// all references to them. The objects that are kept must not reference
+    /// the others.
+    pub(crate) fn renumber_objects(&mut self, order: &[usize]) {
+        let mut uids = vec![None; self.objects.len()];
+        for (new_uid, uid) in order.iter().enumerate() {
+            uids[*uid] = Some(PlistUid::new(new_uid as _));
+        }
+        // Dangling references are moved past the kept objects, so that they
+        // stay dangling (and distinct) rather than referencing unrelated
+        // objects, and are still reported by `validate`.
+        let dangling_offset = (self.objects.len() - order.len()) as u64;
+        let mut remap = |uid: PlistUid| match uids.get(uid.get() as usize) {
+            Some(new_uid) => new_uid.expect("expected referenced objects to be kept"),
+            None => PlistUid::new(uid.get() - dangling_offset),
+        };
+        self.objects = order
+            .iter()
+            .map(|uid| map_references(&self.objects[*uid], &mut remap))
+            .collect();
+        for uid in self.top_objects.values_mut() {
+            *uid = remap(*uid);
+        }
+    }
+
+    /// Converts the archive to a property list value.
+    pub fn to_plist_value(&self) -> Result<PlistValue, Error> {
+        Ok(plist::to_value(self)?)
+    }
+
+    /// Writes the archive to a writer in the given format.
+    pub fn to_writer<W: Write>(&self, writer: W, format: OutputFormat) -> Result<(), Error> {
+        match format {
+            OutputFormat::Binary => plist::to_writer_binary(writer, self)?,
+            OutputFormat::Xml => {
+                let mut value = self.to_plist_value()?;
+                replace_xml_uids(&mut value);
+                value.to_writer_xml(writer)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the class name described by a class info dictionary, followed by
+/// the names of its superclasses (if any).
+pub(crate) fn classes_of_class_info(class_info: &PlistDictionary) -> Option<Vec<&str>> {
+    let class_name = class_info
+        .get("$classname")
+        .and_then(|value| value.as_string())?;
+
+    let mut classes = vec![class_name];
+    if let Some(super_classes) = class_info.get("$classes").and_then(|v| v.as_array()) {
+        classes.extend(
+            super_classes
+                .iter()
+                .filter_map(|value| value.as_string())
+                .skip_while(|class| *class == class_name),
+        );
+    }
+    Some(classes)
+}
+
+/// Returns the UIDs referenced by a value, including those in collections.
+pub(crate) fn references_of(value: &PlistValue) -> Box<dyn Iterator<Item = u64> + '_> {
+    match value {
+        PlistValue::Uid(uid) => Box::new(std::iter::once(uid.get())),
+        PlistValue::Array(array) => Box::new(array.iter().flat_map(references_of)),
+        PlistValue::Dictionary(dict) => Box::new(dict.values().flat_map(references_of)),
+        _ => Box::new(std::iter::empty()),
+    }
+}
+
+/// Returns the name of the type of a value, for error messages.
+pub(crate) fn value_type_name(value: &PlistValue) -> &'static str {
+    match value {
+        PlistValue::Array(_) => "array",
+        PlistValue::Dictionary(_) => "dictionary",
+        PlistValue::Boolean(_) => "boolean",
+        PlistValue::Data(_) => "data",
+        PlistValue::Date(_) => "date",
+        PlistValue::Real(_) => "real",
+        PlistValue::Integer(_) => "integer",
+        PlistValue::String(_) => "string",
+        PlistValue::Uid(_) => "reference",
+        _ => "unknown",
+    }
+}
+
+/// Returns a copy of a value, with every reference in it replaced.
+pub(crate) fn map_references(
+    value: &PlistValue,
+    f: &mut dyn FnMut(PlistUid) -> PlistUid,
+) -> PlistValue {
+    match value {
+        PlistValue::Uid(uid) => PlistValue::Uid(f(*uid)),
+        PlistValue::Array(array) => {
+            PlistValue::Array(array.iter().map(|value| map_references(value, f)).collect())
+        }
+        PlistValue::Dictionary(dict) => PlistValue::Dictionary(
+            dict.iter()
+                .map(|(key, value)| (key.clone(), map_references(value, f)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+fn sort_keys(value: &mut PlistValue) {
+    match value {
+        PlistValue::Array(array) => array.iter_mut().for_each(sort_keys),
+        PlistValue::Dictionary(dict) => {
+            dict.sort_keys();
+            dict.values_mut().for_each(sort_keys);
+        }
+        _ => (),
+    }
+}
+
+fn restore_xml_uids(value: &mut PlistValue) {
+    match value {
+        PlistValue::Array(array) => array.iter_mut().for_each(restore_xml_uids),
+        PlistValue::Dictionary(dict) => {
+            let uid = match dict.get(XML_UID_KEY) {
+                Some(uid) if dict.len() == 1 => uid.as_unsigned_integer(),
+                _ => None,
+            };
+            match uid {
+                Some(uid) => *value = PlistValue::Uid(PlistUid::new(uid)),
+                None => dict.values_mut().for_each(restore_xml_uids),
+            }
+        }
+        _ => (),
+    }
+}
+
+fn replace_xml_uids(value: &mut PlistValue) {
+    match value {
+        PlistValue::Array(array) => array.iter_mut().for_each(replace_xml_uids),
+        PlistValue::Dictionary(dict) => dict.values_mut().for_each(replace_xml_uids),
+        PlistValue::Uid(uid) => {
+            let mut dict = PlistDictionary::new();
+            dict.insert(
+                XML_UID_KEY.to_owned(),
+                PlistValue::Integer(uid.get().into()),
+            );
+            *value = PlistValue::Dictionary(dict);
+        }
+        _ => (),
+    }
+}
+
+/// Formats of the archive data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The binary property list format, which is what `NSKeyedArchiver`
+    /// produces by default.
+    #[default]
+    Binary,
+    /// The XML property list format, which is human-readable.
+    Xml,
+}
+
 /// A type represents all possible errors that can occur when using this crate.
 #[allow(missing_docs)]
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("archive data is malformed")]
     MalformedData(#[from] plist::Error),
+    #[error("failed to access archive data")]
+    Io(#[from] std::io::Error),
     #[error("archiver `{0}` is not supported")]
     UnsupportedArchiver(String),
     #[error("root object is not found")]
@@ -30,4 +360,87 @@ pub enum Error {
     MalformedObject,
     #[error("decoding class `{0}` is unknown, did you forget to register?")]
     UnknownClass(String),
+    #[error("object `{0}` references itself, cycles need to be broken by weak references")]
+    CyclicReference(u64),
+    #[error("value of `{path}` is not found")]
+    MissingKey { path: String },
+    #[error("value of `{path}` is expected to be {expected}, but found {actual}")]
+    TypeMismatch {
+        path: String,
+        expected: &'static str,
+        actual: &'static str,
+    },
+    #[error("object `{path}` of class `{class}` failed to decode")]
+    DecodeFailed { path: String, class: String },
+    #[error("class `{class}` is not allowed at `{path}`")]
+    DisallowedClass { path: String, class: String },
+    #[error("typedstream data is malformed at offset {0}")]
+    MalformedTypedStream(usize),
+    #[error("NIB archive is malformed at offset {0}")]
+    MalformedNibArchive(usize),
+    #[error("object {uid} of the archive is invalid: {reason}")]
+    InvalidObject { uid: u64, reason: &'static str },
+    #[error("JSON data is malformed: {0}")]
+    Json(String),
+    #[error("key path `{path}` is invalid")]
+    InvalidKeyPath { path: String },
+    #[error("JSON value `{path}` is malformed")]
+    MalformedJson { path: String },
+    #[error("{0}")]
+    Serde(String),
+    #[error("object `{path}` is nested too deeply")]
+    DepthLimitExceeded { path: String },
+    #[error("more than {0} objects are decoded")]
+    ObjectLimitExceeded(usize),
+    #[error("value `{path}` is longer than {max_length} bytes")]
+    LengthLimitExceeded { path: String, max_length: usize },
+    #[error("archive data is larger than {0} bytes")]
+    SizeLimitExceeded(usize),
+}
+
+impl Error {
+    /// Duplicates the error if it can occur while decoding objects.
+    pub(crate) fn try_clone(&self) -> Option<Self> {
+        let error = match self {
+            Self::MalformedData(_) | Self::Io(_) => return None,
+            Self::UnsupportedArchiver(name) => Self::UnsupportedArchiver(name.clone()),
+            Self::NoRootObject => Self::NoRootObject,
+            Self::MalformedObject => Self::MalformedObject,
+            Self::UnknownClass(name) => Self::UnknownClass(name.clone()),
+            Self::CyclicReference(uid) => Self::CyclicReference(*uid),
+            Self::MissingKey { path } => Self::MissingKey { path: path.clone() },
+            Self::TypeMismatch {
+                path,
+                expected,
+                actual,
+            } => Self::TypeMismatch {
+                path: path.clone(),
+                expected,
+                actual,
+            },
+            Self::DecodeFailed { path, class } => Self::DecodeFailed {
+                path: path.clone(),
+                class: class.clone(),
+            },
+            Self::DisallowedClass { path, class } => Self::DisallowedClass {
+                path: path.clone(),
+                class: class.clone(),
+            },
+            Self::MalformedTypedStream(offset) => Self::MalformedTypedStream(*offset),
+            Self::MalformedNibArchive(offset) => Self::MalformedNibArchive(*offset),
+            Self::InvalidObject { uid, reason } => Self::InvalidObject { uid: *uid, reason },
+            Self::InvalidKeyPath { path } => Self::InvalidKeyPath { path: path.clone() },
+            Self::Json(message) => Self::Json(message.clone()),
+            Self::MalformedJson { path } => Self::MalformedJson { path: path.clone() },
+            Self::Serde(message) => Self::Serde(message.clone()),
+            Self::DepthLimitExceeded { path } => Self::DepthLimitExceeded { path: path.clone() },
+            Self::ObjectLimitExceeded(max_objects) => Self::ObjectLimitExceeded(*max_objects),
+            Self::LengthLimitExceeded { path, max_length } => Self::LengthLimitExceeded {
+                path: path.clone(),
+                max_length: *max_length,
+            },
+            Self::SizeLimitExceeded(max_size) => Self::SizeLimitExceeded(*max_size),
+        };
+        Some(error)
+    }
 }
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use plist::{Uid as PlistUid, Value as PlistValue};
+
+use crate::object::ObjectRef;
+use crate::types::{map_references, ArchiveDict};
+
+/// Values of an archived object that were not read while decoding it.
+///
+/// A type that only understands some keys of its archived objects can keep
+/// the rest with [`Decoder::unknown_fields`], and write them back with
+/// [`Encoder::encode_unknown_fields`] when it is encoded again. Values are
+/// carried verbatim, including the objects they reference, so archives of
+/// newer versions of a format survive a round trip through older code.
+///
+/// References to objects that are decoded anyway keep pointing to them when
+/// the values are written back, rather than to copies. This covers the
+/// object being decoded and the objects it is nested in (like a parent
+/// pointer), the objects of its known values, and shared objects (see
+/// [`Decoder::decode_shared_object`]). A reference to an enclosing object
+/// is written back as `nil` if the object is not nested the same way when
+/// it is encoded again.
+///
+/// [`Decoder::unknown_fields`]: crate::Decoder::unknown_fields
+/// [`Decoder::decode_shared_object`]: crate::Decoder::decode_shared_object
+/// [`Encoder::encode_unknown_fields`]: crate::Encoder::encode_unknown_fields
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UnknownFields {
+    fields: Vec<(String, PlistValue)>,
+    // Objects referenced by the fields, with UIDs local to this table. Like
+    // in archives, UID 0 is always `$null`.
+    objects: Vec<PlistValue>,
+    // Objects that are decoded anyway, by their local UIDs. The copies of
+    // known values are only written back if they are not encoded again.
+    aliases: Vec<(u64, Alias)>,
+}
+
+/// An object referenced by unknown fields, which is also decoded by other
+/// means.
+#[derive(Clone, Debug)]
+pub(crate) enum Alias {
+    /// The object being decoded (0), or the object it is nested in at the
+    /// given number of levels up.
+    Ancestor(usize),
+    /// The object of a known value of the object being decoded, or of an
+    /// element of it.
+    Value(String, Option<usize>),
+    /// A shared object.
+    Shared(ObjectRef),
+}
+
+impl PartialEq for Alias {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Ancestor(a), Self::Ancestor(b)) => a == b,
+            (Self::Value(a, i), Self::Value(b, j)) => a == b && i == j,
+            (Self::Shared(a), Self::Shared(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl UnknownFields {
+    /// Copies the given values out of an archive, along with all the objects
+    /// reachable from them, except the ones that `alias_of` returns an alias
+    /// for.
+    pub(crate) fn capture<'a, I, F>(dict: &ArchiveDict, fields: I, alias_of: F) -> Self
+    where
+        I: IntoIterator<Item = (&'a String, &'a PlistValue)>,
+        F: Fn(u64) -> Option<Alias>,
+    {
+        let mut copier = Copier {
+            object_count: dict.objects.len(),
+            uids: HashMap::from([(0, PlistUid::new(0))]),
+            objects: vec![PlistValue::String("$null".to_owned())],
+            pending: vec![],
+        };
+        let fields = fields
+            .into_iter()
+            .map(|(key, value)| {
+                let value = map_references(value, &mut |uid| copier.local_uid(uid));
+                (key.clone(), value)
+            })
+            .collect();
+
+        let mut aliases = vec![];
+        while let Some((uid, local_uid)) = copier.pending.pop() {
+            let alias = alias_of(uid);
+            // Enclosing and shared objects can always be found when they
+            // are encoded again, they are not copied.
+            let copied = !matches!(alias, Some(Alias::Ancestor(_) | Alias::Shared(_)));
+            if let Some(alias) = alias {
+                aliases.push((local_uid.get(), alias));
+            }
+            if !copied {
+                continue;
+            }
+            let value = map_references(&dict.objects[uid as usize], &mut |uid| {
+                copier.local_uid(uid)
+            });
+            copier.objects[local_uid.get() as usize] = value;
+        }
+        aliases.sort_by_key(|(local_uid, _)| *local_uid);
+
+        Self {
+            fields,
+            objects: copier.objects,
+            aliases,
+        }
+    }
+
+    /// Returns `true` if there are no unknown values.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Returns the number of unknown values.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Returns the keys of the unknown values, in the order they were
+    /// archived.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.fields.iter().map(|(key, _)| key.as_str())
+    }
+
+    /// Returns `true` if there is an unknown value associated with a given
+    /// key.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.keys().any(|k| k == key)
+    }
+
+    pub(crate) fn fields(&self) -> &[(String, PlistValue)] {
+        &self.fields
+    }
+
+    pub(crate) fn objects(&self) -> &[PlistValue] {
+        &self.objects
+    }
+
+    pub(crate) fn alias_of(&self, local_uid: u64) -> Option<&Alias> {
+        self.aliases
+            .binary_search_by_key(&local_uid, |(uid, _)| *uid)
+            .ok()
+            .map(|index| &self.aliases[index].1)
+    }
+}
+
+struct Copier {
+    object_count: usize,
+    // Local UIDs of the archived objects that have been copied.
+    uids: HashMap<u64, PlistUid>,
+    objects: Vec<PlistValue>,
+    // Objects that have a local UID, but are not copied yet.
+    pending: Vec<(u64, PlistUid)>,
+}
+
+impl Copier {
+    fn local_uid(&mut self, uid: PlistUid) -> PlistUid {
+        // Dangling references are an error of the archive itself, they are
+        // kept as `$null` rather than failing the decoding.
+        if uid.get() as usize >= self.object_count {
+            return PlistUid::new(0);
+        }
+        if let Some(local_uid) = self.uids.get(&uid.get()) {
+            return *local_uid;
+        }
+        let local_uid = PlistUid::new(self.objects.len() as _);
+        self.objects.push(PlistValue::Boolean(false));
+        self.uids.insert(uid.get(), local_uid);
+        self.pending.push((uid.get(), local_uid));
+        local_uid
+    }
+}
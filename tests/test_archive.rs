@@ -1,4 +1,9 @@
-use nscoder::{Archive, Decoder, Encoder, TypeRegistry};
+use std::rc::Rc;
+
+use nscoder::{
+    AnyObject, Archive, ArchiveDict, ArchiveNode, ArchiveOptions, Decoder, Encoder, Error, NSArray,
+    ObjectRef, OutputFormat, TypeRegistry, UnarchiveOptions, UnknownFields,
+};
 
 #[derive(Debug)]
 struct Person {
@@ -55,3 +60,782 @@ fn test_archive() {
     assert_eq!(decoded_person.first_name, "Cyan");
     assert_eq!(decoded_person.last_name, "Yang");
 }
+
+#[derive(Debug)]
+struct Preferences {
+    enabled: bool,
+    opacity: f32,
+    scale: f64,
+    token: Vec<u8>,
+}
+
+impl Archive for Preferences {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDPreferences"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        archiver.encode_bool(self.enabled, "Enabled");
+        archiver.encode_f32(self.opacity, "Opacity");
+        archiver.encode_f64(self.scale, "Scale");
+        archiver.encode_bytes(&self.token, "Token");
+    }
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        let enabled = unarchiver.decode_bool("Enabled");
+        let opacity = unarchiver.decode_f32("Opacity");
+        let scale = unarchiver.decode_f64("Scale");
+        let token = unarchiver.decode_bytes("Token")?;
+        Some(Preferences {
+            enabled,
+            opacity,
+            scale,
+            token,
+        })
+    }
+}
+
+#[test]
+fn test_archive_primitives() {
+    let preferences = Preferences {
+        enabled: true,
+        opacity: 0.5,
+        scale: 2.25,
+        token: vec![0xde, 0xad, 0xbe, 0xef],
+    };
+
+    let encoded_bytes = nscoder::to_bytes(&preferences).expect("should encode successfully");
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Preferences>();
+
+    let object =
+        nscoder::from_bytes(&encoded_bytes, &registry).expect("should decode successfully");
+    let decoded_preferences: &Preferences = object
+        .downcast_ref()
+        .expect("type of the value should be `Preferences`");
+
+    assert!(decoded_preferences.enabled);
+    assert_eq!(decoded_preferences.opacity, 0.5);
+    assert_eq!(decoded_preferences.scale, 2.25);
+    assert_eq!(decoded_preferences.token, [0xde, 0xad, 0xbe, 0xef]);
+}
+
+#[test]
+fn test_archive_uniquing() {
+    let people: Vec<_> = (0..100)
+        .map(|age| {
+            AnyObject::erasing(Person {
+                age,
+                first_name: "Cyan".to_owned(),
+                last_name: "Yang".to_owned(),
+            })
+        })
+        .collect();
+    let array = NSArray::new(people);
+
+    let encoded_bytes = nscoder::to_bytes(&array).expect("should encode successfully");
+
+    // `$null`, the array, its class info, the people, their class info and
+    // the two distinct strings.
+    let archive = plist::Value::from_reader(std::io::Cursor::new(&encoded_bytes))
+        .expect("should be a valid plist");
+    let objects = archive.as_dictionary().unwrap()["$objects"]
+        .as_array()
+        .unwrap();
+    assert_eq!(objects.len(), 1 + 2 + 100 + 1 + 2);
+}
+
+#[derive(Debug)]
+struct Pair {
+    first: ObjectRef,
+    second: ObjectRef,
+}
+
+impl Archive for Pair {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDPair"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        archiver.encode_shared_object(&self.first, "First");
+        archiver.encode_shared_object(&self.second, "Second");
+    }
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        let first = unarchiver.decode_shared_object("First")?;
+        let second = unarchiver.decode_shared_object("Second")?;
+        Some(Pair { first, second })
+    }
+}
+
+#[test]
+fn test_archive_shared_object() {
+    let person = Rc::new(AnyObject::erasing(Person {
+        age: 26,
+        first_name: "Cyan".to_owned(),
+        last_name: "Yang".to_owned(),
+    }));
+    let pair = Pair {
+        first: Rc::clone(&person),
+        second: person,
+    };
+
+    let encoded_bytes = nscoder::to_bytes(&pair).expect("should encode successfully");
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Pair>();
+    registry.register_type::<Person>();
+
+    let object =
+        nscoder::from_bytes(&encoded_bytes, &registry).expect("should decode successfully");
+    let decoded_pair: &Pair = object
+        .downcast_ref()
+        .expect("type of the value should be `Pair`");
+
+    assert!(Rc::ptr_eq(&decoded_pair.first, &decoded_pair.second));
+}
+
+#[derive(Debug)]
+struct Twins {
+    person: AnyObject,
+}
+
+impl Archive for Twins {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDTwins"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        archiver.encode_object(&self.person, "First");
+        archiver.encode_objects(std::slice::from_ref(&self.person), "Others");
+    }
+
+    fn decode(_unarchiver: &dyn Decoder) -> Option<Self> {
+        None
+    }
+}
+
+#[test]
+fn test_archive_object_identity() {
+    let twins = Twins {
+        person: AnyObject::erasing(Person {
+            age: 26,
+            first_name: "Cyan".to_owned(),
+            last_name: "Yang".to_owned(),
+        }),
+    };
+
+    // The same object is encoded twice, but archived once.
+    let encoded_bytes = nscoder::to_bytes(&twins).expect("should encode successfully");
+    let root = ArchiveNode::from_bytes(&encoded_bytes).expect("should decode successfully");
+    let root = root.as_object().expect("root should be an object");
+    let (Some(ArchiveNode::Object(first)), Some([ArchiveNode::Object(other)])) = (
+        root.get("First"),
+        root.get("Others").and_then(ArchiveNode::as_array),
+    ) else {
+        panic!("people should be objects");
+    };
+    assert!(Rc::ptr_eq(first, other));
+}
+
+#[test]
+fn test_archive_output_formats() {
+    let person = Person {
+        age: 26,
+        first_name: "Cyan".to_owned(),
+        last_name: "Yang".to_owned(),
+    };
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Person>();
+    let assert_person = |object: AnyObject| {
+        let decoded_person: &Person = object
+            .downcast_ref()
+            .expect("type of the value should be `Person`");
+        assert_eq!(decoded_person.first_name, "Cyan");
+    };
+
+    let mut xml = Vec::new();
+    nscoder::to_writer(&mut xml, &person, OutputFormat::Xml).expect("should encode successfully");
+    assert!(xml.starts_with(b"<?xml"));
+    assert_person(nscoder::from_bytes(&xml, &registry).expect("should decode successfully"));
+
+    let value = nscoder::to_plist_value(&person).expect("should encode successfully");
+    assert_person(
+        nscoder::from_plist_value(&value, &registry).expect("should decode successfully"),
+    );
+
+    let path = std::env::temp_dir().join(format!("nscoder-{}.plist", std::process::id()));
+    nscoder::to_file(&path, &person, OutputFormat::Binary).expect("should encode successfully");
+    let decoded = nscoder::from_file(&path, &registry);
+    std::fs::remove_file(&path).expect("should remove the file");
+    assert_person(decoded.expect("should decode successfully"));
+}
+
+#[test]
+fn test_archive_class_name_substitution() {
+    let person = Person {
+        age: 26,
+        first_name: "Cyan".to_owned(),
+        last_name: "Yang".to_owned(),
+    };
+
+    let mut options = ArchiveOptions::new();
+    options.set_class_name_for::<Person>("_TtC7Contacts6Person");
+    assert_eq!(
+        options.class_name_for::<Person>(),
+        Some("_TtC7Contacts6Person")
+    );
+    let encoded_bytes =
+        nscoder::to_bytes_with_options(&person, &options).expect("should encode successfully");
+
+    let root = ArchiveNode::from_bytes(&encoded_bytes).expect("should decode successfully");
+    let root = root.as_object().expect("root should be an object");
+    assert_eq!(root.classes, ["_TtC7Contacts6Person", "NSObject"]);
+
+    // The type is not registered with the substituted class name.
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Person>();
+    assert!(nscoder::from_bytes(&encoded_bytes, &registry).is_err());
+
+    registry.register_alias::<Person>("_TtC7Contacts6Person");
+    let object =
+        nscoder::from_bytes(&encoded_bytes, &registry).expect("should decode successfully");
+    let decoded_person: &Person = object
+        .downcast_ref()
+        .expect("type of the value should be `Person`");
+    assert_eq!(decoded_person.first_name, "Cyan");
+}
+
+#[test]
+fn test_archive_top_objects() {
+    let person = Person {
+        age: 26,
+        first_name: "Cyan".to_owned(),
+        last_name: "Yang".to_owned(),
+    };
+    let name = "Cyan".to_owned();
+
+    let options = ArchiveOptions::new();
+    let encoded_bytes =
+        nscoder::to_bytes_top_objects(&[("root", &person), ("Name", &name)], &options)
+            .expect("should encode successfully");
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Person>();
+
+    let objects =
+        nscoder::from_bytes_top_objects(&encoded_bytes, &registry, &UnarchiveOptions::new())
+            .expect("should decode successfully");
+    assert_eq!(objects.keys().collect::<Vec<_>>(), ["Name", "root"]);
+    let decoded_person: &Person = objects["root"]
+        .downcast_ref()
+        .expect("type of the value should be `Person`");
+    assert_eq!(decoded_person.last_name, "Yang");
+    let decoded_name: &String = objects["Name"]
+        .downcast_ref()
+        .expect("type of the value should be `String`");
+    assert_eq!(decoded_name, "Cyan");
+
+    // The root object can still be decoded alone.
+    let object =
+        nscoder::from_bytes(&encoded_bytes, &registry).expect("should decode successfully");
+    assert!(object.downcast_ref::<Person>().is_some());
+
+    let path = std::env::temp_dir().join(format!("nscoder-top-{}.plist", std::process::id()));
+    std::fs::write(&path, &encoded_bytes).expect("should write the archive");
+    let objects = nscoder::from_file_top_objects(&path, &registry, &UnarchiveOptions::new());
+    std::fs::remove_file(&path).expect("should remove the file");
+    let objects = objects.expect("should decode successfully");
+    assert_eq!(objects.len(), 2);
+}
+
+#[test]
+fn test_archive_root_key() {
+    let person = Person {
+        age: 26,
+        first_name: "Cyan".to_owned(),
+        last_name: "Yang".to_owned(),
+    };
+
+    let mut options = ArchiveOptions::new();
+    options.set_root_key("IB.objectdata");
+    let encoded_bytes =
+        nscoder::to_bytes_with_options(&person, &options).expect("should encode successfully");
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Person>();
+
+    let error = nscoder::from_bytes(&encoded_bytes, &registry).expect_err("should fail to decode");
+    assert!(matches!(error, Error::NoRootObject));
+
+    let mut options = UnarchiveOptions::new();
+    options.set_root_key("IB.objectdata");
+    let object = nscoder::from_bytes_with_options(&encoded_bytes, &registry, &options)
+        .expect("should decode successfully");
+    assert!(object.downcast_ref::<Person>().is_some());
+}
+
+#[test]
+fn test_archive_validate() {
+    let person = Person {
+        age: 26,
+        first_name: "Cyan".to_owned(),
+        last_name: "Yang".to_owned(),
+    };
+    let encoded_bytes = nscoder::to_bytes(&person).expect("should encode successfully");
+
+    let mut dict = ArchiveDict::from_bytes(&encoded_bytes).expect("should read successfully");
+    dict.validate().expect("should be valid");
+
+    let object_count = dict.objects.len() as u64;
+    let root = dict.objects[1]
+        .as_dictionary_mut()
+        .expect("root should be a dictionary");
+    root.insert(
+        "Dangling".to_owned(),
+        plist::Value::Uid(plist::Uid::new(object_count)),
+    );
+    let error = dict.validate().expect_err("should be invalid");
+    assert!(matches!(error, Error::InvalidObject { uid: 1, .. }));
+}
+
+#[derive(Debug)]
+struct Contact {
+    name: String,
+    unknown_fields: UnknownFields,
+}
+
+impl Archive for Contact {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDContact"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        archiver.encode_string(&self.name, "Name");
+        archiver.encode_unknown_fields(&self.unknown_fields);
+    }
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        Some(Contact {
+            name: unarchiver.decode_string("Name")?,
+            unknown_fields: unarchiver.unknown_fields(),
+        })
+    }
+}
+
+// A newer version of `Contact`, with values the older one does not know.
+#[derive(Debug)]
+struct ContactV2 {
+    name: String,
+    email: String,
+    nicknames: Vec<String>,
+    manager: Person,
+}
+
+impl Archive for ContactV2 {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDContact"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        archiver.encode_string(&self.name, "Name");
+        archiver.encode_string(&self.email, "Email");
+        let nicknames: Vec<_> = self
+            .nicknames
+            .iter()
+            .cloned()
+            .map(AnyObject::erasing)
+            .collect();
+        archiver.encode_array(&nicknames, "Nicknames");
+        archiver.encode_value(&self.manager, "Manager");
+    }
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        Some(ContactV2 {
+            name: unarchiver.decode_string("Name")?,
+            email: unarchiver.decode_string("Email")?,
+            nicknames: unarchiver.decode_array_of::<String>("Nicknames")?,
+            manager: unarchiver.decode_value("Manager")?,
+        })
+    }
+}
+
+#[test]
+fn test_archive_class_name_substitution_by_type() {
+    // `Contact` and `ContactV2` share a class name, but are substituted separately.
+    let mut options = ArchiveOptions::new();
+    options.set_class_name_for::<ContactV2>("RCDContactV2");
+    assert_eq!(options.class_name_for::<Contact>(), None);
+
+    let contact = Contact {
+        name: "Cyan".to_owned(),
+        unknown_fields: UnknownFields::default(),
+    };
+    let encoded_bytes =
+        nscoder::to_bytes_with_options(&contact, &options).expect("should encode successfully");
+    let root = ArchiveNode::from_bytes(&encoded_bytes).expect("should decode successfully");
+    let root = root.as_object().expect("root should be an object");
+    assert_eq!(root.classes, ["RCDContact", "NSObject"]);
+
+    let contact = ContactV2 {
+        name: "Cyan".to_owned(),
+        email: "cyan@example.com".to_owned(),
+        nicknames: vec![],
+        manager: Person {
+            age: 26,
+            first_name: "Cyan".to_owned(),
+            last_name: "Yang".to_owned(),
+        },
+    };
+    let encoded_bytes =
+        nscoder::to_bytes_with_options(&contact, &options).expect("should encode successfully");
+    let root = ArchiveNode::from_bytes(&encoded_bytes).expect("should decode successfully");
+    let root = root.as_object().expect("root should be an object");
+    assert_eq!(root.classes, ["RCDContactV2", "NSObject"]);
+}
+
+/// A type that borrows its data, and can only be encoded.
+#[derive(Debug)]
+struct Employee<'a> {
+    person: &'a Person,
+    title: &'a str,
+}
+
+impl Archive for Employee<'_> {
+    type Super = Person;
+
+    fn class_name() -> &'static str {
+        "RCDEmployee"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        self.person.encode(archiver);
+        archiver.encode_string(self.title, "Title");
+    }
+
+    fn decode(_unarchiver: &dyn Decoder) -> Option<Self> {
+        None
+    }
+}
+
+#[test]
+fn test_archive_borrowed() {
+    let mut options = ArchiveOptions::new();
+    options.set_class_name_for::<Person>("_TtC7Contacts6Person");
+
+    let person = Person {
+        age: 26,
+        first_name: "Cyan".to_owned(),
+        last_name: "Yang".to_owned(),
+    };
+    let employee = Employee {
+        person: &person,
+        title: "Engineer",
+    };
+    let encoded_bytes =
+        nscoder::to_bytes_with_options(&employee, &options).expect("should encode successfully");
+    let root = ArchiveNode::from_bytes(&encoded_bytes).expect("should decode successfully");
+    let root = root.as_object().expect("root should be an object");
+    assert_eq!(
+        root.classes,
+        ["RCDEmployee", "_TtC7Contacts6Person", "NSObject"]
+    );
+    assert_eq!(
+        root.get("Title").and_then(ArchiveNode::as_str),
+        Some("Engineer")
+    );
+}
+
+#[test]
+fn test_archive_unknown_fields() {
+    let contact = ContactV2 {
+        name: "Cyan".to_owned(),
+        email: "cyan@example.com".to_owned(),
+        nicknames: vec!["Cy".to_owned(), "Yang".to_owned()],
+        manager: Person {
+            age: 40,
+            first_name: "Ada".to_owned(),
+            last_name: "Yang".to_owned(),
+        },
+    };
+    let encoded_bytes = nscoder::to_bytes(&contact).expect("should encode successfully");
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Contact>();
+    let object =
+        nscoder::from_bytes(&encoded_bytes, &registry).expect("should decode successfully");
+    let mut old_contact = object.downcast::<Contact>().expect("should be a contact");
+    assert_eq!(
+        old_contact.unknown_fields.keys().collect::<Vec<_>>(),
+        ["Email", "Nicknames", "Manager"]
+    );
+    old_contact.name = "Yang".to_owned();
+    let reencoded_bytes = nscoder::to_bytes(&*old_contact).expect("should encode successfully");
+
+    let dict = ArchiveDict::from_bytes(&reencoded_bytes).expect("should read successfully");
+    dict.validate().expect("should be valid");
+    // The string `Yang` is shared by the new name and the unknown values.
+    let strings = dict
+        .objects
+        .iter()
+        .filter(|value| value.as_string() == Some("Yang"))
+        .count();
+    assert_eq!(strings, 1);
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<ContactV2>();
+    registry.register_type::<Person>();
+    let object =
+        nscoder::from_bytes(&reencoded_bytes, &registry).expect("should decode successfully");
+    let new_contact = object
+        .downcast_ref::<ContactV2>()
+        .expect("should be a contact");
+    assert_eq!(new_contact.name, "Yang");
+    assert_eq!(new_contact.email, contact.email);
+    assert_eq!(new_contact.nicknames, contact.nicknames);
+    assert_eq!(new_contact.manager.first_name, "Ada");
+    assert_eq!(new_contact.manager.age, 40);
+}
+
+#[derive(Debug)]
+struct Member {
+    mentor: Person,
+    unknown_fields: UnknownFields,
+}
+
+impl Archive for Member {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDMember"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        archiver.encode_value(&self.mentor, "Mentor");
+        archiver.encode_unknown_fields(&self.unknown_fields);
+    }
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        Some(Member {
+            mentor: unarchiver.decode_value("Mentor")?,
+            unknown_fields: unarchiver.unknown_fields(),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Squad {
+    coach: ObjectRef,
+    lead: Member,
+}
+
+impl Archive for Squad {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDSquad"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        archiver.encode_shared_object(&self.coach, "Coach");
+        archiver.encode_value(&self.lead, "Lead");
+    }
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        Some(Squad {
+            coach: unarchiver.decode_shared_object("Coach")?,
+            lead: unarchiver.decode_value("Lead")?,
+        })
+    }
+}
+
+#[test]
+fn test_archive_unknown_fields_identity() {
+    let person = |first_name: &str| Person {
+        age: 40,
+        first_name: first_name.to_owned(),
+        last_name: "Yang".to_owned(),
+    };
+    let squad = Squad {
+        coach: Rc::new(AnyObject::erasing(person("Ada"))),
+        lead: Member {
+            mentor: person("Cyan"),
+            unknown_fields: UnknownFields::default(),
+        },
+    };
+    let encoded_bytes = nscoder::to_bytes(&squad).expect("should encode successfully");
+
+    // A newer version of the member references its squad, itself, its
+    // mentor and the coach with values the older one does not know.
+    let mut dict = ArchiveDict::from_bytes(&encoded_bytes).expect("should read successfully");
+    let uid_for_key = |dict: &ArchiveDict, uid: plist::Uid, key: &str| {
+        dict.objects[uid.get() as usize].as_dictionary().unwrap()[key]
+            .as_uid()
+            .copied()
+            .unwrap()
+    };
+    let squad_uid = dict.top_objects["root"];
+    let lead_uid = uid_for_key(&dict, squad_uid, "Lead");
+    let new_values = [
+        ("Squad", squad_uid),
+        ("Itself", lead_uid),
+        ("Buddy", uid_for_key(&dict, lead_uid, "Mentor")),
+        ("Trainer", uid_for_key(&dict, squad_uid, "Coach")),
+    ];
+    let lead = dict.objects[lead_uid.get() as usize]
+        .as_dictionary_mut()
+        .unwrap();
+    for (key, uid) in new_values {
+        lead.insert(key.to_owned(), plist::Value::Uid(uid));
+    }
+    let object_count = dict.objects.len();
+    let mut encoded_bytes = vec![];
+    dict.to_writer(&mut encoded_bytes, OutputFormat::Binary)
+        .expect("should write successfully");
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Squad>();
+    registry.register_type::<Member>();
+    registry.register_type::<Person>();
+    let object =
+        nscoder::from_bytes(&encoded_bytes, &registry).expect("should decode successfully");
+    let squad = object.downcast::<Squad>().expect("should be a squad");
+    assert_eq!(
+        squad.lead.unknown_fields.keys().collect::<Vec<_>>(),
+        ["Squad", "Itself", "Buddy", "Trainer"]
+    );
+    let reencoded_bytes = nscoder::to_bytes(&*squad).expect("should encode successfully");
+
+    // The references lead to the re-encoded objects, rather than to copies.
+    let dict = ArchiveDict::from_bytes(&reencoded_bytes).expect("should read successfully");
+    dict.validate().expect("should be valid");
+    assert_eq!(dict.objects.len(), object_count);
+    let squad_uid = dict.top_objects["root"];
+    let lead_uid = uid_for_key(&dict, squad_uid, "Lead");
+    assert_eq!(uid_for_key(&dict, lead_uid, "Squad"), squad_uid);
+    assert_eq!(uid_for_key(&dict, lead_uid, "Itself"), lead_uid);
+    assert_eq!(
+        uid_for_key(&dict, lead_uid, "Buddy"),
+        uid_for_key(&dict, lead_uid, "Mentor")
+    );
+    assert_eq!(
+        uid_for_key(&dict, lead_uid, "Trainer"),
+        uid_for_key(&dict, squad_uid, "Coach")
+    );
+}
+
+// `Person` with its values encoded in the reverse order.
+#[derive(Debug)]
+struct ReversedPerson(Person);
+
+impl Archive for ReversedPerson {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDPerson"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        archiver.encode_string(&self.0.last_name, "LastName");
+        archiver.encode_string(&self.0.first_name, "FirstName");
+        archiver.encode_i32(self.0.age as _, "Age");
+    }
+
+    fn decode(_unarchiver: &dyn Decoder) -> Option<Self> {
+        None
+    }
+}
+
+#[test]
+fn test_archive_canonical() {
+    let person = Person {
+        age: 26,
+        first_name: "Cyan".to_owned(),
+        last_name: "Yang".to_owned(),
+    };
+    let reversed_person = ReversedPerson(Person {
+        age: 26,
+        first_name: "Cyan".to_owned(),
+        last_name: "Yang".to_owned(),
+    });
+    let name = "Yang".to_owned();
+
+    let mut options = ArchiveOptions::new();
+    let encoded_bytes =
+        nscoder::to_bytes_top_objects(&[("root", &person), ("Name", &name)], &options)
+            .expect("should encode successfully");
+    let reversed_bytes =
+        nscoder::to_bytes_top_objects(&[("Name", &name), ("root", &reversed_person)], &options)
+            .expect("should encode successfully");
+    assert_ne!(encoded_bytes, reversed_bytes);
+
+    options.set_canonical(true);
+    let encoded_bytes =
+        nscoder::to_bytes_top_objects(&[("root", &person), ("Name", &name)], &options)
+            .expect("should encode successfully");
+    let reversed_bytes =
+        nscoder::to_bytes_top_objects(&[("Name", &name), ("root", &reversed_person)], &options)
+            .expect("should encode successfully");
+    assert_eq!(encoded_bytes, reversed_bytes);
+
+    let mut dict = ArchiveDict::from_bytes(&encoded_bytes).expect("should read successfully");
+    dict.validate().expect("should be valid");
+    // `Name` comes first, and the class info comes right after the root.
+    assert_eq!(dict.objects[1].as_string(), Some("Yang"));
+    let root = dict.objects[2]
+        .as_dictionary()
+        .expect("root should be a dictionary");
+    assert_eq!(
+        root.keys().collect::<Vec<_>>(),
+        ["$class", "Age", "FirstName", "LastName"]
+    );
+    assert_eq!(
+        dict.classes_of(root).ok(),
+        Some(vec!["RCDPerson", "NSObject"])
+    );
+    assert_eq!(
+        root.get("$class"),
+        Some(&plist::Value::Uid(plist::Uid::new(3)))
+    );
+
+    // Canonical archives stay the same when they are canonicalized again.
+    let object_count = dict.objects.len();
+    dict.objects.push(plist::Value::String("Unused".to_owned()));
+    dict.canonicalize();
+    assert_eq!(dict.objects.len(), object_count);
+    let mut canonical_bytes = vec![];
+    dict.to_writer(&mut canonical_bytes, OutputFormat::Binary)
+        .expect("should write successfully");
+    assert_eq!(canonical_bytes, encoded_bytes);
+
+    // Dangling references stay dangling, rather than becoming `$null`.
+    dict.objects.push(plist::Value::String("Unused".to_owned()));
+    let uid = plist::Uid::new(object_count as u64 + 6);
+    dict.top_objects.insert("Missing".to_owned(), uid);
+    dict.canonicalize();
+    assert_eq!(dict.objects.len(), object_count);
+    let uid = plist::Uid::new(object_count as u64 + 5);
+    assert_eq!(dict.top_objects.get("Missing"), Some(&uid));
+    assert!(dict.validate().is_err());
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Person>();
+    let object =
+        nscoder::from_bytes(&encoded_bytes, &registry).expect("should decode successfully");
+    assert_eq!(
+        object.downcast_ref::<Person>().map(|person| person.age),
+        Some(26)
+    );
+}
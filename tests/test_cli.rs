@@ -0,0 +1,142 @@
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use nscoder::{ArchiveDict, ArchiveEditor, OutputFormat};
+
+const FIXTURE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/mobilesync_backup.plist"
+);
+
+fn nscoder(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nscoder"))
+        .args(args)
+        .output()
+        .expect("should run successfully")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).expect("should be UTF-8")
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name)
+}
+
+fn temp_str(name: &str) -> String {
+    temp_path(name)
+        .to_str()
+        .expect("should be UTF-8")
+        .to_owned()
+}
+
+fn fixture() -> ArchiveDict {
+    let bytes = fs::read(FIXTURE).expect("should read successfully");
+    ArchiveDict::from_bytes(&bytes).expect("should read successfully")
+}
+
+#[test]
+fn test_cli_dump() {
+    let output = nscoder(&["dump", FIXTURE]);
+    assert_eq!(output.status.code(), Some(0));
+    let dump = stdout(&output);
+    assert!(dump.starts_with("MBFile #1\n"));
+    assert!(dump.contains("  RelativePath: \"Library/PersistentStores\"\n"));
+    assert!(dump.contains("  GroupID: 501\n"));
+
+    let output = nscoder(&["dump", &temp_str("cli_missing.plist")]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("error: "));
+}
+
+#[test]
+fn test_cli_classes() {
+    let output = nscoder(&["classes", FIXTURE]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "MBFile (1): NSObject\n");
+}
+
+#[test]
+fn test_cli_validate() {
+    let output = nscoder(&["validate", FIXTURE]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).ends_with(" objects, no errors found\n"));
+}
+
+#[test]
+fn test_cli_convert() {
+    let dict = fixture();
+
+    let xml_path = temp_str("cli_convert.xml");
+    let output = nscoder(&["convert", FIXTURE, "--to", "xml", "-o", &xml_path]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    let xml = fs::read(&xml_path).expect("should read successfully");
+    assert!(xml.starts_with(b"<?xml"));
+    let xml_dict = ArchiveDict::from_bytes(&xml).expect("should read successfully");
+    assert_eq!(xml_dict.objects, dict.objects);
+
+    // JSON is written to the standard output by default, and can be read
+    // back like the other formats.
+    let output = nscoder(&["convert", &xml_path, "--to", "json"]);
+    assert_eq!(output.status.code(), Some(0));
+    let json = stdout(&output);
+    let json_dict = nscoder::json::from_str(&json).expect("should import successfully");
+    assert_eq!(nscoder::diff(&dict, &json_dict).ok(), Some(vec![]));
+
+    let json_path = temp_path("cli_convert.json");
+    fs::write(&json_path, &json).expect("should write successfully");
+    let binary_path = temp_str("cli_convert.plist");
+    let output = nscoder(&[
+        "convert",
+        json_path.to_str().expect("should be UTF-8"),
+        "--to",
+        "binary",
+        "-o",
+        &binary_path,
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    let bytes = fs::read(&binary_path).expect("should read successfully");
+    assert!(bytes.starts_with(b"bplist00"));
+    let binary_dict = ArchiveDict::from_bytes(&bytes).expect("should read successfully");
+    assert_eq!(nscoder::diff(&dict, &binary_dict).ok(), Some(vec![]));
+
+    // Canonical archives do not depend on how the input was numbered.
+    let output = nscoder(&["convert", FIXTURE, "--to", "binary", "--canonical"]);
+    let canonical_output = nscoder(&["convert", &binary_path, "--to", "binary", "--canonical"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, canonical_output.stdout);
+
+    let output = nscoder(&["convert", FIXTURE, "--to", "yaml"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_cli_diff() {
+    let output = nscoder(&["diff", FIXTURE, FIXTURE]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+
+    let mut dict = fixture();
+    ArchiveEditor::new(&mut dict)
+        .set_i64("root.GroupID", 20)
+        .expect("should set successfully");
+    let new_path = temp_str("cli_diff.plist");
+    let mut bytes = vec![];
+    dict.to_writer(&mut bytes, OutputFormat::Binary)
+        .expect("should write successfully");
+    fs::write(&new_path, bytes).expect("should write successfully");
+
+    let output = nscoder(&["diff", FIXTURE, &new_path]);
+    assert_eq!(output.status.code(), Some(1));
+    let changes = stdout(&output);
+    assert_eq!(changes.lines().count(), 1);
+    assert!(changes.contains("root.GroupID"));
+
+    let output = nscoder(&["diff", FIXTURE, &temp_str("cli_missing.plist")]);
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -0,0 +1,247 @@
+use std::rc::Rc;
+
+use nscoder::{
+    AnyObject, Archive, Decoder, Encoder, NSMutableArray, NSMutableDictionary, NSMutableSet,
+    NSOrderedSet, TypeRegistry,
+};
+
+#[derive(Debug)]
+struct Person {
+    name: String,
+}
+
+impl Archive for Person {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDPerson"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        archiver.encode_string(&self.name, "Name");
+    }
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        let name = unarchiver.decode_string("Name")?;
+        Some(Person { name })
+    }
+}
+
+#[derive(Debug)]
+struct Team {
+    members: Vec<Person>,
+    tags: Vec<String>,
+}
+
+impl Archive for Team {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDTeam"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        let members: Vec<_> = self
+            .members
+            .iter()
+            .map(|member| {
+                AnyObject::erasing(Person {
+                    name: member.name.clone(),
+                })
+            })
+            .collect();
+        archiver.encode_array(&members, "Members");
+
+        let tags: Vec<_> = self.tags.iter().cloned().map(AnyObject::erasing).collect();
+        archiver.encode_array(&tags, "Tags");
+    }
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        let members = unarchiver.decode_array_of::<Person>("Members")?;
+        let tags = unarchiver.decode_array_of::<String>("Tags")?;
+        Some(Team { members, tags })
+    }
+}
+
+#[test]
+fn test_array() {
+    let team = Team {
+        members: vec![
+            Person {
+                name: "Cyan".to_owned(),
+            },
+            Person {
+                name: "Yang".to_owned(),
+            },
+        ],
+        tags: vec!["rust".to_owned(), "cocoa".to_owned()],
+    };
+
+    let encoded_bytes = nscoder::to_bytes(&team).expect("should encode successfully");
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Team>();
+    registry.register_type::<Person>();
+
+    let object =
+        nscoder::from_bytes(&encoded_bytes, &registry).expect("should decode successfully");
+    let decoded_team: &Team = object
+        .downcast_ref()
+        .expect("type of the value should be `Team`");
+
+    let names: Vec<_> = decoded_team.members.iter().map(|m| &m.name).collect();
+    assert_eq!(names, ["Cyan", "Yang"]);
+    assert_eq!(decoded_team.tags, ["rust", "cocoa"]);
+}
+
+#[test]
+fn test_mutable_array() {
+    let array = NSMutableArray::default();
+    let encoded_bytes = nscoder::to_bytes(&array).expect("should encode successfully");
+
+    let object = nscoder::from_bytes(&encoded_bytes, &TypeRegistry::new())
+        .expect("should decode successfully");
+    assert_eq!(object.class_name(), "NSMutableArray");
+}
+
+#[test]
+fn test_dictionary() {
+    let mut dictionary = NSMutableDictionary::default();
+    dictionary.insert(
+        AnyObject::erasing("Name".to_owned()),
+        AnyObject::erasing("Cyan".to_owned()),
+    );
+    dictionary.insert(
+        AnyObject::erasing("Age".to_owned()),
+        AnyObject::erasing(26i64),
+    );
+    dictionary.insert(
+        AnyObject::erasing("Name".to_owned()),
+        AnyObject::erasing("Yang".to_owned()),
+    );
+    assert_eq!(dictionary.len(), 2);
+
+    let encoded_bytes = nscoder::to_bytes(&dictionary).expect("should encode successfully");
+
+    let object = nscoder::from_bytes(&encoded_bytes, &TypeRegistry::new())
+        .expect("should decode successfully");
+    assert_eq!(object.class_name(), "NSMutableDictionary");
+    let decoded_dictionary: &NSMutableDictionary = object
+        .downcast_ref()
+        .expect("type of the value should be `NSMutableDictionary`");
+
+    let keys: Vec<_> = decoded_dictionary.iter_str().map(|(key, _)| key).collect();
+    assert_eq!(keys, ["Name", "Age"]);
+    assert_eq!(
+        decoded_dictionary
+            .get("Name")
+            .and_then(|name| name.downcast_ref::<String>()),
+        Some(&"Yang".to_owned())
+    );
+    assert_eq!(
+        decoded_dictionary
+            .get("Age")
+            .and_then(|age| age.downcast_ref::<i64>()),
+        Some(&26)
+    );
+    assert!(decoded_dictionary.get("Missing").is_none());
+}
+
+#[derive(Debug)]
+struct Labels {
+    colors: Vec<String>,
+    sizes: Vec<i64>,
+}
+
+impl Archive for Labels {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDLabels"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        let mut colors = NSMutableSet::default();
+        colors.objects = self
+            .colors
+            .iter()
+            .cloned()
+            .map(|color| Rc::new(AnyObject::erasing(color)))
+            .collect();
+        archiver.encode_object(&AnyObject::erasing(colors), "Colors");
+
+        let sizes = NSOrderedSet::new(self.sizes.iter().copied().map(AnyObject::erasing).collect());
+        archiver.encode_object(&AnyObject::erasing(sizes), "Sizes");
+    }
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        let colors = unarchiver
+            .decode_set("Colors")?
+            .into_iter()
+            .map(|color| color.downcast::<String>().ok().map(|color| *color))
+            .collect::<Option<_>>()?;
+        let sizes = unarchiver
+            .decode_ordered_set("Sizes")?
+            .into_iter()
+            .map(|size| size.downcast::<i64>().ok().map(|size| *size))
+            .collect::<Option<_>>()?;
+        Some(Labels { colors, sizes })
+    }
+}
+
+#[test]
+fn test_sets() {
+    let labels = Labels {
+        colors: vec!["red".to_owned(), "green".to_owned(), "blue".to_owned()],
+        sizes: vec![3, 1, 2],
+    };
+
+    let encoded_bytes = nscoder::to_bytes(&labels).expect("should encode successfully");
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Labels>();
+
+    let object =
+        nscoder::from_bytes(&encoded_bytes, &registry).expect("should decode successfully");
+    let decoded_labels: &Labels = object
+        .downcast_ref()
+        .expect("type of the value should be `Labels`");
+
+    assert_eq!(decoded_labels.colors, ["red", "green", "blue"]);
+    assert_eq!(decoded_labels.sizes, [3, 1, 2]);
+
+    let archive = plist::Value::from_reader(std::io::Cursor::new(&encoded_bytes))
+        .expect("should be a valid plist");
+    let classes: Vec<_> = archive.as_dictionary().unwrap()["$objects"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|object| object.as_dictionary()?.get("$classes")?.as_array())
+        .map(|classes| {
+            classes
+                .iter()
+                .filter_map(|class| class.as_string())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    assert!(classes.contains(&vec!["NSMutableSet", "NSSet", "NSObject"]));
+    assert!(classes.contains(&vec!["NSOrderedSet", "NSObject"]));
+}
+
+#[test]
+fn test_ordered_set_with_unknown_element() {
+    let set = NSOrderedSet::new(vec![
+        AnyObject::erasing(1i64),
+        AnyObject::erasing(Person {
+            name: "Cyan".to_owned(),
+        }),
+        AnyObject::erasing(2i64),
+    ]);
+    let encoded_bytes = nscoder::to_bytes(&set).expect("should encode successfully");
+
+    // `RCDPerson` is not registered, which fails the set instead of
+    // truncating it.
+    let error = nscoder::from_bytes(&encoded_bytes, &TypeRegistry::new())
+        .expect_err("should fail to decode");
+    assert!(matches!(error, nscoder::Error::UnknownClass(class) if class == "RCDPerson"));
+}
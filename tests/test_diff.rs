@@ -0,0 +1,123 @@
+use nscoder::{AnyObject, ArchiveChange, ArchiveDict, ArchiveEditor, ArchiveNode, NSArray};
+use plist::{Dictionary, Uid, Value};
+
+fn fixture() -> ArchiveDict {
+    let bytes = include_bytes!("./fixtures/mobilesync_backup.plist");
+    ArchiveDict::from_bytes(bytes).expect("should read successfully")
+}
+
+#[test]
+fn test_diff() {
+    let old_dict = fixture();
+
+    // Renumber the relative path, which is not a difference.
+    let mut new_dict = fixture();
+    let mut editor = ArchiveEditor::new(&mut new_dict);
+    editor
+        .set_string("root.RelativePath", "Library")
+        .expect("should set successfully");
+    editor.collect_garbage();
+    editor
+        .set_string("root.RelativePath", "Library/PersistentStores")
+        .expect("should set successfully");
+    editor.collect_garbage();
+    assert_ne!(old_dict.objects, new_dict.objects);
+    assert_eq!(nscoder::diff(&old_dict, &new_dict).ok(), Some(vec![]));
+
+    let mut editor = ArchiveEditor::new(&mut new_dict);
+    editor
+        .set_i64("root.GroupID", 20)
+        .expect("should set successfully");
+    editor
+        .remove("root.Flags")
+        .expect("should remove successfully");
+    let tags = NSArray::new(vec![AnyObject::erasing("a".to_owned())]);
+    editor
+        .insert_object("root.Tags", &tags)
+        .expect("should insert successfully");
+    let changes = nscoder::diff(&old_dict, &new_dict).expect("should compare successfully");
+    assert_eq!(
+        changes[0],
+        ArchiveChange::Removed {
+            path: "root.Flags".to_owned(),
+            value: ArchiveNode::Integer(0),
+        }
+    );
+    assert_eq!(
+        changes.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        [
+            "- root.Flags: 0",
+            "~ root.GroupID: 501 -> 20",
+            "+ root.Tags: <NSArray>",
+        ]
+    );
+
+    // Elements of collections are compared by their indices.
+    let mut old_dict = fixture();
+    let mut editor = ArchiveEditor::new(&mut old_dict);
+    editor
+        .insert_object("root.Tags", &tags)
+        .expect("should insert successfully");
+    let mut editor = ArchiveEditor::new(&mut new_dict);
+    let tags = NSArray::new(vec![AnyObject::erasing("b".to_owned())]);
+    editor
+        .insert_object("root.Tags[0]", &tags)
+        .expect("should insert successfully");
+    let changes = nscoder::diff(&old_dict, &new_dict).expect("should compare successfully");
+    assert_eq!(
+        changes.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        [
+            "- root.Flags: 0",
+            "~ root.GroupID: 501 -> 20",
+            "~ root.Tags[0]: \"a\" -> <NSArray>",
+        ]
+    );
+}
+
+#[test]
+fn test_diff_cyclic_references() {
+    fn object(fields: &[(&str, u64)]) -> Value {
+        let mut dict = Dictionary::new();
+        dict.insert("$class".to_owned(), Value::Uid(Uid::new(4)));
+        for (key, uid) in fields {
+            dict.insert((*key).to_owned(), Value::Uid(Uid::new(*uid)));
+        }
+        Value::Dictionary(dict)
+    }
+
+    // Two nodes reference each other, and the keys of the root object are
+    // archived in reverse order.
+    let archive = Dictionary::from_iter([
+        ("$archiver", Value::from("NSKeyedArchiver")),
+        ("$version", Value::from(100000)),
+        (
+            "$top",
+            Value::Dictionary(Dictionary::from_iter([("root", Value::Uid(Uid::new(1)))])),
+        ),
+        (
+            "$objects",
+            Value::Array(vec![
+                "$null".into(),
+                object(&[("Right", 2), ("Left", 3)]),
+                object(&[("Left", 3)]),
+                object(&[("Left", 2)]),
+                Value::Dictionary(Dictionary::from_iter([
+                    ("$classname", Value::from("RCDNode")),
+                    (
+                        "$classes",
+                        Value::Array(vec!["RCDNode".into(), "NSObject".into()]),
+                    ),
+                ])),
+            ]),
+        ),
+    ]);
+    let old_dict = ArchiveDict::from_plist_value(&Value::Dictionary(archive))
+        .expect("should read successfully");
+
+    // Sorting the keys cuts the cycle at another node, which is not a
+    // difference.
+    let mut new_dict = ArchiveDict::from_plist_value(&old_dict.to_plist_value().unwrap())
+        .expect("should read successfully");
+    new_dict.canonicalize();
+    assert_eq!(nscoder::diff(&old_dict, &new_dict).ok(), Some(vec![]));
+}
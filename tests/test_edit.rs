@@ -0,0 +1,167 @@
+use nscoder::{
+    AnyObject, ArchiveDict, ArchiveEditor, ArchiveNode, Error, NSArray, NSDictionary, OutputFormat,
+    TypeRegistry,
+};
+use plist::Value;
+
+#[test]
+fn test_edit() {
+    let bytes = include_bytes!("./fixtures/mobilesync_backup.plist");
+    let mut dict = ArchiveDict::from_bytes(bytes).expect("should read successfully");
+    let object_count = dict.objects.len();
+
+    let mut editor = ArchiveEditor::new(&mut dict);
+    assert_eq!(editor.find_objects("MBFile"), ["root"]);
+    assert_eq!(
+        editor.get("root.RelativePath").ok(),
+        Some(&Value::String("Library/PersistentStores".to_owned()))
+    );
+
+    editor
+        .set_string("root.RelativePath", "Library/Caches")
+        .expect("should set successfully");
+    editor
+        .set_i64("root.GroupID", 20)
+        .expect("should set successfully");
+    editor
+        .set_bool("root.Hidden", true)
+        .expect("should set successfully");
+    let tags = NSArray::new(vec![
+        AnyObject::erasing("a".to_owned()),
+        AnyObject::erasing("b".to_owned()),
+    ]);
+    editor
+        .insert_object("root.Tags", &tags)
+        .expect("should insert successfully");
+    assert_eq!(editor.find_objects("NSArray"), ["root.Tags"]);
+    assert_eq!(
+        editor.get("root.Tags[1]").ok(),
+        Some(&Value::String("b".to_owned()))
+    );
+    editor
+        .set_string("root.Tags[1]", "c")
+        .expect("should set successfully");
+    editor
+        .remove("root.Flags")
+        .expect("should remove successfully");
+
+    let mut encoded_bytes = vec![];
+    dict.validate().expect("should be valid");
+    dict.to_writer(&mut encoded_bytes, OutputFormat::Binary)
+        .expect("should write successfully");
+    let root = ArchiveNode::from_bytes(&encoded_bytes).expect("should decode successfully");
+    let file = root.as_object().expect("root should be an object");
+    assert_eq!(
+        file.get("RelativePath").and_then(ArchiveNode::as_str),
+        Some("Library/Caches")
+    );
+    assert_eq!(file.get("GroupID").and_then(ArchiveNode::as_i64), Some(20));
+    assert_eq!(file.get("Hidden"), Some(&ArchiveNode::Boolean(true)));
+    assert_eq!(file.get("Flags"), None);
+    let tags = file
+        .get("Tags")
+        .and_then(ArchiveNode::as_object)
+        .and_then(|tags| tags.get("NS.objects"))
+        .and_then(ArchiveNode::as_array)
+        .expect("tags should be an array");
+    assert_eq!(
+        tags.iter().map(ArchiveNode::as_str).collect::<Vec<_>>(),
+        [Some("a"), Some("c")]
+    );
+
+    // The old relative path and `b` are no longer referenced.
+    let mut editor = ArchiveEditor::new(&mut dict);
+    assert_eq!(editor.collect_garbage(), 2);
+    assert_eq!(dict.objects.len(), object_count + 4);
+    dict.validate().expect("should be valid");
+    let editor = ArchiveEditor::new(&mut dict);
+    assert_eq!(
+        editor.get("root.RelativePath").ok(),
+        Some(&Value::String("Library/Caches".to_owned()))
+    );
+    assert_eq!(
+        editor.get("root.Tags[1]").ok(),
+        Some(&Value::String("c".to_owned()))
+    );
+}
+
+#[test]
+fn test_edit_errors() {
+    let bytes = include_bytes!("./fixtures/mobilesync_backup.plist");
+    let mut dict = ArchiveDict::from_bytes(bytes).expect("should read successfully");
+    let mut editor = ArchiveEditor::new(&mut dict);
+
+    let error = editor.get("").expect_err("should fail");
+    assert!(matches!(error, Error::InvalidKeyPath { path } if path.is_empty()));
+    let error = editor.get("root.Flags]").expect_err("should fail");
+    assert!(matches!(error, Error::InvalidKeyPath { .. }));
+    let error = editor.get("files.Flags").expect_err("should fail");
+    assert!(matches!(error, Error::MissingKey { path } if path == "files"));
+    let error = editor.get("root.Owner.Name").expect_err("should fail");
+    assert!(matches!(error, Error::MissingKey { path } if path == "root.Owner"));
+    let error = editor
+        .get("root.RelativePath.Name")
+        .expect_err("should fail");
+    assert!(matches!(
+        error,
+        Error::TypeMismatch { path, expected: "object", actual: "string" }
+            if path == "root.RelativePath"
+    ));
+    let error = editor.get("root[0]").expect_err("should fail");
+    assert!(matches!(
+        error,
+        Error::TypeMismatch {
+            expected: "array",
+            ..
+        }
+    ));
+
+    let error = editor.set_i64("root", 1).expect_err("should fail");
+    assert!(matches!(error, Error::InvalidKeyPath { path } if path == "root"));
+    let error = editor.remove("root.Owner").expect_err("should fail");
+    assert!(matches!(error, Error::MissingKey { path } if path == "root.Owner"));
+
+    // A dangling UID in `$top` is reported instead of panicking.
+    let uid = plist::Uid::new(dict.objects.len() as u64);
+    dict.top_objects.insert("files".to_owned(), uid);
+    let mut editor = ArchiveEditor::new(&mut dict);
+    let error = editor.get("files").expect_err("should fail");
+    assert!(matches!(error, Error::InvalidObject { uid: u, .. } if u == uid.get()));
+    let error = editor.set_i64("files.Flags", 1).expect_err("should fail");
+    assert!(matches!(error, Error::InvalidObject { .. }));
+}
+
+#[test]
+fn test_edit_dictionary() {
+    let mut entries = NSDictionary::new();
+    for (key, value) in [("a", 1i64), ("b", 2), ("c", 3)] {
+        entries.insert(
+            AnyObject::erasing(key.to_owned()),
+            AnyObject::erasing(value),
+        );
+    }
+    let bytes = nscoder::to_bytes(&entries).expect("should encode successfully");
+    let mut dict = ArchiveDict::from_bytes(&bytes).expect("should read successfully");
+
+    // Removing an entry by its index removes its key too.
+    let mut editor = ArchiveEditor::new(&mut dict);
+    editor
+        .remove("root[1]")
+        .expect("should remove successfully");
+    editor.collect_garbage();
+    dict.validate().expect("should be valid");
+
+    let mut encoded_bytes = vec![];
+    dict.to_writer(&mut encoded_bytes, OutputFormat::Binary)
+        .expect("should write successfully");
+    let object = nscoder::from_bytes(&encoded_bytes, &TypeRegistry::new())
+        .expect("should decode successfully");
+    let entries: &NSDictionary = object
+        .downcast_ref()
+        .expect("type of the value should be `NSDictionary`");
+    assert_eq!(
+        entries.iter_str().map(|(key, _)| key).collect::<Vec<_>>(),
+        ["a", "c"]
+    );
+    assert!(entries.get("b").is_none());
+}
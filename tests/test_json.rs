@@ -0,0 +1,214 @@
+#![cfg(feature = "json")]
+
+use nscoder::{
+    Archive, ArchiveDict, ArchiveNode, Decoder, Encoder, Error, OutputFormat, TypeRegistry,
+};
+use plist::{Dictionary, Uid, Value};
+use serde_json::json;
+
+#[derive(Debug, PartialEq)]
+struct Document {
+    title: String,
+    scale: f64,
+    thumbnail: Vec<u8>,
+    tags: Vec<String>,
+}
+
+impl Archive for Document {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDDocument"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        archiver.encode_string(&self.title, "Title");
+        archiver.encode_f64(self.scale, "Scale");
+        archiver.encode_bytes(&self.thumbnail, "Thumbnail");
+        let tags: Vec<_> = self
+            .tags
+            .iter()
+            .cloned()
+            .map(nscoder::AnyObject::erasing)
+            .collect();
+        archiver.encode_array(&tags, "Tags");
+    }
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        let tags = unarchiver
+            .decode_array("Tags")?
+            .into_iter()
+            .map(|tag| tag.downcast::<String>().ok().map(|tag| *tag))
+            .collect::<Option<_>>()?;
+        Some(Document {
+            title: unarchiver.decode_string("Title")?,
+            scale: unarchiver.decode_f64("Scale"),
+            thumbnail: unarchiver.decode_bytes("Thumbnail")?,
+            tags,
+        })
+    }
+}
+
+#[test]
+fn test_json() {
+    let document = Document {
+        title: "Notes".to_owned(),
+        scale: 2.0,
+        thumbnail: vec![0xca, 0xfe],
+        tags: vec!["Notes".to_owned(), "Draft".to_owned()],
+    };
+    let encoded_bytes = nscoder::to_bytes(&document).expect("should encode successfully");
+    let dict = ArchiveDict::from_bytes(&encoded_bytes).expect("should read successfully");
+
+    let value = nscoder::json::to_value(&dict).expect("should export successfully");
+    assert_eq!(
+        value["$top"]["root"],
+        json!({
+            "$class": ["RCDDocument", "NSObject"],
+            "Title": "Notes",
+            "Scale": 2.0,
+            "Thumbnail": { "$data": "yv4=" },
+            "Tags": {
+                "$class": ["NSArray", "NSObject"],
+                "NS.objects": ["Notes", "Draft"],
+            },
+        })
+    );
+
+    let imported_dict = nscoder::json::from_value(&value).expect("should import successfully");
+    imported_dict.validate().expect("should be valid");
+    assert_eq!(
+        nscoder::json::to_value(&imported_dict).expect("should export successfully"),
+        value
+    );
+
+    let mut imported_bytes = vec![];
+    imported_dict
+        .to_writer(&mut imported_bytes, OutputFormat::Binary)
+        .expect("should write successfully");
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Document>();
+    let object =
+        nscoder::from_bytes(&imported_bytes, &registry).expect("should decode successfully");
+    assert_eq!(object.downcast_ref::<Document>(), Some(&document));
+}
+
+#[test]
+fn test_json_shared_objects() {
+    let value = json!({
+        "$archiver": "NSKeyedArchiver",
+        "$version": 100000,
+        "$top": {
+            "root": {
+                "$class": ["RCDNode", "NSObject"],
+                "$id": 1,
+                "Name": "Parent",
+                "Parent": null,
+                "Count": { "$value": 3 },
+                "Children": [
+                    {
+                        "$class": ["RCDNode", "NSObject"],
+                        "Name": "Child",
+                        "Parent": { "$ref": 1 },
+                        "Scale": { "$real": "inf" },
+                    },
+                ],
+            },
+        },
+    });
+
+    let dict = nscoder::json::from_value(&value).expect("should import successfully");
+    dict.validate().expect("should be valid");
+    assert_eq!(
+        nscoder::json::to_value(&dict).expect("should export successfully"),
+        value
+    );
+
+    let root = ArchiveNode::from_archive_dict(&dict).expect("should resolve successfully");
+    let root = root.as_object().expect("root should be an object");
+    assert_eq!(
+        root.get("Name").and_then(ArchiveNode::as_str),
+        Some("Parent")
+    );
+    assert_eq!(root.get("Parent"), Some(&ArchiveNode::Null));
+    assert_eq!(root.get("Count").and_then(ArchiveNode::as_i64), Some(3));
+    let child = root
+        .get("Children")
+        .and_then(ArchiveNode::as_array)
+        .expect("should be an array")[0]
+        .as_object()
+        .expect("child should be an object");
+    assert_eq!(child.get("Parent"), Some(&ArchiveNode::Reference(root.uid)));
+    assert_eq!(child.get("Scale"), Some(&ArchiveNode::Real(f64::INFINITY)));
+
+    let error = nscoder::json::from_value(&json!({
+        "$archiver": "NSKeyedArchiver",
+        "$version": 100000,
+        "$top": { "root": { "$ref": 2 } },
+    }))
+    .expect_err("should fail to import");
+    assert!(matches!(error, Error::MalformedJson { path } if path == "$top.root"));
+}
+
+// A chain of nodes as JSON, each referencing the next one.
+fn json_chain(count: usize) -> String {
+    let node = r#"{"$class": ["RCDNode", "NSObject"], "Next": "#;
+    format!("{{\"$archiver\": \"NSKeyedArchiver\", \"$version\": 100000, \"$top\": {{\"root\": {}null{}}}}}",
+        node.repeat(count),
+        "}".repeat(count),
+    )
+}
+
+#[test]
+fn test_json_depth() {
+    // Deeper than the recursion limit of `serde_json`.
+    let dict = nscoder::json::from_str(&json_chain(200)).expect("should import successfully");
+    dict.validate().expect("should be valid");
+    assert_eq!(dict.objects.len(), 202);
+    let string = nscoder::json::to_string(&dict).expect("should export successfully");
+    assert_eq!(
+        nscoder::json::from_str(&string)
+            .expect("should import successfully")
+            .objects,
+        dict.objects
+    );
+
+    let error = nscoder::json::from_str(&json_chain(100_000)).expect_err("should fail to import");
+    assert!(matches!(error, Error::DepthLimitExceeded { .. }));
+
+    // Unbalanced brackets are malformed, however deep the JSON is.
+    for string in ["]", "}{}", "{\"$top\": {}}}"] {
+        let error = nscoder::json::from_str(string).expect_err("should fail to import");
+        assert!(matches!(error, Error::MalformedJson { .. }), "{string}");
+    }
+    let error = nscoder::json::from_str("{").expect_err("should fail to import");
+    assert!(matches!(error, Error::Json(_)));
+
+    // A long chain is rejected rather than overflowing the stack.
+    let count = 100_000;
+    let mut objects = vec![Value::String("$null".to_owned())];
+    for uid in 1..=count {
+        let mut node = Dictionary::new();
+        node.insert("$class".to_owned(), Value::Uid(Uid::new(count + 1)));
+        node.insert(
+            "Next".to_owned(),
+            Value::Uid(Uid::new((uid + 1) % (count + 1))),
+        );
+        objects.push(Value::Dictionary(node));
+    }
+    let mut class_info = Dictionary::new();
+    class_info.insert("$classname".to_owned(), "RCDNode".into());
+    class_info.insert("$classes".to_owned(), Value::Array(vec!["RCDNode".into()]));
+    objects.push(Value::Dictionary(class_info));
+    let dict = ArchiveDict {
+        archiver_class_name: "NSKeyedArchiver".to_owned(),
+        objects,
+        top_objects: [("root".to_owned(), Uid::new(1))].into(),
+        version: 100000,
+    };
+    dict.validate().expect("should be valid");
+    let error = nscoder::json::to_value(&dict).expect_err("should fail to export");
+    assert!(
+        matches!(error, Error::DepthLimitExceeded { path } if path.starts_with("$top.root.Next.Next"))
+    );
+}
@@ -0,0 +1,266 @@
+use nscoder::{
+    Archive, Decoder, Encoder, Error, NSArray, NSMutableDictionary, TypeRegistry, UnarchiveOptions,
+};
+
+enum Value {
+    Int8(i8),
+    Int32(i32),
+    True,
+    Data(&'static [u8]),
+    Object(u32),
+}
+
+fn varint(bytes: &mut Vec<u8>, mut integer: usize) {
+    while integer >= 0x80 {
+        bytes.push((integer & 0x7f) as u8);
+        integer >>= 7;
+    }
+    bytes.push(integer as u8 | 0x80);
+}
+
+/// Builds a NIB file from objects with their class names and values.
+fn nib(objects: &[(&str, Vec<(&str, Value)>)]) -> Vec<u8> {
+    let mut keys: Vec<&str> = vec![];
+    let mut class_names: Vec<&str> = vec![];
+    let (mut object_bytes, mut key_bytes, mut value_bytes, mut class_bytes) =
+        (vec![], vec![], vec![], vec![]);
+    let mut value_count = 0;
+
+    for (class_name, values) in objects {
+        let class = class_names
+            .iter()
+            .position(|name| name == class_name)
+            .unwrap_or_else(|| {
+                class_names.push(class_name);
+                class_names.len() - 1
+            });
+        varint(&mut object_bytes, class);
+        varint(&mut object_bytes, value_count);
+        varint(&mut object_bytes, values.len());
+        value_count += values.len();
+
+        for (key_name, value) in values {
+            let key = keys
+                .iter()
+                .position(|name| name == key_name)
+                .unwrap_or_else(|| {
+                    keys.push(key_name);
+                    keys.len() - 1
+                });
+            varint(&mut value_bytes, key);
+            match value {
+                Value::Int8(integer) => value_bytes.extend([0, *integer as u8]),
+                Value::Int32(integer) => {
+                    value_bytes.push(2);
+                    value_bytes.extend(integer.to_le_bytes());
+                }
+                Value::True => value_bytes.push(5),
+                Value::Data(data) => {
+                    value_bytes.push(8);
+                    varint(&mut value_bytes, data.len());
+                    value_bytes.extend(*data);
+                }
+                Value::Object(index) => {
+                    value_bytes.push(10);
+                    value_bytes.extend(index.to_le_bytes());
+                }
+            }
+        }
+    }
+    for key in &keys {
+        varint(&mut key_bytes, key.len());
+        key_bytes.extend(key.as_bytes());
+    }
+    for class_name in &class_names {
+        varint(&mut class_bytes, class_name.len() + 1);
+        varint(&mut class_bytes, 0);
+        class_bytes.extend(class_name.as_bytes());
+        class_bytes.push(0);
+    }
+
+    let mut bytes = b"NIBArchive".to_vec();
+    bytes.extend(1u32.to_le_bytes());
+    bytes.extend(10u32.to_le_bytes());
+    let mut offset = bytes.len() + 32;
+    for (count, section) in [
+        (objects.len(), &object_bytes),
+        (keys.len(), &key_bytes),
+        (value_count, &value_bytes),
+        (class_names.len(), &class_bytes),
+    ] {
+        bytes.extend((count as u32).to_le_bytes());
+        bytes.extend((offset as u32).to_le_bytes());
+        offset += section.len();
+    }
+    for section in [object_bytes, key_bytes, value_bytes, class_bytes] {
+        bytes.extend(section);
+    }
+    bytes
+}
+
+#[derive(Debug)]
+struct Person {
+    age: u32,
+    first_name: String,
+    tags: NSArray,
+    info: NSMutableDictionary,
+}
+
+impl Archive for Person {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDPerson"
+    }
+
+    fn encode(&self, _archiver: &mut dyn Encoder) {}
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        let age = unarchiver.decode_i32("Age") as u32;
+        let first_name = unarchiver.decode_string("FirstName")?;
+        let tags = *unarchiver.decode_object("Tags")?.downcast().ok()?;
+        let info = *unarchiver.decode_object("Info")?.downcast().ok()?;
+        Some(Person {
+            age,
+            first_name,
+            tags,
+            info,
+        })
+    }
+}
+
+fn person_nib() -> Vec<u8> {
+    nib(&[
+        (
+            "RCDPerson",
+            vec![
+                ("Age", Value::Int8(26)),
+                ("FirstName", Value::Object(1)),
+                ("Tags", Value::Object(2)),
+                ("Info", Value::Object(4)),
+            ],
+        ),
+        ("NSString", vec![("NS.bytes", Value::Data(b"Cyan"))]),
+        (
+            "NSArray",
+            vec![
+                ("NSInlinedValue", Value::True),
+                ("UINibEncoderEmptyKey", Value::Object(1)),
+                ("UINibEncoderEmptyKey", Value::Object(3)),
+            ],
+        ),
+        ("NSString", vec![("NS.bytes", Value::Data(b"Yang"))]),
+        (
+            "NSMutableDictionary",
+            vec![
+                ("NSInlinedValue", Value::True),
+                ("UINibEncoderEmptyKey", Value::Object(5)),
+                ("UINibEncoderEmptyKey", Value::Object(6)),
+            ],
+        ),
+        ("NSString", vec![("NS.bytes", Value::Data(b"Key"))]),
+        ("NSNumber", vec![("NS.intval", Value::Int32(7))]),
+    ])
+}
+
+#[derive(Debug)]
+struct Number(i64);
+
+impl Archive for Number {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "NSNumber"
+    }
+
+    fn encode(&self, _archiver: &mut dyn Encoder) {}
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        Some(Number(unarchiver.decode_i64("NS.intval")))
+    }
+}
+
+#[test]
+fn test_nib() {
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Person>();
+    registry.register_type::<Number>();
+
+    let object =
+        nscoder::from_nib_bytes(&person_nib(), &registry).expect("should decode successfully");
+    let person: &Person = object
+        .downcast_ref()
+        .expect("type of the value should be `Person`");
+
+    assert_eq!(person.age, 26);
+    assert_eq!(person.first_name, "Cyan");
+
+    let tags: Vec<_> = person
+        .tags
+        .objects
+        .iter()
+        .map(|object| object.downcast_ref::<String>().expect("should be a string"))
+        .collect();
+    assert_eq!(tags, ["Cyan", "Yang"]);
+
+    let number: &Number = person
+        .info
+        .0
+        .get("Key")
+        .and_then(|object| object.downcast_ref())
+        .expect("should contain a number");
+    assert_eq!(number.0, 7);
+}
+
+#[test]
+fn test_nib_errors() {
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Person>();
+
+    let error =
+        nscoder::from_nib_bytes(b"NIBArchiv", &registry).expect_err("should fail to decode");
+    assert!(matches!(error, Error::MalformedNibArchive(_)));
+
+    let mut bytes = person_nib();
+    bytes.truncate(bytes.len() - 8);
+    let error = nscoder::from_nib_bytes(&bytes, &registry).expect_err("should fail to decode");
+    assert!(matches!(error, Error::MalformedNibArchive(_)));
+
+    let error =
+        nscoder::from_nib_bytes(&person_nib(), &registry).expect_err("should fail to decode");
+    assert!(
+        matches!(&error, Error::UnknownClass(class) if class == "NSNumber"),
+        "{error}"
+    );
+}
+
+#[test]
+fn test_nib_limits() {
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Person>();
+    registry.register_type::<Number>();
+
+    let mut options = UnarchiveOptions::new();
+    options.set_max_depth(2);
+    let error = nscoder::from_nib_bytes_with_options(&person_nib(), &registry, &options)
+        .expect_err("should fail to decode");
+    assert!(
+        matches!(&error, Error::DepthLimitExceeded { path } if path == "root.Tags[0]"),
+        "{error}"
+    );
+
+    let mut options = UnarchiveOptions::new();
+    options.set_max_objects(4);
+    let error = nscoder::from_nib_bytes_with_options(&person_nib(), &registry, &options)
+        .expect_err("should fail to decode");
+    assert!(matches!(error, Error::ObjectLimitExceeded(4)));
+
+    let mut options = UnarchiveOptions::new();
+    options.set_max_length(3);
+    let error = nscoder::from_nib_bytes_with_options(&person_nib(), &registry, &options)
+        .expect_err("should fail to decode");
+    assert!(
+        matches!(&error, Error::LengthLimitExceeded { path, .. } if path == "root.FirstName.NS.bytes"),
+        "{error}"
+    );
+}
@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+
+use nscoder::{
+    AnyObject, Archive, ArchiveDict, ArchiveNode, Decoder, Encoder, Error, OutputFormat,
+    UnarchiveOptions,
+};
+use plist::{Dictionary, Uid, Value};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "RCDAddress")]
+struct Address {
+    #[serde(rename = "City")]
+    city: String,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Role {
+    Owner,
+    Member(u32),
+    Guest { invited_by: String },
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Person {
+    #[serde(rename = "Age")]
+    age: u32,
+    #[serde(rename = "FirstName")]
+    first_name: String,
+    #[serde(rename = "Nickname")]
+    nickname: Option<String>,
+    #[serde(rename = "Height")]
+    height: f64,
+    #[serde(rename = "Address")]
+    address: Address,
+    #[serde(rename = "Tags")]
+    tags: Vec<String>,
+    #[serde(rename = "Scores")]
+    scores: BTreeMap<String, i64>,
+    #[serde(rename = "Roles")]
+    roles: Vec<Role>,
+}
+
+fn person() -> Person {
+    Person {
+        age: 26,
+        first_name: "Cyan".to_owned(),
+        nickname: None,
+        height: 1.8,
+        address: Address {
+            city: "Hangzhou".to_owned(),
+        },
+        tags: vec!["a".to_owned(), "b".to_owned()],
+        scores: BTreeMap::from([("math".to_owned(), 90), ("art".to_owned(), 85)]),
+        roles: vec![
+            Role::Owner,
+            Role::Member(3),
+            Role::Guest {
+                invited_by: "Yang".to_owned(),
+            },
+        ],
+    }
+}
+
+#[test]
+fn test_serde() {
+    let person = person();
+    let encoded_bytes =
+        nscoder::serde::to_bytes(&person, "RCDPerson").expect("should encode successfully");
+
+    let root = ArchiveNode::from_bytes(&encoded_bytes).expect("should decode successfully");
+    let root = root.as_object().expect("root should be an object");
+    assert_eq!(root.classes, ["RCDPerson", "NSObject"]);
+    assert_eq!(root.get("Age").and_then(ArchiveNode::as_i64), Some(26));
+    assert_eq!(root.get("Nickname"), None);
+    let address = root
+        .get("Address")
+        .and_then(ArchiveNode::as_object)
+        .expect("address should be an object");
+    assert_eq!(address.class_name(), "RCDAddress");
+    let tags = root
+        .get("Tags")
+        .and_then(ArchiveNode::as_object)
+        .expect("tags should be an object");
+    assert_eq!(tags.class_name(), "NSArray");
+    let scores = root
+        .get("Scores")
+        .and_then(ArchiveNode::as_object)
+        .expect("scores should be an object");
+    assert_eq!(scores.class_name(), "NSDictionary");
+
+    let decoded_person: Person =
+        nscoder::serde::from_bytes(&encoded_bytes).expect("should decode successfully");
+    assert_eq!(decoded_person, person);
+}
+
+#[derive(Debug)]
+struct Team {
+    name: String,
+    members: Vec<AnyObject>,
+}
+
+impl Archive for Team {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDTeam"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        archiver.encode_string(&self.name, "Name");
+        archiver.encode_array(&self.members, "Members");
+    }
+
+    fn decode(_unarchiver: &dyn Decoder) -> Option<Self> {
+        None
+    }
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct DecodedTeam {
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "Members")]
+    members: Vec<String>,
+}
+
+#[test]
+fn test_serde_interop() {
+    let team = Team {
+        name: "Core".to_owned(),
+        members: vec![
+            AnyObject::erasing("Cyan".to_owned()),
+            AnyObject::erasing("Yang".to_owned()),
+        ],
+    };
+    let encoded_bytes = nscoder::to_bytes(&team).expect("should encode successfully");
+
+    let decoded_team: DecodedTeam =
+        nscoder::serde::from_bytes(&encoded_bytes).expect("should decode successfully");
+    assert_eq!(
+        decoded_team,
+        DecodedTeam {
+            name: "Core".to_owned(),
+            members: vec!["Cyan".to_owned(), "Yang".to_owned()],
+        }
+    );
+}
+
+#[test]
+fn test_serde_errors() {
+    let error =
+        nscoder::serde::to_bytes(&vec![1, 2], "RCDList").expect_err("should fail to encode");
+    assert!(matches!(error, Error::Serde(_)));
+
+    let error = nscoder::serde::to_bytes(&(Some(1), None::<i32>), "RCDPair");
+    assert!(matches!(error, Err(Error::Serde(_))));
+
+    let encoded_bytes =
+        nscoder::serde::to_bytes(&person(), "RCDPerson").expect("should encode successfully");
+    let error = nscoder::serde::from_bytes::<DecodedTeam>(&encoded_bytes)
+        .expect_err("should fail to decode");
+    assert!(matches!(error, Error::Serde(message) if message.contains("Name")));
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Node {
+    #[serde(rename = "Name")]
+    name: String,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct LinkedNode {
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "Parent")]
+    parent: Option<Box<LinkedNode>>,
+}
+
+#[test]
+fn test_serde_options() {
+    // The root node is its own parent.
+    let mut node = Dictionary::new();
+    node.insert("$class".to_owned(), Value::Uid(Uid::new(3)));
+    node.insert("Name".to_owned(), Value::Uid(Uid::new(2)));
+    node.insert("Parent".to_owned(), Value::Uid(Uid::new(1)));
+    let mut class_info = Dictionary::new();
+    class_info.insert("$classname".to_owned(), "RCDNode".into());
+    class_info.insert("$classes".to_owned(), Value::Array(vec!["RCDNode".into()]));
+    let dict = ArchiveDict {
+        archiver_class_name: "NSKeyedArchiver".to_owned(),
+        objects: vec![
+            "$null".into(),
+            Value::Dictionary(node),
+            "Root".into(),
+            Value::Dictionary(class_info),
+        ],
+        top_objects: [("root".to_owned(), Uid::new(1))].into(),
+        version: 100000,
+    };
+    let mut bytes = vec![];
+    dict.to_writer(&mut bytes, OutputFormat::Binary)
+        .expect("should encode successfully");
+
+    // Cyclic references can be ignored, but not deserialized.
+    let node: Node = nscoder::serde::from_bytes(&bytes).expect("should decode successfully");
+    assert_eq!(node.name, "Root");
+    let error =
+        nscoder::serde::from_bytes::<LinkedNode>(&bytes).expect_err("should fail to decode");
+    assert!(matches!(error, Error::CyclicReference(1)));
+
+    let mut options = UnarchiveOptions::new();
+    options.set_max_length(3);
+    let error = nscoder::serde::from_bytes_with_options::<Node>(&bytes, &options)
+        .expect_err("should fail to decode");
+    assert!(matches!(
+        error,
+        Error::LengthLimitExceeded { path, max_length: 3 } if path == "root.Name"
+    ));
+
+    let mut options = UnarchiveOptions::new();
+    options.set_max_size(bytes.len() - 1);
+    let error = nscoder::serde::from_bytes_with_options::<Node>(&bytes, &options)
+        .expect_err("should fail to decode");
+    assert!(matches!(error, Error::SizeLimitExceeded(_)));
+}
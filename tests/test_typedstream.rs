@@ -0,0 +1,232 @@
+use nscoder::{Archive, Decoder, Encoder, Error, NSArray, TypeRegistry, UnarchiveOptions};
+
+const HEADER: &[u8] = b"\x04\x0bstreamtyped\x81\xe8\x03";
+
+fn stream(body: &[&[u8]]) -> Vec<u8> {
+    let mut bytes = HEADER.to_vec();
+    for part in body {
+        bytes.extend_from_slice(part);
+    }
+    bytes
+}
+
+#[derive(Debug)]
+struct Person {
+    version: i64,
+    age: u32,
+    first_name: String,
+    last_name: String,
+}
+
+impl Archive for Person {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDPerson"
+    }
+
+    fn encode(&self, _archiver: &mut dyn Encoder) {}
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        let version = unarchiver.class_version("RCDPerson")?;
+        let age = unarchiver.decode_i32("Age") as u32;
+        let first_name = unarchiver.decode_string("FirstName")?;
+        let last_name = unarchiver.decode_string("LastName")?;
+        Some(Person {
+            version,
+            age,
+            first_name,
+            last_name,
+        })
+    }
+}
+
+fn person_stream() -> Vec<u8> {
+    stream(&[
+        // Type `@`, and a new object.
+        b"\x84\x01@\x84",
+        // Class `RCDPerson` version 2, whose superclass is `NSObject`.
+        b"\x84\x84\x09RCDPerson\x02",
+        b"\x84\x84\x08NSObject\x00\x85",
+        // Type `i`, and the age.
+        b"\x84\x01i\x1a",
+        // Type `@`, and a new `NSString` object.
+        b"\x92\x84\x84\x84\x08NSString\x01\x94",
+        b"\x84\x01+\x04Cyan\x86",
+        // Type `@`, and another `NSString` object with shared references.
+        b"\x92\x84\x96\x97\x04Yang\x86",
+        b"\x86",
+    ])
+}
+
+#[test]
+fn test_typedstream() {
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Person>();
+
+    let object = nscoder::typedstream::from_bytes(&person_stream(), &registry)
+        .expect("should decode successfully");
+    let person: &Person = object
+        .downcast_ref()
+        .expect("type of the value should be `Person`");
+
+    assert_eq!(person.version, 2);
+    assert_eq!(person.age, 26);
+    assert_eq!(person.first_name, "Cyan");
+    assert_eq!(person.last_name, "Yang");
+}
+
+#[test]
+fn test_typedstream_array() {
+    let bytes = stream(&[
+        b"\x84\x01@\x84",
+        b"\x84\x84\x07NSArray\x00",
+        b"\x84\x84\x08NSObject\x00\x85",
+        // The count of elements.
+        b"\x84\x01i\x02",
+        // An `NSNumber` object, whose superclass is `NSValue`.
+        b"\x92\x84\x84\x84\x08NSNumber\x00\x84\x84\x07NSValue\x00\x94",
+        b"\x84\x01q\x07\x86",
+        // Another `NSNumber` object with a 2-byte integer.
+        b"\x92\x84\x96\x95\x81\x2c\x01\x86",
+        b"\x86",
+    ]);
+
+    let registry = TypeRegistry::new();
+    let object =
+        nscoder::typedstream::from_bytes(&bytes, &registry).expect("should decode successfully");
+    let array: &NSArray = object
+        .downcast_ref()
+        .expect("type of the value should be `NSArray`");
+
+    let numbers: Vec<_> = array
+        .objects
+        .iter()
+        .map(|object| *object.downcast_ref::<i64>().expect("should be a number"))
+        .collect();
+    assert_eq!(numbers, [7, 300]);
+}
+
+#[test]
+fn test_typedstream_errors() {
+    let registry = TypeRegistry::new();
+
+    let mut bytes = person_stream();
+    bytes.truncate(bytes.len() - 8);
+    let error =
+        nscoder::typedstream::from_bytes(&bytes, &registry).expect_err("should fail to decode");
+    assert!(matches!(error, Error::MalformedTypedStream(_)));
+
+    let error = nscoder::typedstream::from_bytes(&person_stream(), &registry)
+        .expect_err("should fail to decode");
+    assert!(matches!(error, Error::UnknownClass(class) if class == "RCDPerson"));
+}
+
+#[test]
+fn test_typedstream_limits() {
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Person>();
+    let bytes = person_stream();
+    let decode = |options: &UnarchiveOptions| {
+        nscoder::typedstream::from_bytes_with_options(&bytes, &registry, options)
+    };
+
+    let mut options = UnarchiveOptions::new();
+    options.set_max_size(bytes.len());
+    options.set_max_depth(2);
+    options.set_max_length(4);
+    decode(&options).expect("should decode successfully");
+
+    let mut options = UnarchiveOptions::new();
+    options.set_max_size(bytes.len() - 1);
+    let error = decode(&options).expect_err("should fail to decode");
+    assert!(matches!(error, Error::SizeLimitExceeded(_)));
+
+    let mut options = UnarchiveOptions::new();
+    options.set_max_depth(1);
+    let error = decode(&options).expect_err("should fail to decode");
+    assert!(matches!(error, Error::DepthLimitExceeded { .. }));
+
+    let mut options = UnarchiveOptions::new();
+    options.set_max_length(3);
+    let error = decode(&options).expect_err("should fail to decode");
+    assert!(matches!(
+        error,
+        Error::LengthLimitExceeded { max_length: 3, .. }
+    ));
+}
+
+// A reference to the shared string or class or object with a given number.
+fn reference(number: usize) -> Vec<u8> {
+    let reference = number as i64 - 110;
+    if reference <= i8::MAX as i64 {
+        vec![reference as u8]
+    } else {
+        let mut bytes = vec![0x81];
+        bytes.extend((reference as i16).to_le_bytes());
+        bytes
+    }
+}
+
+// An array whose elements are arrays, each containing the previous one.
+fn chain_stream(count: usize) -> Vec<u8> {
+    let mut bytes = stream(&[
+        b"\x84\x01@\x84",
+        b"\x84\x84\x07NSArray\x00",
+        b"\x84\x84\x08NSObject\x00\x85",
+        b"\x84\x01i\x81",
+    ]);
+    bytes.extend((count as i16).to_le_bytes());
+    for index in 0..count {
+        // Type `@`, and a new `NSArray` object.
+        bytes.extend(b"\x92\x84\x93\x95");
+        if index == 0 {
+            bytes.push(0);
+        } else {
+            // The previous element, which is numbered after the root object
+            // and the two classes.
+            bytes.extend(b"\x01\x92");
+            bytes.extend(reference(index + 2));
+        }
+        bytes.push(0x86);
+    }
+    bytes.push(0x86);
+    bytes
+}
+
+#[test]
+fn test_typedstream_depth() {
+    let registry = TypeRegistry::new();
+    let decode = |bytes: &[u8]| nscoder::typedstream::from_bytes(bytes, &registry);
+
+    let object = decode(&chain_stream(50)).expect("should decode successfully");
+    let array: &NSArray = object
+        .downcast_ref()
+        .expect("type of the value should be `NSArray`");
+    assert_eq!(array.objects.len(), 50);
+
+    // Sibling objects that reference each other are decoded recursively.
+    let error = decode(&chain_stream(1000)).expect_err("should fail to decode");
+    assert!(matches!(error, Error::MalformedTypedStream(_)));
+
+    // A class with a long chain of superclasses.
+    let mut bytes = stream(&[b"\x84\x01@\x84"]);
+    for index in 0..1000 {
+        let name = format!("RCDClass{index}");
+        bytes.extend([0x84, 0x84, name.len() as u8]);
+        bytes.extend(name.as_bytes());
+        bytes.push(0);
+    }
+    bytes.extend(b"\x85\x86");
+    let error = decode(&bytes).expect_err("should fail to decode");
+    assert!(matches!(error, Error::MalformedTypedStream(_)));
+
+    // Deeply nested arrays and structs in a type encoding.
+    let encoding = format!("{}i{}", "[1{".repeat(1000), "}]".repeat(1000));
+    let mut bytes = stream(&[b"\x84\x81"]);
+    bytes.extend((encoding.len() as u16).to_le_bytes());
+    bytes.extend(encoding.as_bytes());
+    bytes.push(7);
+    let error = decode(&bytes).expect_err("should fail to decode");
+    assert!(matches!(error, Error::MalformedTypedStream(_)));
+}
@@ -1,4 +1,11 @@
-use nscoder::{Archive, Decoder, Encoder, TypeRegistry};
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use nscoder::{
+    AnyObject, Archive, ArchiveNode, Decoder, Encoder, Error, NSArray, ObjectRef, TypeRegistry,
+    UnarchiveOptions, WeakObjectRef,
+};
+use plist::{Dictionary, Uid, Value};
 
 #[derive(Debug)]
 struct MBFile {
@@ -47,3 +54,1009 @@ fn test_unarchive() {
     assert_eq!(file.inode_number, 228000);
     assert_eq!(file.relative_path, "Library/PersistentStores");
 }
+
+#[derive(Debug)]
+struct Node {
+    name: String,
+    left: Option<ObjectRef>,
+    right: Option<ObjectRef>,
+}
+
+impl Archive for Node {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDNode"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        archiver.encode_string(&self.name, "Name");
+    }
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        let name = unarchiver.decode_string("Name")?;
+        let left = unarchiver.decode_shared_object("Left");
+        let right = unarchiver.decode_shared_object("Right");
+        Some(Node { name, left, right })
+    }
+}
+
+/// Builds a keyed archive whose root object is the first object after `$null`.
+fn keyed_archive(objects: Vec<Value>) -> Vec<u8> {
+    let mut archive = Dictionary::new();
+    archive.insert("$archiver".to_owned(), "NSKeyedArchiver".into());
+    archive.insert("$version".to_owned(), 100000.into());
+    archive.insert(
+        "$top".to_owned(),
+        Value::Dictionary(Dictionary::from_iter([("root", Value::Uid(Uid::new(1)))])),
+    );
+    let objects = std::iter::once("$null".into()).chain(objects).collect();
+    archive.insert("$objects".to_owned(), Value::Array(objects));
+
+    let mut bytes = Vec::new();
+    Value::Dictionary(archive)
+        .to_writer_binary(&mut bytes)
+        .expect("should write successfully");
+    bytes
+}
+
+fn node(name: u64, class: u64, left: Option<u64>, right: Option<u64>) -> Value {
+    let mut dict = Dictionary::new();
+    dict.insert("$class".to_owned(), Value::Uid(Uid::new(class)));
+    dict.insert("Name".to_owned(), Value::Uid(Uid::new(name)));
+    if let Some(left) = left {
+        dict.insert("Left".to_owned(), Value::Uid(Uid::new(left)));
+    }
+    if let Some(right) = right {
+        dict.insert("Right".to_owned(), Value::Uid(Uid::new(right)));
+    }
+    Value::Dictionary(dict)
+}
+
+fn class_info(class: &str, super_class: &str) -> Value {
+    let mut classes = vec![class.into(), super_class.into()];
+    if super_class != "NSObject" {
+        classes.push("NSObject".into());
+    }
+    Value::Dictionary(Dictionary::from_iter([
+        ("$classname", Value::from(class)),
+        ("$classes", Value::Array(classes)),
+    ]))
+}
+
+#[test]
+fn test_unarchive_shared_object() {
+    // The root node references the same child with both keys.
+    let bytes = keyed_archive(vec![
+        node(3, 4, Some(2), Some(2)),
+        node(5, 4, None, None),
+        "Root".into(),
+        class_info("RCDNode", "NSObject"),
+        "Child".into(),
+    ]);
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Node>();
+
+    let object = nscoder::from_bytes(&bytes, &registry).expect("should decode successfully");
+    let root: &Node = object
+        .downcast_ref()
+        .expect("type of the value should be `Node`");
+    let left = root.left.as_ref().expect("left child should exist");
+    let right = root.right.as_ref().expect("right child should exist");
+
+    assert!(Rc::ptr_eq(left, right));
+    let child: &Node = left
+        .downcast_ref()
+        .expect("type of the value should be `Node`");
+    assert_eq!(child.name, "Child");
+}
+
+#[test]
+fn test_unarchive_cyclic_reference() {
+    // The root node and its child reference each other.
+    let bytes = keyed_archive(vec![
+        node(3, 4, Some(2), None),
+        node(5, 4, Some(1), None),
+        "Root".into(),
+        class_info("RCDNode", "NSObject"),
+        "Child".into(),
+    ]);
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Node>();
+
+    let error = nscoder::from_bytes(&bytes, &registry).expect_err("should fail to decode");
+    assert!(matches!(error, Error::CyclicReference(1)));
+}
+
+#[test]
+fn test_unarchive_shared_element() {
+    // Both inner arrays contain the same child.
+    let bytes = keyed_archive(vec![
+        array(&[2, 3]),
+        array(&[4]),
+        array(&[4]),
+        node(6, 7, None, None),
+        class_info("NSArray", "NSObject"),
+        "Child".into(),
+        class_info("RCDNode", "NSObject"),
+    ]);
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Node>();
+
+    let object = nscoder::from_bytes(&bytes, &registry).expect("should decode successfully");
+    let array: &NSArray = object
+        .downcast_ref()
+        .expect("type of the value should be `NSArray`");
+    let elements: Vec<&NSArray> = array
+        .objects
+        .iter()
+        .map(|object| object.downcast_ref().expect("should be an array"))
+        .collect();
+
+    assert!(Rc::ptr_eq(&elements[0].objects[0], &elements[1].objects[0]));
+    let child: &Node = elements[0].objects[0]
+        .downcast_ref()
+        .expect("type of the value should be `Node`");
+    assert_eq!(child.name, "Child");
+}
+
+fn array(objects: &[u64]) -> Value {
+    let objects = objects.iter().map(|uid| Value::Uid(Uid::new(*uid)));
+    Value::Dictionary(Dictionary::from_iter([
+        ("$class", Value::Uid(Uid::new(5))),
+        ("NS.objects", Value::Array(objects.collect())),
+    ]))
+}
+
+#[derive(Debug)]
+struct TreeNode {
+    name: String,
+    parent: Option<WeakObjectRef>,
+    children: Vec<ObjectRef>,
+}
+
+impl Archive for TreeNode {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDTreeNode"
+    }
+
+    fn encode(&self, archiver: &mut dyn Encoder) {
+        archiver.encode_string(&self.name, "Name");
+        if let Some(parent) = &self.parent {
+            archiver.encode_weak_object(parent, "Parent");
+        }
+        archiver.encode_shared_objects(&self.children, "Children");
+    }
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        let name = unarchiver.decode_string("Name")?;
+        let parent = unarchiver.decode_weak_object("Parent");
+        let children = unarchiver.decode_shared_objects("Children")?;
+        Some(TreeNode {
+            name,
+            parent,
+            children,
+        })
+    }
+}
+
+#[test]
+fn test_unarchive_weak_reference() {
+    // The child references its parent, which is being decoded at the time.
+    let parent = Rc::new_cyclic(|parent| {
+        let child = TreeNode {
+            name: "Child".to_owned(),
+            parent: Some(parent.clone()),
+            children: vec![],
+        };
+        AnyObject::erasing(TreeNode {
+            name: "Parent".to_owned(),
+            parent: None,
+            children: vec![Rc::new(AnyObject::erasing(child))],
+        })
+    });
+    let mut array = NSArray::default();
+    array.objects.push(parent);
+    let bytes = nscoder::to_bytes(&array).expect("should encode successfully");
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<TreeNode>();
+
+    let object = nscoder::from_bytes(&bytes, &registry).expect("should decode successfully");
+    let array: &NSArray = object
+        .downcast_ref()
+        .expect("type of the value should be `NSArray`");
+    let parent = &array.objects[0];
+    let child: &TreeNode = parent
+        .downcast_ref::<TreeNode>()
+        .expect("type of the value should be `TreeNode`")
+        .children[0]
+        .downcast_ref()
+        .expect("type of the value should be `TreeNode`");
+
+    assert_eq!(child.name, "Child");
+    let child_parent = child.parent.as_ref().and_then(|p| p.upgrade());
+    assert!(child_parent.is_some_and(|p| Rc::ptr_eq(&p, parent)));
+}
+
+#[derive(Debug)]
+struct Folder {
+    files: Vec<MBFile>,
+}
+
+impl Archive for Folder {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDFolder"
+    }
+
+    fn encode(&self, _archiver: &mut dyn Encoder) {}
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        let files = unarchiver.decode_array_of::<MBFile>("Files")?;
+        Some(Folder { files })
+    }
+}
+
+fn object(class: u64, fields: Vec<(&str, Value)>) -> Value {
+    let mut dict = Dictionary::from_iter(fields);
+    dict.insert("$class".to_owned(), Value::Uid(Uid::new(class)));
+    Value::Dictionary(dict)
+}
+
+fn file(relative_path: u64) -> Value {
+    object(
+        7,
+        vec![
+            ("GroupID", 501.into()),
+            ("InodeNumber", 228000.into()),
+            ("RelativePath", Value::Uid(Uid::new(relative_path))),
+        ],
+    )
+}
+
+fn folder_archive(second_relative_path: Value) -> Vec<u8> {
+    keyed_archive(vec![
+        object(2, vec![("Files", Value::Uid(Uid::new(3)))]),
+        class_info("RCDFolder", "NSObject"),
+        object(
+            4,
+            vec![(
+                "NS.objects",
+                Value::Array(vec![Value::Uid(Uid::new(5)), Value::Uid(Uid::new(6))]),
+            )],
+        ),
+        class_info("NSArray", "NSObject"),
+        file(8),
+        file(9),
+        class_info("MBFile", "NSObject"),
+        "Library/PersistentStores".into(),
+        second_relative_path,
+    ])
+}
+
+#[test]
+fn test_unarchive_error_path() {
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Folder>();
+    registry.register_type::<MBFile>();
+
+    let bytes = folder_archive("Library/Preferences".into());
+    let object = nscoder::from_bytes(&bytes, &registry).expect("should decode successfully");
+    let folder: &Folder = object
+        .downcast_ref()
+        .expect("type of the value should be `Folder`");
+    assert_eq!(folder.files[1].relative_path, "Library/Preferences");
+
+    // The relative path of the second file is not a string.
+    let bytes = folder_archive(42.into());
+    let error = nscoder::from_bytes(&bytes, &registry).expect_err("should fail to decode");
+    let Error::TypeMismatch {
+        path,
+        expected,
+        actual,
+    } = error
+    else {
+        panic!("unexpected error: {error}");
+    };
+    assert_eq!(path, "root.Files[1].RelativePath");
+    assert_eq!(expected, "string");
+    assert_eq!(actual, "integer");
+}
+
+#[test]
+fn test_unarchive_mutable_string_and_data() {
+    let bytes = keyed_archive(vec![
+        object(
+            2,
+            vec![(
+                "NS.objects",
+                Value::Array(vec![
+                    Value::Uid(Uid::new(3)),
+                    Value::Uid(Uid::new(5)),
+                    Value::Uid(Uid::new(7)),
+                ]),
+            )],
+        ),
+        class_info("NSArray", "NSObject"),
+        // Mutable strings and data store their contents inline.
+        object(4, vec![("NS.string", "Cyan".into())]),
+        class_info("NSMutableString", "NSString"),
+        object(6, vec![("NS.data", Value::Data(b"Yang".to_vec()))]),
+        class_info("NSMutableData", "NSData"),
+        Value::Integer(u64::MAX.into()),
+    ]);
+    let object =
+        nscoder::from_bytes(&bytes, &TypeRegistry::new()).expect("should decode successfully");
+    let array: &nscoder::NSArray = object
+        .downcast_ref()
+        .expect("type of the value should be `NSArray`");
+    assert_eq!(
+        array.objects[0]
+            .downcast_ref::<String>()
+            .map(String::as_str),
+        Some("Cyan")
+    );
+    assert_eq!(
+        array.objects[1]
+            .downcast_ref::<Vec<u8>>()
+            .map(Vec::as_slice),
+        Some(&b"Yang"[..])
+    );
+    assert_eq!(array.objects[2].downcast_ref::<u64>(), Some(&u64::MAX));
+}
+
+#[test]
+fn test_unarchive_limits() {
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Folder>();
+    registry.register_type::<MBFile>();
+
+    let bytes = folder_archive("Library/Preferences".into());
+    let decode =
+        |options: &UnarchiveOptions| nscoder::from_bytes_with_options(&bytes, &registry, options);
+
+    // The folder, the array and two files are nested in three levels.
+    let mut options = UnarchiveOptions::new();
+    options.set_max_depth(3);
+    options.set_max_objects(4);
+    options.set_max_length(24);
+    decode(&options).expect("should decode successfully");
+
+    let mut options = UnarchiveOptions::new();
+    options.set_max_depth(2);
+    let error = decode(&options).expect_err("should fail to decode");
+    assert!(matches!(error, Error::DepthLimitExceeded { path } if path == "root.Files[0]"));
+
+    let mut options = UnarchiveOptions::new();
+    options.set_max_objects(3);
+    let error = decode(&options).expect_err("should fail to decode");
+    assert!(matches!(error, Error::ObjectLimitExceeded(3)));
+
+    let mut options = UnarchiveOptions::new();
+    options.set_max_length(20);
+    let error = decode(&options).expect_err("should fail to decode");
+    assert!(matches!(
+        error,
+        Error::LengthLimitExceeded { path, max_length: 20 }
+            if path == "root.Files[0].RelativePath"
+    ));
+}
+
+#[test]
+fn test_unarchive_size_limit() {
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<MBFile>();
+
+    let path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/mobilesync_backup.plist"
+    );
+    let bytes = std::fs::read(path).expect("should read the fixture");
+
+    let mut options = UnarchiveOptions::new();
+    options.set_max_size(bytes.len());
+    nscoder::from_bytes_with_options(&bytes, &registry, &options)
+        .expect("should decode successfully");
+    nscoder::from_file_with_options(path, &registry, &options).expect("should decode successfully");
+
+    // The data is rejected before it is parsed, so even garbage fails this way.
+    options.set_max_size(bytes.len() - 1);
+    let error = nscoder::from_bytes_with_options(&bytes, &registry, &options)
+        .expect_err("should fail to decode");
+    assert!(matches!(error, Error::SizeLimitExceeded(size) if size == bytes.len() - 1));
+    let error = nscoder::from_bytes_with_options(&vec![0; bytes.len()], &registry, &options)
+        .expect_err("should fail to decode");
+    assert!(matches!(error, Error::SizeLimitExceeded(_)));
+    let error = nscoder::from_file_with_options(path, &registry, &options)
+        .expect_err("should fail to decode");
+    assert!(matches!(error, Error::SizeLimitExceeded(_)));
+    let error =
+        ArchiveNode::from_file_with_options(path, &options).expect_err("should fail to decode");
+    assert!(matches!(error, Error::SizeLimitExceeded(_)));
+}
+
+#[derive(Debug)]
+struct StrictMBFile;
+
+impl Archive for StrictMBFile {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "MBFile"
+    }
+
+    fn encode(&self, _archiver: &mut dyn Encoder) {}
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        assert_eq!(unarchiver.try_decode_i32("GroupID").ok(), Some(501));
+        unarchiver.try_decode_i64("Generation").ok()?;
+        Some(StrictMBFile)
+    }
+}
+
+#[test]
+fn test_unarchive_missing_key() {
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<StrictMBFile>();
+
+    let bytes = include_bytes!("./fixtures/mobilesync_backup.plist");
+    let error = nscoder::from_bytes(bytes, &registry).expect_err("should fail to decode");
+    assert!(matches!(error, Error::MissingKey { path } if path == "root.Generation"));
+}
+
+// Rejects every file, after looking up a value that is absent.
+#[derive(Debug)]
+struct PickyMBFile;
+
+impl Archive for PickyMBFile {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "MBFile"
+    }
+
+    fn encode(&self, _archiver: &mut dyn Encoder) {}
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        let _ = unarchiver.decode_i64("Generation");
+        unarchiver.decode_string("RelativePath")?;
+        None
+    }
+}
+
+#[test]
+fn test_unarchive_decode_failed() {
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<PickyMBFile>();
+
+    // The absent value is not the reason of the failure.
+    let bytes = include_bytes!("./fixtures/mobilesync_backup.plist");
+    let error = nscoder::from_bytes(bytes, &registry).expect_err("should fail to decode");
+    assert!(matches!(
+        error,
+        Error::DecodeFailed { path, class } if path == "root" && class == "MBFile"
+    ));
+}
+
+#[derive(Debug)]
+struct MBFileGroup;
+
+impl Archive for MBFileGroup {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "MBFile"
+    }
+
+    fn encode(&self, _archiver: &mut dyn Encoder) {}
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        unarchiver.try_decode_i32("GroupID").ok()?;
+        Some(MBFileGroup)
+    }
+}
+
+#[test]
+fn test_unarchive_integer_out_of_range() {
+    let mut file = Dictionary::new();
+    file.insert("$class".to_owned(), Value::Uid(Uid::new(2)));
+    file.insert("GroupID".to_owned(), Value::from(1_i64 << 40));
+    let bytes = keyed_archive(vec![
+        Value::Dictionary(file),
+        class_info("MBFile", "NSObject"),
+    ]);
+
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<MBFileGroup>();
+    let error = nscoder::from_bytes(&bytes, &registry).expect_err("should fail to decode");
+    assert!(matches!(
+        error,
+        Error::TypeMismatch { path, expected: "32-bit integer", .. } if path == "root.GroupID"
+    ));
+}
+
+#[derive(Debug)]
+struct MBFileKeys {
+    keys: Vec<String>,
+    has_group_id: bool,
+    has_generation: bool,
+}
+
+impl Archive for MBFileKeys {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "MBFile"
+    }
+
+    fn encode(&self, _archiver: &mut dyn Encoder) {}
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        Some(MBFileKeys {
+            keys: unarchiver.keys(),
+            has_group_id: unarchiver.contains_key("GroupID"),
+            has_generation: unarchiver.contains_key("Generation"),
+        })
+    }
+}
+
+#[test]
+fn test_unarchive_keys() {
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<MBFileKeys>();
+
+    let bytes = include_bytes!("./fixtures/mobilesync_backup.plist");
+    let object = nscoder::from_bytes(bytes, &registry).expect("should decode successfully");
+    let file: &MBFileKeys = object
+        .downcast_ref()
+        .expect("type of the value should be `MBFileKeys`");
+
+    assert!(file.has_group_id);
+    assert!(!file.has_generation);
+    assert_eq!(file.keys.len(), 11);
+    assert!(file.keys.iter().any(|key| key == "RelativePath"));
+    assert!(!file.keys.iter().any(|key| key == "$class"));
+}
+
+#[test]
+fn test_unarchive_untyped() {
+    let bytes = include_bytes!("./fixtures/mobilesync_backup.plist");
+    let root = ArchiveNode::from_bytes(bytes).expect("should decode successfully");
+    let file = root.as_object().expect("root should be an object");
+
+    assert_eq!(file.class_name(), "MBFile");
+    assert_eq!(file.classes, ["MBFile", "NSObject"]);
+    assert_eq!(file.get("GroupID").and_then(ArchiveNode::as_i64), Some(501));
+    assert_eq!(
+        file.get("RelativePath").and_then(ArchiveNode::as_str),
+        Some("Library/PersistentStores")
+    );
+    assert!(file.get("$class").is_none());
+}
+
+#[test]
+fn test_unarchive_untyped_references() {
+    // The root node references the child twice, and the child points back.
+    let bytes = keyed_archive(vec![
+        node(3, 4, Some(2), Some(2)),
+        node(5, 4, Some(1), None),
+        "Root".into(),
+        class_info("RCDNode", "NSObject"),
+        "Child".into(),
+    ]);
+
+    let root = ArchiveNode::from_bytes(&bytes).expect("should decode successfully");
+    let root = root.as_object().expect("root should be an object");
+    assert_eq!(root.classes, ["RCDNode", "NSObject"]);
+
+    let (Some(ArchiveNode::Object(left)), Some(ArchiveNode::Object(right))) =
+        (root.get("Left"), root.get("Right"))
+    else {
+        panic!("children should be objects");
+    };
+    assert!(Rc::ptr_eq(left, right));
+    assert_eq!(
+        left.get("Name").and_then(ArchiveNode::as_str),
+        Some("Child")
+    );
+    assert_eq!(left.get("Left"), Some(&ArchiveNode::Reference(1)));
+}
+
+#[test]
+fn test_unarchive_untyped_dictionaries() {
+    // The root node references its class info, and has an inline dictionary.
+    let options = Dictionary::from_iter([
+        ("Label", Value::Uid(Uid::new(2))),
+        ("Enabled", Value::Boolean(true)),
+    ]);
+    let root = Dictionary::from_iter([
+        ("$class", Value::Uid(Uid::new(3))),
+        ("Name", Value::Uid(Uid::new(2))),
+        ("Info", Value::Uid(Uid::new(3))),
+        ("Options", Value::Dictionary(options)),
+    ]);
+    let bytes = keyed_archive(vec![
+        Value::Dictionary(root),
+        "Root".into(),
+        class_info("RCDNode", "NSObject"),
+    ]);
+
+    let root = ArchiveNode::from_bytes(&bytes).expect("should decode successfully");
+    let root = root.as_object().expect("root should be an object");
+
+    let info = root
+        .get("Info")
+        .and_then(ArchiveNode::as_object)
+        .expect("class info should be an object");
+    assert_eq!(info.uid, 3);
+    assert_eq!(info.classes, ["RCDNode", "NSObject"]);
+    assert_eq!(
+        info.get("$classname").and_then(ArchiveNode::as_str),
+        Some("RCDNode")
+    );
+
+    let Some(ArchiveNode::Dictionary(options)) = root.get("Options") else {
+        panic!("options should be a dictionary");
+    };
+    assert!(options.contains(&("Label".to_owned(), ArchiveNode::String("Root".to_owned()))));
+    assert!(options.contains(&("Enabled".to_owned(), ArchiveNode::Boolean(true))));
+}
+
+#[test]
+fn test_unarchive_untyped_limits() {
+    // Integers above `i64::MAX` are kept as they are.
+    let bytes = keyed_archive(vec![
+        object(2, vec![("Size", Value::Integer(u64::MAX.into()))]),
+        class_info("RCDFile", "NSObject"),
+    ]);
+    let root = ArchiveNode::from_bytes(&bytes).expect("should decode successfully");
+    let size = root.as_object().and_then(|root| root.get("Size"));
+    assert_eq!(size, Some(&ArchiveNode::UnsignedInteger(u64::MAX)));
+    assert_eq!(size.and_then(ArchiveNode::as_u64), Some(u64::MAX));
+    assert_eq!(size.and_then(ArchiveNode::as_i64), None);
+
+    // A long chain of nodes is rejected rather than overflowing the stack.
+    let count = 1000;
+    let mut objects: Vec<_> = (1..=count)
+        .map(|uid| node(count + 1, count + 2, (uid < count).then_some(uid + 1), None))
+        .collect();
+    objects.push("Node".into());
+    objects.push(class_info("RCDNode", "NSObject"));
+    let bytes = keyed_archive(objects);
+    let error = ArchiveNode::from_bytes(&bytes).expect_err("should fail to decode");
+    assert!(
+        matches!(error, Error::DepthLimitExceeded { path } if path.starts_with("root.Left.Left"))
+    );
+
+    // The limits of the options apply too.
+    let bytes = folder_archive("Library/Preferences".into());
+    let mut options = UnarchiveOptions::new();
+    options.set_max_depth(2);
+    let error =
+        ArchiveNode::from_bytes_with_options(&bytes, &options).expect_err("should fail to decode");
+    assert!(
+        matches!(error, Error::DepthLimitExceeded { path } if path == "root.Files.NS.objects[0]")
+    );
+
+    let mut options = UnarchiveOptions::new();
+    options.set_max_objects(3);
+    let error =
+        ArchiveNode::from_bytes_with_options(&bytes, &options).expect_err("should fail to decode");
+    assert!(matches!(error, Error::ObjectLimitExceeded(3)));
+
+    let mut options = UnarchiveOptions::new();
+    options.set_max_length(20);
+    let error =
+        ArchiveNode::from_bytes_with_options(&bytes, &options).expect_err("should fail to decode");
+    assert!(matches!(
+        error,
+        Error::LengthLimitExceeded { path, max_length: 20 }
+            if path == "root.Files.NS.objects[0].RelativePath"
+    ));
+
+    let mut options = UnarchiveOptions::new();
+    options.set_max_depth(3);
+    options.set_max_length(24);
+    ArchiveNode::from_bytes_with_options(&bytes, &options).expect("should decode successfully");
+}
+
+fn document_archive() -> Vec<u8> {
+    keyed_archive(vec![
+        object(
+            3,
+            vec![
+                ("GroupID", 501.into()),
+                ("InodeNumber", 228000.into()),
+                ("RelativePath", Value::Uid(Uid::new(2))),
+            ],
+        ),
+        "Library/PersistentStores".into(),
+        class_info("MyDocumentV2", "MBFile"),
+    ])
+}
+
+#[test]
+fn test_unarchive_superclass_fallback() {
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<MBFile>();
+
+    let bytes = document_archive();
+    let error = nscoder::from_bytes(&bytes, &registry).expect_err("should fail to decode");
+    assert!(matches!(error, Error::UnknownClass(class) if class == "MyDocumentV2"));
+
+    registry.set_superclass_fallback(true);
+    let object = nscoder::from_bytes(&bytes, &registry).expect("should decode successfully");
+    assert_eq!(object.class_name(), "MBFile");
+    assert_eq!(object.substituted_class_name(), Some("MyDocumentV2"));
+    let file: &MBFile = object
+        .downcast_ref()
+        .expect("type of the value should be `MBFile`");
+    assert_eq!(file.relative_path, "Library/PersistentStores");
+
+    // Allowing a class does not allow its subclasses to decode as it.
+    let mut options = UnarchiveOptions::new();
+    options.set_allowed_classes(&["MBFile"]);
+    let error = nscoder::from_bytes_with_options(&bytes, &registry, &options)
+        .expect_err("should fail to decode");
+    assert!(matches!(
+        error,
+        Error::DisallowedClass { path, class } if path == "root" && class == "MyDocumentV2"
+    ));
+    options.set_allowed_classes(&["MyDocumentV2"]);
+    nscoder::from_bytes_with_options(&bytes, &registry, &options)
+        .expect("should decode successfully");
+
+    // The root class is never used as a substitute.
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<Node>();
+    registry.set_superclass_fallback(true);
+    let error = nscoder::from_bytes(&bytes, &registry).expect_err("should fail to decode");
+    assert!(matches!(error, Error::UnknownClass(class) if class == "MyDocumentV2"));
+}
+
+/// The classes that a `SecureFolder` allows for its files.
+trait Allowlist: std::fmt::Debug + 'static {
+    const CLASSES: &'static [&'static str];
+}
+
+#[derive(Debug)]
+struct FileAllowlist;
+
+impl Allowlist for FileAllowlist {
+    const CLASSES: &'static [&'static str] = &["NSArray", "MBFile"];
+}
+
+#[derive(Debug)]
+struct ArrayAllowlist;
+
+impl Allowlist for ArrayAllowlist {
+    const CLASSES: &'static [&'static str] = &["NSArray"];
+}
+
+#[derive(Debug)]
+struct MutableFileAllowlist;
+
+impl Allowlist for MutableFileAllowlist {
+    const CLASSES: &'static [&'static str] = &["NSMutableArray", "MBFile"];
+}
+
+#[derive(Debug)]
+struct SecureFolder<A> {
+    files: AnyObject,
+    allowlist: PhantomData<A>,
+}
+
+impl<A: Allowlist> Archive for SecureFolder<A> {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDFolder"
+    }
+
+    fn encode(&self, _archiver: &mut dyn Encoder) {}
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        let files = unarchiver.decode_object_of_classes(A::CLASSES, "Files")?;
+        Some(SecureFolder {
+            files,
+            allowlist: PhantomData,
+        })
+    }
+}
+
+fn secure_folder_archive() -> Vec<u8> {
+    keyed_archive(vec![
+        object(2, vec![("Files", Value::Uid(Uid::new(3)))]),
+        class_info("RCDFolder", "NSObject"),
+        object(
+            4,
+            vec![(
+                "NS.objects",
+                Value::Array(vec![Value::Uid(Uid::new(5)), Value::Uid(Uid::new(6))]),
+            )],
+        ),
+        class_info("NSArray", "NSObject"),
+        file(8),
+        file(9),
+        class_info("MBFile", "NSObject"),
+        "Library/PersistentStores".into(),
+        "Library/Preferences".into(),
+    ])
+}
+
+fn folder_registry<A: Allowlist>() -> TypeRegistry {
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<SecureFolder<A>>();
+    registry.register_type::<MBFile>();
+    registry
+}
+
+#[test]
+fn test_unarchive_allowed_classes() {
+    let bytes = secure_folder_archive();
+    let registry = folder_registry::<FileAllowlist>();
+    let object = nscoder::from_bytes(&bytes, &registry).expect("should decode successfully");
+    let folder: &SecureFolder<FileAllowlist> = object
+        .downcast_ref()
+        .expect("type of the value should be `SecureFolder`");
+    assert_eq!(folder.files.class_name(), "NSArray");
+
+    // Elements of the array are checked against the same allowlist.
+    let registry = folder_registry::<ArrayAllowlist>();
+    let error = nscoder::from_bytes(&bytes, &registry).expect_err("should fail to decode");
+    assert!(matches!(
+        error,
+        Error::DisallowedClass { path, class } if path == "root.Files[0]" && class == "MBFile"
+    ));
+
+    // Subclasses are not allowed implicitly.
+    let registry = folder_registry::<MutableFileAllowlist>();
+    let error = nscoder::from_bytes(&bytes, &registry).expect_err("should fail to decode");
+    assert!(matches!(
+        error,
+        Error::DisallowedClass { path, class } if path == "root.Files" && class == "NSArray"
+    ));
+
+    // The root object is checked against the allowlist of the options, the
+    // files are decoded with their own allowlist.
+    let registry = folder_registry::<FileAllowlist>();
+    let mut options = UnarchiveOptions::new();
+    options.set_allowed_classes(&["RCDFolder"]);
+    nscoder::from_bytes_with_options(&bytes, &registry, &options)
+        .expect("should decode successfully");
+
+    let mut options = UnarchiveOptions::new();
+    options.set_allowed_classes(&["NSArray", "MBFile"]);
+    let error = nscoder::from_bytes_with_options(&bytes, &registry, &options)
+        .expect_err("should fail to decode");
+    assert!(matches!(
+        error,
+        Error::DisallowedClass { path, class } if path == "root" && class == "RCDFolder"
+    ));
+
+    // Untyped object graphs allow only the given classes anywhere.
+    let mut options = UnarchiveOptions::new();
+    options.set_allowed_classes(&["RCDFolder", "NSArray"]);
+    let error =
+        ArchiveNode::from_bytes_with_options(&bytes, &options).expect_err("should fail to decode");
+    assert!(matches!(
+        error,
+        Error::DisallowedClass { path, class }
+            if path == "root.Files.NS.objects[0]" && class == "MBFile"
+    ));
+    options.set_allowed_classes(&["RCDFolder", "NSArray", "MBFile"]);
+    ArchiveNode::from_bytes_with_options(&bytes, &options).expect("should decode successfully");
+}
+
+#[derive(Debug)]
+struct FileBox {
+    file: Option<MBFile>,
+}
+
+impl Archive for FileBox {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDFileBox"
+    }
+
+    fn encode(&self, _archiver: &mut dyn Encoder) {}
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        let file = unarchiver.decode_object_of::<MBFile>("File");
+        Some(FileBox { file })
+    }
+}
+
+fn file_box_archive(class: &str) -> Vec<u8> {
+    keyed_archive(vec![
+        object(2, vec![("File", Value::Uid(Uid::new(3)))]),
+        class_info("RCDFileBox", "NSObject"),
+        object(
+            4,
+            vec![
+                ("GroupID", 501.into()),
+                ("InodeNumber", 228000.into()),
+                ("RelativePath", Value::Uid(Uid::new(5))),
+            ],
+        ),
+        class_info(class, "NSObject"),
+        "Library/PersistentStores".into(),
+    ])
+}
+
+#[test]
+fn test_unarchive_object_of() {
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<FileBox>();
+    registry.register_type::<MBFile>();
+    registry.register_type::<Node>();
+
+    let bytes = file_box_archive("MBFile");
+    let object = nscoder::from_bytes(&bytes, &registry).expect("should decode successfully");
+    let file_box: &FileBox = object
+        .downcast_ref()
+        .expect("type of the value should be `FileBox`");
+    let file = file_box.file.as_ref().expect("file should be decoded");
+    assert_eq!(file.relative_path, "Library/PersistentStores");
+
+    // `RCDNode` is registered, but not expected at this key.
+    let bytes = file_box_archive("RCDNode");
+    let error = nscoder::from_bytes(&bytes, &registry).expect_err("should fail to decode");
+    assert!(matches!(
+        error,
+        Error::DisallowedClass { path, class } if path == "root.File" && class == "RCDNode"
+    ));
+    // Another type is registered with the class name of `MBFile`.
+    let mut registry = TypeRegistry::new();
+    registry.register_type::<CheckedFileBox>();
+    registry.register_type::<OtherMBFile>();
+    let bytes = file_box_archive("MBFile");
+    nscoder::from_bytes(&bytes, &registry).expect("should decode successfully");
+}
+
+#[derive(Debug)]
+struct OtherMBFile;
+
+impl Archive for OtherMBFile {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "MBFile"
+    }
+
+    fn encode(&self, _archiver: &mut dyn Encoder) {}
+
+    fn decode(_unarchiver: &dyn Decoder) -> Option<Self> {
+        Some(OtherMBFile)
+    }
+}
+
+#[derive(Debug)]
+struct CheckedFileBox;
+
+impl Archive for CheckedFileBox {
+    type Super = nscoder::RootObject;
+
+    fn class_name() -> &'static str {
+        "RCDFileBox"
+    }
+
+    fn encode(&self, _archiver: &mut dyn Encoder) {}
+
+    fn decode(unarchiver: &dyn Decoder) -> Option<Self> {
+        let error = unarchiver.try_decode_object_of::<MBFile>("File").err()?;
+        assert!(matches!(
+            error,
+            Error::TypeMismatch { path, expected, .. } if path == "File" && expected.ends_with("MBFile")
+        ));
+        Some(CheckedFileBox)
+    }
+}